@@ -7,6 +7,11 @@
 // objects must be dynamically sized and cannot be stored directly on the stack.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // `tonic_build` is a helper library crate that integrates with Tonic, a gRPC client and server implementation in Rust.
-    tonic_build::compile_protos("src/proto/rustle_tree.proto")?;
+    // Besides the Rust code, the encoded file descriptor set of the proto is written to OUT_DIR. The server hands it to
+    // the reflection service, so tools like `grpcurl` can list and call the RPCs without a copy of the `.proto`.
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("rustle_tree_descriptor.bin"))
+        .compile(&["src/proto/rustle_tree.proto"], &["src/proto"])?;
     Ok(())
 }
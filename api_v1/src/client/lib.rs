@@ -1,35 +1,104 @@
 use dotenv::dotenv;
 use rustle_tree::{
-    merkle_tree_client::MerkleTreeClient, DownloadRequest, MerkleProofRequest, UploadRequest,
+    merkle_tree_client::MerkleTreeClient, BatchProofRequest, DownloadByHashRequest,
+    DownloadRequest, DownloadStreamRequest, DownloadWithProofRequest, GetTreeRequest,
+    LeafHashRequest, MerkleProofRequest, PingRequest, UploadRequest, WatchRootRequest,
 };
 
 use std::env;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
 use tonic::transport::Channel;
-use util::calc_sha256;
+use util::{bytes_to_hex, calc_sha256};
 
 // `rustle_tree` refers to the name of the Protobuf package defined in our `.proto` file.
 // The tonic crate provides the `include_proto` macro which will generate Rust code from the .proto definitions
 // and include it inside the `rustle_tree` module.
 pub mod rustle_tree {
     tonic::include_proto!("rustle_tree");
+
+    // Encoded descriptors of the proto, written by `build.rs`. The server registers them with the reflection service.
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("rustle_tree_descriptor");
 }
 
+// Wire form of a `merkle::TreeNode`, as sent in proofs. See `ToProofNode` for the conversions.
+pub use rustle_tree::TreeNode as ProofNode;
+
 #[derive(Debug)]
 pub struct UploadResponse {
     pub msg: String,
     pub root_hash: String,
+    // Id the server stored the tree under; pass it to later download and proof requests
+    pub tree_id: String,
 }
 
 #[derive(Debug)]
 pub struct DownloadResponse {
     pub msg: String,
     pub file: Vec<u8>,
+    pub file_name: Option<String>,
+    // Merkle proof for the file; empty unless requested through `download_with_proofs`
+    pub proofs: Vec<rustle_tree::TreeNode>,
+}
+
+#[derive(Debug)]
+pub struct DownloadWithProofResponse {
+    pub file: Vec<u8>,
+    pub proofs: Vec<rustle_tree::TreeNode>,
+    // Root and leaf count of the tree the file and proofs were read from
+    pub root_hash: String,
+    pub leaf_count: usize,
 }
 
 #[derive(Debug)]
 pub struct ProofResponse {
     pub msg: String,
     pub proofs: Vec<rustle_tree::TreeNode>,
+    // Root and leaf count of the tree the proofs belong to, needed to replay the proof on unbalanced trees
+    pub root_hash: String,
+    pub leaf_count: usize,
+}
+
+#[derive(Debug)]
+pub struct LeafHashResponse {
+    pub leaf_hash: String,
+    // Root of the tree the leaf hash was read from
+    pub root_hash: String,
+}
+
+#[derive(Debug)]
+pub struct PingResponse {
+    pub uptime: Duration,
+    // Whether the server holds at least one tree, i.e. is ready to serve downloads and proofs
+    pub tree_loaded: bool,
+    pub tree_count: usize,
+}
+
+#[derive(Debug)]
+pub struct BatchProofResponse {
+    pub msg: String,
+    // Every distinct proof node of the batch, sent once even when shared by several files
+    pub nodes: Vec<rustle_tree::TreeNode>,
+    pub entries: Vec<rustle_tree::BatchProofEntry>,
+}
+
+impl BatchProofResponse {
+    // Reconstructs the individual proof of `file_idx` from the shared node set, in the same shape `get_merkle_proof`
+    // returns, so it can be verified on its own. Returns None if the file is not part of the batch.
+    pub fn proof_for(&self, file_idx: i64) -> Option<Vec<rustle_tree::TreeNode>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.file_index == file_idx)?;
+        entry
+            .node_positions
+            .iter()
+            .map(|&pos| self.nodes.get(pos as usize).cloned())
+            .collect()
+    }
 }
 
 #[derive(Debug)]
@@ -47,7 +116,163 @@ pub struct VerifyResponse {
     pub is_verified: bool,
 }
 
-pub async fn setup_grpc_client() -> Result<MerkleTreeClient<Channel>, Box<dyn std::error::Error>> {
+// Deadline of every RPC made by the helpers below, unless `GRPC_TIMEOUT_SECS` is set
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+// ClientError is returned by every helper below, so callers can tell the kinds of failure apart without parsing
+// messages, e.g. to retry only when `is_transient` says a new attempt may succeed.
+#[derive(Debug)]
+pub enum ClientError {
+    // The server could not be reached while connecting
+    Connect(tonic::transport::Error),
+    // The RPC did not complete: the deadline passed (`RpcTimeoutError`) or the connection was lost (an `unavailable`
+    // or `deadline_exceeded` status). Retrying may succeed.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    // The server answered with an error status, e.g. `out_of_range` for a file index past the last file
+    Status(tonic::Status),
+    // The response arrived but is unusable: it could not be decoded or failed its integrity check
+    Decode(String),
+}
+
+impl ClientError {
+    // Whether the same request may succeed when sent again
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ClientError::Transport(_))
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connect(err) => write!(f, "failed to connect: {}", err),
+            ClientError::Transport(err) => write!(f, "{}", err),
+            ClientError::Status(status) => {
+                write!(
+                    f,
+                    "server returned {:?}: {}",
+                    status.code(),
+                    status.message()
+                )
+            }
+            ClientError::Decode(msg) => write!(f, "invalid response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Connect(err) => Some(err),
+            ClientError::Transport(err) => Some(err.as_ref()),
+            ClientError::Status(status) => Some(status),
+            ClientError::Decode(_) => None,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for ClientError {
+    fn from(err: tonic::transport::Error) -> Self {
+        ClientError::Connect(err)
+    }
+}
+
+// tonic reports a dropped connection or an expired deadline as a status, even though the server never handled the call
+impl From<tonic::Status> for ClientError {
+    fn from(status: tonic::Status) -> Self {
+        match status.code() {
+            tonic::Code::Unavailable | tonic::Code::DeadlineExceeded => {
+                ClientError::Transport(Box::new(status))
+            }
+            _ => ClientError::Status(status),
+        }
+    }
+}
+
+impl From<RpcTimeoutError> for ClientError {
+    fn from(err: RpcTimeoutError) -> Self {
+        ClientError::Transport(Box::new(err))
+    }
+}
+
+// Returned inside `ClientError::Transport` when the server does not answer within the deadline. Callers can tell it
+// apart from a lost connection with `downcast_ref::<RpcTimeoutError>()` on the inner error.
+#[derive(Debug)]
+pub struct RpcTimeoutError {
+    pub rpc: &'static str,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for RpcTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.rpc, self.timeout)
+    }
+}
+
+impl std::error::Error for RpcTimeoutError {}
+
+fn rpc_timeout() -> Duration {
+    env::var("GRPC_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RPC_TIMEOUT)
+}
+
+// Wraps `message` in a request carrying the deadline in its `grpc-timeout` header, so the server can give up too
+fn new_request<T>(message: T) -> tonic::Request<T> {
+    let mut request = authorized_request(message);
+    request.set_timeout(rpc_timeout());
+    request
+}
+
+// Wraps `message` in a request carrying the key from the `API_KEY` env var, if set, in its `authorization` header.
+// Servers started without `API_KEY` ignore the header.
+fn authorized_request<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    let header = env::var("API_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .and_then(|key| format!("Bearer {}", key).parse().ok());
+    if let Some(header) = header {
+        request.metadata_mut().insert("authorization", header);
+    }
+    request
+}
+
+// Awaits `call`, failing with `RpcTimeoutError` instead of hanging when the server does not answer in time
+async fn with_timeout<T>(
+    rpc: &'static str,
+    call: impl Future<Output = Result<T, tonic::Status>>,
+) -> Result<T, ClientError> {
+    deadline(rpc, rpc_timeout(), call).await
+}
+
+async fn deadline<T>(
+    rpc: &'static str,
+    timeout: Duration,
+    call: impl Future<Output = Result<T, tonic::Status>>,
+) -> Result<T, ClientError> {
+    match tokio::time::timeout(timeout, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(RpcTimeoutError { rpc, timeout }.into()),
+    }
+}
+
+// Connection attempts made by `setup_grpc_client`, starting with this delay between the first two attempts
+const DEFAULT_CONNECT_ATTEMPTS: u32 = 5;
+const DEFAULT_CONNECT_BASE_DELAY: Duration = Duration::from_millis(200);
+
+pub async fn setup_grpc_client() -> Result<MerkleTreeClient<Channel>, ClientError> {
+    setup_grpc_client_with_retry(DEFAULT_CONNECT_ATTEMPTS, DEFAULT_CONNECT_BASE_DELAY).await
+}
+
+// Connects to `SERVER_ADDRESS`, retrying up to `max_attempts` times when the server is not up yet (e.g. while a
+// container is still starting). The delay doubles after every failed attempt, starting at `base_delay`.
+// The error of the last attempt is returned once all attempts are exhausted.
+pub async fn setup_grpc_client_with_retry(
+    max_attempts: u32,
+    base_delay: Duration,
+) -> Result<MerkleTreeClient<Channel>, ClientError> {
     // .ok() suppresses any errors (e.g., if the file doesn't exist).
     dotenv().ok();
 
@@ -61,74 +286,537 @@ pub async fn setup_grpc_client() -> Result<MerkleTreeClient<Channel>, Box<dyn st
             format!("grpc://{}", grpc_server_addr)
         };
 
-    println!("gRPC client dialing on server address {}", grpc_server_addr);
+    // On stderr like the retry messages, so commands that print JSON keep stdout clean
+    eprintln!("gRPC client dialing on server address {}", grpc_server_addr);
 
-    let client = MerkleTreeClient::connect(grpc_server_addr).await?;
-    Ok(client)
+    connect_with_retry(grpc_server_addr, max_attempts, base_delay).await
 }
 
+async fn connect_with_retry(
+    grpc_server_addr: String,
+    max_attempts: u32,
+    base_delay: Duration,
+) -> Result<MerkleTreeClient<Channel>, ClientError> {
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    loop {
+        match MerkleTreeClient::connect(grpc_server_addr.clone()).await {
+            Ok(client) => return Ok(client),
+            Err(e) if attempt >= max_attempts => return Err(e.into()),
+            Err(e) => {
+                eprintln!(
+                    "Connection attempt {}/{} failed ({}), retrying in {:?}",
+                    attempt, max_attempts, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// Uploads the files as the tree `tree_id`, replacing any tree already stored under that id.
+// An empty `tree_id` lets the server generate one, which is returned in the response.
 pub async fn upload(
     client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    files: Vec<Vec<u8>>,
+) -> Result<UploadResponse, ClientError> {
+    send_upload(client, tree_id, files, Vec::new()).await
+}
+
+// Uploads `(file_name, content)` pairs (as returned by `util::read_files_with_names`) so that the server
+// can hand back the original file name on download.
+pub async fn upload_with_names(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    files: Vec<(String, Vec<u8>)>,
+) -> Result<UploadResponse, ClientError> {
+    // `unzip` splits the vector of pairs into two vectors that keep the same ordering
+    let (file_names, files): (Vec<String>, Vec<Vec<u8>>) = files.into_iter().unzip();
+    send_upload(client, tree_id, files, file_names).await
+}
+
+async fn send_upload(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
     files: Vec<Vec<u8>>,
-) -> Result<UploadResponse, Box<dyn std::error::Error>> {
-    let request = tonic::Request::new(UploadRequest { files });
+    file_names: Vec<String>,
+) -> Result<UploadResponse, ClientError> {
+    let request = new_request(UploadRequest {
+        files,
+        file_names,
+        tree_id: tree_id.to_string(),
+    });
 
     // Sends the upload request to the gRPC server. The await keyword ensures that the function suspends and waits for the server's response.
     // In this case since  there are no other asynchronous tasks running concurrently, nothing else happens while waiting for the response.
     // If the server returns an error, the ? operator will propagate the error. `into_inner()`: Extracts the actual response (stripping
     // away the gRPC envelope metadata).
-    let response = client.upload(request).await?.into_inner();
+    let response = with_timeout("upload", client.upload(request))
+        .await?
+        .into_inner();
 
     let res = UploadResponse {
         msg: "All files uploaded successfully".to_string(),
-        root_hash: String::from_utf8(response.merkle_root_hash).unwrap(),
+        root_hash: root_hash_from_bytes(response.merkle_root_hash),
+        tree_id: response.tree_id,
     };
 
     println!("Storing the merkle tree root hash on client's disk");
     Ok(res)
 }
 
+// Number of attempts made by `download` before giving up on a file whose bytes keep failing the integrity check
+pub const DEFAULT_DOWNLOAD_ATTEMPTS: u32 = 3;
+
 pub async fn download(
     client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
     file_idx: i64,
-) -> Result<DownloadResponse, Box<dyn std::error::Error>> {
-    let request = tonic::Request::new(DownloadRequest {
-        file_index: file_idx,
+) -> Result<DownloadResponse, ClientError> {
+    download_with_retries(client, tree_id, file_idx, DEFAULT_DOWNLOAD_ATTEMPTS).await
+}
+
+// Downloads the file together with its Merkle proof in a single round-trip, for the common "fetch and verify" pattern
+pub async fn download_with_proofs(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+) -> Result<DownloadResponse, ClientError> {
+    request_download(client, tree_id, file_idx, DEFAULT_DOWNLOAD_ATTEMPTS, true).await
+}
+
+// Downloads the file with its proof and returns the bytes only if they are included under `root_hash`, a root the
+// caller already trusts (e.g. the one returned by `upload`). `file_hash` alone is advertised by the same server that
+// sends the bytes, so a malicious server could change both; the proof ties the bytes to the trusted root instead.
+pub async fn download_and_verify(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+    root_hash: &str,
+) -> Result<Vec<u8>, ClientError> {
+    let response = download_with_proofs(client, tree_id, file_idx).await?;
+
+    // The server rejects negative indices, so a successful download always has a valid one
+    let file_hash = calc_sha256(&response.file);
+    let is_verified = Verifier::new(root_hash.to_string())
+        .verify_one(file_idx as usize, &file_hash, &response.proofs)
+        .map_err(|e| ClientError::Decode(format!("invalid proof for file{}: {}", file_idx, e)))?;
+
+    if !is_verified {
+        return Err(ClientError::Decode(format!(
+            "file{} is not included under root {}",
+            file_idx, root_hash
+        )));
+    }
+    Ok(response.file)
+}
+
+// Downloads the file and checks that the received bytes hash to the `file_hash` advertised by the server.
+// On a mismatch (e.g. corruption over a flaky link) the file is requested again, up to `max_attempts` times in total.
+// Servers that do not advertise a hash (empty `file_hash`) are trusted as-is.
+pub async fn download_with_retries(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+    max_attempts: u32,
+) -> Result<DownloadResponse, ClientError> {
+    request_download(client, tree_id, file_idx, max_attempts, false).await
+}
+
+async fn request_download(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+    max_attempts: u32,
+    with_proof: bool,
+) -> Result<DownloadResponse, ClientError> {
+    // `max(1)` guarantees at least one request is made even if zero attempts are asked for
+    for attempt in 1..=max_attempts.max(1) {
+        let request = new_request(DownloadRequest {
+            file_index: file_idx,
+            with_proof,
+            tree_id: tree_id.to_string(),
+        });
+
+        let response = with_timeout("download", client.download(request))
+            .await?
+            .into_inner();
+
+        if !response.file_hash.is_empty()
+            && calc_sha256(&response.file_content) != response.file_hash
+        {
+            eprintln!(
+                "file{} failed the integrity check on attempt {}/{}",
+                file_idx, attempt, max_attempts
+            );
+            continue;
+        }
+
+        // format! automatically converts variables (like integers) to strings rather than manual conversion and returns the string for further use
+        let msg = format!("file{} downloaded successfully", file_idx);
+
+        return Ok(DownloadResponse {
+            msg,
+            file: response.file_content,
+            file_name: response.file_name,
+            proofs: response.proofs,
+        });
+    }
+
+    Err(ClientError::Decode(format!(
+        "file{} failed the integrity check after {} attempts",
+        file_idx, max_attempts
+    )))
+}
+
+// Downloads a file by its leaf hash instead of its position, for clients that kept the hash rather than the index.
+// The hash is checked against the received bytes, so a corrupted transfer is reported as an error.
+pub async fn download_by_hash(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    leaf_hash: &str,
+) -> Result<DownloadResponse, ClientError> {
+    let request = new_request(DownloadByHashRequest {
+        leaf_hash: leaf_hash.to_string(),
+        tree_id: tree_id.to_string(),
     });
 
-    let response = client.download(request).await?.into_inner();
+    let response = with_timeout("download_by_hash", client.download_by_hash(request))
+        .await?
+        .into_inner();
 
-    // format! automatically converts variables (like integers) to strings rather than manual conversion and returns the string for further use
-    let msg = format!("file{} downloaded successfully", file_idx);
+    if calc_sha256(&response.file_content) != leaf_hash {
+        return Err(ClientError::Decode(format!(
+            "file with hash {} failed the integrity check",
+            leaf_hash
+        )));
+    }
 
     Ok(DownloadResponse {
-        msg,
+        msg: format!("file with hash {} downloaded successfully", leaf_hash),
         file: response.file_content,
+        file_name: response.file_name,
+        proofs: response.proofs,
     })
 }
 
+// Downloads a file over the `DownloadStream` RPC, which splits it into frames so it may exceed the gRPC message size
+// limit. The frames are reassembled in order and checked against the hash sent with the first frame.
+pub async fn download_stream(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+) -> Result<Vec<u8>, ClientError> {
+    let request = new_request(DownloadStreamRequest {
+        file_index: file_idx,
+        tree_id: tree_id.to_string(),
+    });
+
+    let mut stream = with_timeout("download_stream", client.download_stream(request))
+        .await?
+        .into_inner();
+
+    let mut file = Vec::new();
+    let mut file_hash = String::new();
+    // `message` yields None once the server has sent the last frame. Every frame must arrive within the timeout,
+    // so a server that stalls mid-stream is detected too.
+    while let Some(chunk) = with_timeout("download_stream", stream.message()).await? {
+        if file_hash.is_empty() {
+            file_hash = chunk.file_hash;
+        }
+        file.extend_from_slice(&chunk.data);
+    }
+
+    if calc_sha256(&file) != file_hash {
+        return Err(ClientError::Decode(format!(
+            "file{} failed the integrity check",
+            file_idx
+        )));
+    }
+
+    Ok(file)
+}
+
 pub async fn get_merkle_proof(
     client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
     file_idx: i64,
-) -> Result<ProofResponse, Box<dyn std::error::Error>> {
-    let request = tonic::Request::new(MerkleProofRequest {
+) -> Result<ProofResponse, ClientError> {
+    let request = new_request(MerkleProofRequest {
         file_index: file_idx,
+        tree_id: tree_id.to_string(),
+        raw_hashes: true,
     });
 
-    let response = client.get_merkle_proof(request).await?.into_inner();
+    let response = with_timeout("get_merkle_proof", client.get_merkle_proof(request))
+        .await?
+        .into_inner();
 
     let msg = format!("merkle proofs for file{} generated successfully", file_idx);
 
+    // Raw digests are turned back into hex, so callers see the same proofs whichever format the server sent
+    let mut proofs = response.proofs;
+    proofs.iter_mut().for_each(expand_hash);
+
     Ok(ProofResponse {
         msg,
+        proofs,
+        root_hash: response.root_hash,
+        leaf_count: response.leaf_count as usize,
+    })
+}
+
+// Fetches a file with its proof, root hash and leaf count over the `DownloadWithProof` RPC. Unlike
+// `download_with_proofs`, the root and leaf count come along, so the proof can be replayed from this one response.
+// The server reads all of them under one lock, so they always describe the same tree.
+pub async fn download_with_proof(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+) -> Result<DownloadWithProofResponse, ClientError> {
+    let request = new_request(DownloadWithProofRequest {
+        file_index: file_idx,
+        tree_id: tree_id.to_string(),
+    });
+
+    let response = with_timeout("download_with_proof", client.download_with_proof(request))
+        .await?
+        .into_inner();
+
+    Ok(DownloadWithProofResponse {
+        file: response.file_content,
         proofs: response.proofs,
+        root_hash: response.root_hash,
+        leaf_count: response.leaf_count as usize,
+    })
+}
+
+// Fetches the leaf hash the server stores for a file, e.g. to compare it with `calc_sha256` of a local copy and detect
+// tampering before downloading the file or its proof
+pub async fn get_leaf_hash(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_idx: i64,
+) -> Result<LeafHashResponse, ClientError> {
+    let request = new_request(LeafHashRequest {
+        file_index: file_idx,
+        tree_id: tree_id.to_string(),
+    });
+
+    let response = with_timeout("get_leaf_hash", client.get_leaf_hash(request))
+        .await?
+        .into_inner();
+
+    Ok(LeafHashResponse {
+        leaf_hash: response.leaf_hash,
+        root_hash: response.root_hash,
+    })
+}
+
+// Fetches the whole Merkle tree, so that many files can be verified offline without a proof request per file
+pub async fn get_tree(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+) -> Result<merkle::MerkleTree, ClientError> {
+    let request = new_request(GetTreeRequest {
+        tree_id: tree_id.to_string(),
+    });
+
+    let response = with_timeout("get_tree", client.get_tree(request))
+        .await?
+        .into_inner();
+
+    // The tree arrives in the versioned envelope written by `write_json`
+    let json =
+        std::str::from_utf8(&response.tree_json).map_err(|e| ClientError::Decode(e.to_string()))?;
+    merkle::MerkleTree::from_json(json).map_err(|e| ClientError::Decode(e.to_string()))
+}
+
+// Subscribes to the root hash of `tree_id`. The returned stream yields the current root first, then the new root after
+// every upload to the tree, for as long as the server runs. Only opening the subscription is bounded by the RPC timeout:
+// the request carries no gRPC deadline, since that would close a subscription meant to stay open.
+// Stream items are `ClientError`s too, whose `Status` variant holds the `tonic::Status` unboxed.
+#[allow(clippy::result_large_err)]
+pub async fn watch_root(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+) -> Result<impl Stream<Item = Result<String, ClientError>>, ClientError> {
+    let request = authorized_request(WatchRootRequest {
+        tree_id: tree_id.to_string(),
+    });
+
+    let updates = with_timeout("watch_root", client.watch_root(request))
+        .await?
+        .into_inner();
+
+    Ok(updates.map(|update| Ok(update?.root_hash)))
+}
+
+// Checks that the server is up, and whether it is ready to serve proofs, e.g. for a readiness probe
+pub async fn ping(client: &mut MerkleTreeClient<Channel>) -> Result<PingResponse, ClientError> {
+    let response = with_timeout("ping", client.ping(new_request(PingRequest {})))
+        .await?
+        .into_inner();
+
+    Ok(PingResponse {
+        uptime: Duration::from_secs(response.uptime_secs),
+        tree_loaded: response.tree_loaded,
+        tree_count: response.tree_count as usize,
+    })
+}
+
+// Fetches the proofs of several files of the same tree in one request. Proof nodes shared between the files (e.g. the
+// sibling of a common ancestor) are transferred only once; use `BatchProofResponse::proof_for` to get each file's proof.
+pub async fn get_batch_proof(
+    client: &mut MerkleTreeClient<Channel>,
+    tree_id: &str,
+    file_indices: &[i64],
+) -> Result<BatchProofResponse, ClientError> {
+    let request = new_request(BatchProofRequest {
+        file_indices: file_indices.to_vec(),
+        tree_id: tree_id.to_string(),
+    });
+
+    let response = with_timeout("get_batch_proof", client.get_batch_proof(request))
+        .await?
+        .into_inner();
+
+    let msg = format!(
+        "batch merkle proof for {} files generated successfully",
+        response.entries.len()
+    );
+
+    Ok(BatchProofResponse {
+        msg,
+        nodes: response.nodes,
+        entries: response.entries,
     })
 }
 
 //  The lifetime 'a is used to indicate that the function can borrow data for the duration of the request.
+// Verifier checks proofs against a root hash it is constructed with once, so verifying many files costs one proof
+// fold (O(log n)) per file instead of rebuilding the whole tree for every call.
+#[derive(Debug)]
+pub struct Verifier {
+    root_hash: String,
+    // When the tree is known locally, its shape is used instead of the one implied by the proof
+    merkle_tree: Option<merkle::MerkleTree>,
+}
+
+impl Verifier {
+    pub fn new(root_hash: String) -> Verifier {
+        Verifier {
+            root_hash,
+            merkle_tree: None,
+        }
+    }
+
+    // Uses a prebuilt tree (e.g. fetched with `get_tree`). Proofs are then also checked against the tree's root.
+    pub fn with_tree(root_hash: String, merkle_tree: merkle::MerkleTree) -> Verifier {
+        Verifier {
+            root_hash,
+            merkle_tree: Some(merkle_tree),
+        }
+    }
+
+    // Checks that the file with hash `file_hash` sits at `file_idx` under the verifier's root, using the proof nodes
+    // returned by `get_merkle_proof` or `download_with_proofs`
+    pub fn verify_one(
+        &self,
+        file_idx: usize,
+        file_hash: &str,
+        proof: &[rustle_tree::TreeNode],
+    ) -> Result<bool, merkle::MerkleTreeError> {
+        let nodes: Vec<merkle::TreeNode> = proof.iter().map(ProofNode::to_tree_node).collect();
+        let node_refs: Vec<&merkle::TreeNode> = nodes.iter().collect();
+
+        match &self.merkle_tree {
+            Some(merkle_tree) => {
+                merkle_tree.verify_merkle_proof(&self.root_hash, file_hash, file_idx, &node_refs)
+            }
+            None => {
+                // The proof nodes' index ranges give the sibling sides and the leaf count
+                let proof_file =
+                    merkle::ProofFile::from_nodes(self.root_hash.clone(), file_idx, &node_refs)?;
+                merkle::verify(
+                    &self.root_hash,
+                    file_hash,
+                    file_idx,
+                    proof_file.leaf_count,
+                    &proof_file.steps,
+                )
+            }
+        }
+    }
+}
+
+// The upload response carries the root as bytes. Servers send the hex string as UTF-8 today, but raw digest bytes are
+// accepted as well and hex encoded, so the root is always returned as hex instead of panicking on invalid UTF-8.
+fn root_hash_from_bytes(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(hex) if hex.bytes().all(|byte| byte.is_ascii_hexdigit()) => hex,
+        Ok(other) => bytes_to_hex(other.as_bytes()),
+        Err(err) => bytes_to_hex(err.as_bytes()),
+    }
+}
+
+// Hex hash of `node`, preferring the raw digest bytes when the server sent them
+fn node_hash(node: &rustle_tree::TreeNode) -> String {
+    if node.hash_bytes.is_empty() {
+        node.hash.clone()
+    } else {
+        bytes_to_hex(&node.hash_bytes)
+    }
+}
+
+// Replaces raw digest bytes of `node` by the equivalent hex `hash`
+fn expand_hash(node: &mut rustle_tree::TreeNode) {
+    if !node.hash_bytes.is_empty() {
+        node.hash = node_hash(node);
+        node.hash_bytes.clear();
+    }
+}
+
+// The one conversion between `merkle::TreeNode` and its wire form, shared by the server, the client and the CLI.
+// `to_proof_node` lives on a trait because `TreeNode` belongs to the `merkle` crate.
+pub trait ToProofNode {
+    fn to_proof_node(&self) -> ProofNode;
+}
+
+impl ToProofNode for merkle::TreeNode {
+    // Proof nodes carry only their hash and index range; verifiers never need the children
+    fn to_proof_node(&self) -> ProofNode {
+        ProofNode {
+            hash: self.hash.clone(),
+            left_idx: self.left_idx as i64,
+            right_idx: self.right_idx as i64,
+            left: None,
+            right: None,
+            hash_bytes: Vec::new(),
+        }
+    }
+}
+
+impl ProofNode {
+    // Reverse of `to_proof_node`. Children are converted too, should a node carry any, and raw digest bytes are
+    // preferred over the hex hash.
+    pub fn to_tree_node(&self) -> merkle::TreeNode {
+        merkle::TreeNode {
+            hash: node_hash(self),
+            left_idx: self.left_idx as usize,
+            right_idx: self.right_idx as usize,
+            left: self.left.as_deref().map(|l| Box::new(l.to_tree_node())),
+            right: self.right.as_deref().map(|r| Box::new(r.to_tree_node())),
+        }
+    }
+}
+
 pub async fn verify_merkle_proofs<'a>(
     request: VerifyRequest<'a>,
-) -> Result<VerifyResponse, Box<dyn std::error::Error>> {
+) -> Result<VerifyResponse, ClientError> {
     // Extract necessary fields from the request
     let VerifyRequest {
         files,
@@ -139,46 +827,10 @@ pub async fn verify_merkle_proofs<'a>(
     } = request;
 
     // Calculate the hash of the specified file
-    let file_hash = calc_sha256(&files[file_idx as usize]);
-
-    // Convert proofs from Vec<rustle_tree::TreeNode> to Vec<merkle::TreeNode>
-    let proof_refs: Vec<merkle::TreeNode> = proofs
-        .iter()
-        .map(|proof| merkle::TreeNode {
-            hash: proof.hash.clone(),
-            left_idx: proof.left_idx as usize,
-            right_idx: proof.right_idx as usize,
-            left: proof.left.as_ref().map(|l| {
-                Box::new(merkle::TreeNode {
-                    hash: l.hash.clone(),
-                    left_idx: l.left_idx as usize,
-                    right_idx: l.right_idx as usize,
-                    left: None,
-                    right: None,
-                })
-            }),
-            right: proof.right.as_ref().map(|r| {
-                Box::new(merkle::TreeNode {
-                    hash: r.hash.clone(),
-                    left_idx: r.left_idx as usize,
-                    right_idx: r.right_idx as usize,
-                    left: None,
-                    right: None,
-                })
-            }),
-        })
-        .collect();
+    let file_hash = calc_sha256(&files[file_idx]);
 
-    // Create an instance of the Merkle tree (you may need to adjust this based on your implementation)
-    let merkle_tree = merkle::MerkleTree::new(files)?;
-
-    // Verify the Merkle proof
-    let verification_result = merkle_tree.verify_merkle_proof(
-        &root_hash,
-        &file_hash,
-        file_idx,
-        &proof_refs.iter().collect::<Vec<&merkle::TreeNode>>(),
-    );
+    // Only the root hash is needed to check the proof, so no tree is built from `files`
+    let verification_result = Verifier::new(root_hash).verify_one(file_idx, &file_hash, &proofs);
 
     let is_verified = match verification_result {
         Ok(result) => result,
@@ -199,3 +851,316 @@ pub async fn verify_merkle_proofs<'a>(
 
     Ok(VerifyResponse { msg, is_verified })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustle_tree as api;
+    use rustle_tree::merkle_tree_server::{MerkleTree as MerkleTreeTrait, MerkleTreeServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{transport::Server, Request, Response, Status};
+
+    const FILE: &[u8] = b"hello merkle";
+
+    // Mock server whose first `download` response carries corrupted bytes while advertising the correct hash
+    #[derive(Default)]
+    struct FlakyServer {
+        downloads: Arc<AtomicUsize>,
+    }
+
+    #[tonic::async_trait]
+    impl MerkleTreeTrait for FlakyServer {
+        async fn upload(
+            &self,
+            _request: Request<api::UploadRequest>,
+        ) -> Result<Response<api::UploadResponse>, Status> {
+            Err(Status::unimplemented("upload"))
+        }
+
+        async fn download(
+            &self,
+            _request: Request<api::DownloadRequest>,
+        ) -> Result<Response<api::DownloadResponse>, Status> {
+            let call = self.downloads.fetch_add(1, Ordering::SeqCst);
+            let file_content = if call == 0 {
+                b"hello merkle!".to_vec()
+            } else {
+                FILE.to_vec()
+            };
+
+            Ok(Response::new(api::DownloadResponse {
+                file_content,
+                file_name: None,
+                file_hash: calc_sha256(FILE),
+                proofs: Vec::new(),
+            }))
+        }
+
+        async fn download_by_hash(
+            &self,
+            _request: Request<api::DownloadByHashRequest>,
+        ) -> Result<Response<api::DownloadResponse>, Status> {
+            Err(Status::unimplemented("download_by_hash"))
+        }
+
+        type DownloadStreamStream = tokio_stream::Empty<Result<api::DownloadChunk, Status>>;
+
+        async fn download_stream(
+            &self,
+            _request: Request<api::DownloadStreamRequest>,
+        ) -> Result<Response<Self::DownloadStreamStream>, Status> {
+            Err(Status::unimplemented("download_stream"))
+        }
+
+        async fn get_merkle_proof(
+            &self,
+            _request: Request<api::MerkleProofRequest>,
+        ) -> Result<Response<api::MerkleProofResponse>, Status> {
+            Err(Status::unimplemented("get_merkle_proof"))
+        }
+
+        async fn download_with_proof(
+            &self,
+            _request: Request<api::DownloadWithProofRequest>,
+        ) -> Result<Response<api::DownloadWithProofResponse>, Status> {
+            Err(Status::unimplemented("download_with_proof"))
+        }
+
+        async fn get_leaf_hash(
+            &self,
+            _request: Request<api::LeafHashRequest>,
+        ) -> Result<Response<api::LeafHashResponse>, Status> {
+            Err(Status::unimplemented("get_leaf_hash"))
+        }
+
+        async fn get_tree(
+            &self,
+            _request: Request<api::GetTreeRequest>,
+        ) -> Result<Response<api::GetTreeResponse>, Status> {
+            Err(Status::unimplemented("get_tree"))
+        }
+
+        type WatchRootStream = tokio_stream::Empty<Result<api::RootUpdate, Status>>;
+
+        async fn watch_root(
+            &self,
+            _request: Request<api::WatchRootRequest>,
+        ) -> Result<Response<Self::WatchRootStream>, Status> {
+            Err(Status::unimplemented("watch_root"))
+        }
+
+        async fn ping(
+            &self,
+            _request: Request<api::PingRequest>,
+        ) -> Result<Response<api::PingResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_batch_proof(
+            &self,
+            _request: Request<api::BatchProofRequest>,
+        ) -> Result<Response<api::BatchProofResponse>, Status> {
+            Err(Status::unimplemented("get_batch_proof"))
+        }
+
+        async fn begin_upload(
+            &self,
+            _request: Request<api::BeginUploadRequest>,
+        ) -> Result<Response<api::BeginUploadResponse>, Status> {
+            Err(Status::unimplemented("begin_upload"))
+        }
+
+        async fn upload_chunk(
+            &self,
+            _request: Request<api::UploadChunkRequest>,
+        ) -> Result<Response<api::UploadChunkResponse>, Status> {
+            Err(Status::unimplemented("upload_chunk"))
+        }
+
+        async fn get_upload_status(
+            &self,
+            _request: Request<api::UploadStatusRequest>,
+        ) -> Result<Response<api::UploadStatusResponse>, Status> {
+            Err(Status::unimplemented("get_upload_status"))
+        }
+
+        async fn finish_upload(
+            &self,
+            _request: Request<api::FinishUploadRequest>,
+        ) -> Result<Response<api::UploadResponse>, Status> {
+            Err(Status::unimplemented("finish_upload"))
+        }
+    }
+
+    // Serves `service` on an ephemeral local port and returns a client connected to it
+    async fn spawn_server<S: MerkleTreeTrait>(service: S) -> MerkleTreeClient<Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            Server::builder()
+                .add_service(MerkleTreeServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+
+        MerkleTreeClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn connect_gives_up_after_max_attempts() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("grpc://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let started = std::time::Instant::now();
+        let result = connect_with_retry(addr, 3, Duration::from_millis(20)).await;
+        assert!(matches!(result, Err(ClientError::Connect(_))));
+        // Two waits between three attempts: 20ms + 40ms
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn verifier_checks_every_file_against_one_root() {
+        let files: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i]).collect();
+        let merkle_tree = merkle::MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        let verifier = Verifier::new(root_hash.clone());
+        let tree_verifier = Verifier::with_tree(root_hash, merkle_tree.clone());
+
+        for (file_idx, file) in files.iter().enumerate() {
+            let proof: Vec<api::TreeNode> = merkle_tree
+                .generate_merkle_proof(file_idx)
+                .unwrap()
+                .into_iter()
+                .map(|node| node.to_proof_node())
+                .collect();
+
+            let file_hash = calc_sha256(file);
+            assert!(verifier.verify_one(file_idx, &file_hash, &proof).unwrap());
+            assert!(tree_verifier
+                .verify_one(file_idx, &file_hash, &proof)
+                .unwrap());
+            assert!(!verifier
+                .verify_one(file_idx, &calc_sha256(b"tampered"), &proof)
+                .unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn hanging_call_times_out() {
+        let err = deadline(
+            "get_tree",
+            Duration::from_millis(10),
+            std::future::pending::<Result<(), Status>>(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.is_transient());
+        let ClientError::Transport(inner) = &err else {
+            panic!("expected a transport error, got {:?}", err);
+        };
+        let timeout = inner.downcast_ref::<RpcTimeoutError>().unwrap();
+        assert_eq!(timeout.rpc, "get_tree");
+        assert_eq!(err.to_string(), "get_tree timed out after 10ms");
+    }
+
+    #[tokio::test]
+    async fn download_retries_on_corrupted_bytes() {
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let mut client = spawn_server(FlakyServer {
+            downloads: downloads.clone(),
+        })
+        .await;
+
+        let response = download(&mut client, "default", 0).await.unwrap();
+        assert_eq!(response.file, FILE);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // A single attempt cannot recover from the corrupted first response
+        downloads.store(0, Ordering::SeqCst);
+        let err = download_with_retries(&mut client, "default", 0, 1)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("integrity check"));
+    }
+
+    #[test]
+    fn proof_node_conversion_round_trips() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let merkle_tree = merkle::MerkleTree::new(&files).unwrap();
+
+        for node in merkle_tree.generate_merkle_proof(3).unwrap() {
+            let mut proof_node = node.to_proof_node();
+            assert_eq!(proof_node.to_tree_node(), node);
+
+            // Raw digest bytes take precedence over the hex hash
+            proof_node.hash_bytes = util::hex_to_bytes(&node.hash).unwrap();
+            proof_node.hash.clear();
+            assert_eq!(proof_node.to_tree_node().hash, node.hash);
+        }
+    }
+
+    #[test]
+    fn root_hash_accepts_hex_and_raw_bytes() {
+        let hex = calc_sha256(FILE);
+        assert_eq!(root_hash_from_bytes(hex.clone().into_bytes()), hex);
+
+        // Raw digest bytes, which are not valid UTF-8 here, are hex encoded instead of panicking
+        let raw = util::hex_to_bytes(&hex).unwrap();
+        assert!(String::from_utf8(raw.clone()).is_err());
+        assert_eq!(root_hash_from_bytes(raw), hex);
+
+        // Valid UTF-8 that is not hex is treated as raw bytes too
+        assert_eq!(root_hash_from_bytes(b"AZ".to_vec()), "415a");
+    }
+
+    #[tokio::test]
+    async fn errors_are_classified() {
+        let mut client = spawn_server(FlakyServer::default()).await;
+
+        // An error status from the server is not worth retrying
+        let err = get_merkle_proof(&mut client, "default", 0)
+            .await
+            .unwrap_err();
+        assert!(!err.is_transient());
+        assert!(
+            matches!(&err, ClientError::Status(status) if status.code() == tonic::Code::Unimplemented)
+        );
+
+        // A dropped connection is
+        let err = ClientError::from(Status::unavailable("connection reset"));
+        assert!(err.is_transient());
+
+        let err = download_with_retries(&mut client, "default", 0, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn download_and_verify_checks_the_trusted_root() {
+        let mut client = spawn_server(FlakyServer::default()).await;
+
+        // The mock serves a single-file tree, whose root is the file hash and whose proof is empty
+        let file = download_and_verify(&mut client, "default", 0, &calc_sha256(FILE))
+            .await
+            .unwrap();
+        assert_eq!(file, FILE);
+
+        // Bytes that match the server's own `file_hash` are still rejected under another root
+        let err = download_and_verify(&mut client, "default", 0, &calc_sha256(b"other"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Decode(_)));
+    }
+}
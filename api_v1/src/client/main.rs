@@ -1,5 +1,6 @@
 use grpc_client::{
-    download, get_merkle_proof, setup_grpc_client, upload, verify_merkle_proofs, VerifyRequest,
+    download, download_and_verify, get_merkle_proof, setup_grpc_client, upload,
+    verify_merkle_proofs, VerifyRequest,
 };
 
 #[tokio::main]
@@ -14,17 +15,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         vec![10, 11, 12],
     ];
 
-    // Pass the client as mutable reference
-    let upload_response = upload(&mut client, files).await?;
+    // Pass the client as mutable reference. An empty tree id lets the server generate one.
+    let upload_response = upload(&mut client, "", files).await?;
     println!("Upload response: {:?}", upload_response);
+    let tree_id = upload_response.tree_id.clone();
 
     // Pass the client as mutable reference
-    let download_response = download(&mut client, 3).await?;
+    let download_response = download(&mut client, &tree_id, 3).await?;
     println!("Download response: {:?}", download_response);
 
+    // Download again, this time only accepting bytes that the proof ties to the root returned by the upload
+    let verified_file =
+        download_and_verify(&mut client, &tree_id, 3, &upload_response.root_hash).await?;
+    println!("Verified download: {:?}", verified_file);
+
     // Pass the client as mutable reference
-    let proof_response_1 = get_merkle_proof(&mut client, 3).await?;
-    println!("Proof response: {:?}", proof_response_1);
+    let proof_response_1 = get_merkle_proof(&mut client, &tree_id, 3).await?;
+    // One line per sibling instead of the raw response, whose digest bytes are unreadable
+    println!("Proof response:");
+    for node in &proof_response_1.proofs {
+        println!("  {}", node.to_tree_node().summary());
+    }
 
     // Independently verify the client proof - positive case
     let files_1 = vec![
@@ -45,7 +56,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Independently verify the client proof - negative case
     let files_2 = vec![vec![1, 2, 4], vec![4, 5, 6]];
-    let proof_response_2 = get_merkle_proof(&mut client, 0).await?;
+    let proof_response_2 = get_merkle_proof(&mut client, &tree_id, 0).await?;
     let verify_request_2 = VerifyRequest {
         files: &files_2,
 
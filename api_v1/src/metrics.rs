@@ -0,0 +1,126 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+// Request counts and handler latencies of the gRPC service, labelled by RPC name. The metrics live in their own
+// registry rather than the process-wide default one, so every `MerkleTreeService` (e.g. one per test) starts at zero.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+    proof_cache: IntCounterVec,
+}
+
+// `Registry` does not implement Debug, and listing every time series would not be useful anyway
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "rustle_tree_requests_total",
+                "Number of gRPC requests handled",
+            ),
+            &["rpc"],
+        )
+        .expect("valid counter options");
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "rustle_tree_request_duration_seconds",
+                "Time spent in the gRPC handlers",
+            ),
+            &["rpc"],
+        )
+        .expect("valid histogram options");
+        let proof_cache = IntCounterVec::new(
+            Opts::new(
+                "rustle_tree_proof_cache_total",
+                "Proof lookups in the per-tree proof cache",
+            ),
+            &["result"],
+        )
+        .expect("valid counter options");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests.clone()))
+            .expect("counter registered once");
+        registry
+            .register(Box::new(duration.clone()))
+            .expect("histogram registered once");
+        registry
+            .register(Box::new(proof_cache.clone()))
+            .expect("counter registered once");
+
+        Metrics {
+            registry,
+            requests,
+            duration,
+            proof_cache,
+        }
+    }
+}
+
+impl Metrics {
+    // Counts a request to `rpc` and starts timing it. The duration is recorded when the returned timer is dropped, so
+    // a handler only has to hold it until it returns, whichever path it returns on.
+    pub fn track(&self, rpc: &str) -> HistogramTimer {
+        self.requests.with_label_values(&[rpc]).inc();
+        self.duration.with_label_values(&[rpc]).start_timer()
+    }
+
+    // Counts a lookup in a proof cache as a `hit` or a `miss`
+    pub fn proof_cache(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.proof_cache.with_label_values(&[result]).inc();
+    }
+
+    // All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics are always encodable");
+        String::from_utf8(buffer).expect("the text format is UTF-8")
+    }
+}
+
+// Answers every HTTP request on `addr` with the current metrics. Prometheus only ever issues `GET /metrics`, so the
+// request itself is read and ignored instead of pulling in an HTTP server framework.
+pub async fn serve(addr: SocketAddr, metrics: Metrics) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("metrics available on http://{}/metrics", addr);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let body = metrics.render();
+
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            if let Err(e) = stream.read(&mut request).await {
+                warn!("failed to read metrics request: {}", e);
+                return;
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                TextEncoder::new().format_type(),
+                body.len(),
+                body
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                warn!("failed to write metrics response: {}", e);
+            }
+        });
+    }
+}
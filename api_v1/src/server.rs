@@ -1,16 +1,31 @@
 use dotenv::dotenv;
-use merkle::MerkleTree;
+use merkle::{MerkleErrorKind, MerkleTree, MerkleTreeError};
+use metrics::Metrics;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
-use tonic::{transport::Server, Request, Response, Status};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{service::Interceptor, transport::Server, Request, Response, Status};
+use tracing::{info, instrument, warn};
+use tracing_subscriber::EnvFilter;
+use util::{calc_sha256, hex_to_bytes, write_file};
 
-// `rustle_tree` refers to the name of the Protobuf package defined in our `.proto` file.
-// The tonic crate provides the `include_proto` macro which will generate Rust code from the .proto definitions
-// and include it inside the `rustle_tree` module.
-pub mod rustle_tree {
-    tonic::include_proto!("rustle_tree");
-}
+mod metrics;
+
+// `rustle_tree` refers to the name of the Protobuf package defined in our `.proto` file. The generated code is taken
+// from the client library rather than generated a second time, so the server and the client share one set of message
+// types and the conversions between `merkle::TreeNode` and the wire `TreeNode` (`ToProofNode`) are written only once.
+use grpc_client::{rustle_tree, ToProofNode};
 
 // The `MerkleTree` here refers to the trait generated from the service definition in your .proto file. It corresponds to the service `MerkleTree`  defined
 // in the proto file. It is renamed as MerkleTreeTrait using as to avoid name conflicts with other items (e.g., a struct or another implementation named MerkleTree).
@@ -18,190 +33,2269 @@ pub mod rustle_tree {
 // logic to handle requests from clients.
 use rustle_tree::{
     merkle_tree_server::{MerkleTree as MerkleTreeTrait, MerkleTreeServer},
-    DownloadRequest, DownloadResponse, MerkleProofRequest, MerkleProofResponse, UploadRequest,
-    UploadResponse,
+    BatchProofEntry, BatchProofRequest, BatchProofResponse, BeginUploadRequest,
+    BeginUploadResponse, DownloadByHashRequest, DownloadChunk, DownloadRequest, DownloadResponse,
+    DownloadStreamRequest, DownloadWithProofRequest, DownloadWithProofResponse,
+    FinishUploadRequest, GetTreeRequest, GetTreeResponse, LeafHashRequest, LeafHashResponse,
+    MerkleProofRequest, MerkleProofResponse, PingRequest, PingResponse, RootUpdate,
+    UploadChunkRequest, UploadChunkResponse, UploadRequest, UploadResponse, UploadStatusRequest,
+    UploadStatusResponse, WatchRootRequest,
 };
 
-#[derive(Debug)]
+// Abandoned resumable uploads are dropped after this long without receiving a chunk, unless `UPLOAD_TIMEOUT_SECS` is set
+const DEFAULT_UPLOAD_TIMEOUT_SECS: u64 = 600;
+
+// Size of the frames sent by `download_stream`, unless `DOWNLOAD_CHUNK_SIZE` is set
+const DEFAULT_DOWNLOAD_CHUNK_SIZE: usize = 256 * 1024;
+
+// Proofs cached per tree, unless `PROOF_CACHE_ENTRIES` is set. A proof is a few hundred bytes, so the default costs
+// about a megabyte per tree at most. 0 disables the cache.
+const DEFAULT_PROOF_CACHE_ENTRIES: usize = 4096;
+
+// Upload size limits, unless `MAX_UPLOAD_BYTES` (all files of one upload) and `MAX_FILE_BYTES` (any single file) are set.
+// Generous, but finite so that a client cannot exhaust the server's memory.
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 1024 * 1024 * 1024;
+const DEFAULT_MAX_FILE_BYTES: usize = 256 * 1024 * 1024;
+
+// Number of files of one upload, unless `MAX_FILE_COUNT` is set, and number of resumable uploads staged at the same
+// time, unless `MAX_STAGED_UPLOADS` is set. `BeginUpload` allocates one entry per announced file up front, so the count
+// must be bounded before the byte limits ever come into play.
+const DEFAULT_MAX_FILE_COUNT: usize = 1_000_000;
+const DEFAULT_MAX_STAGED_UPLOADS: usize = 64;
+
+// A dataset hosted by the server: the uploaded files and the Merkle tree built over them.
+// It derives `Serialize`/`Deserialize` so that it can be persisted to `DATA_DIR` as a single JSON document.
+#[derive(Debug, Serialize, Deserialize)]
+struct Dataset {
+    files: Vec<Vec<u8>>,
+    file_names: Vec<String>,
+    merkle_tree: MerkleTree,
+    // Not persisted: a reloaded tree starts with an empty cache
+    #[serde(skip)]
+    proof_cache: ProofCache,
+}
+
+// Proofs already generated for a dataset, keyed by file index and filled lazily by `get_merkle_proof` and `download`, so
+// repeated requests for the same file get a clone instead of another walk of the tree. The cache lives inside its
+// `Dataset`, so an upload to the same tree id replaces it together with the tree and a stale proof is never served.
+// It has its own lock because handlers only hold the read lock of the global state.
+#[derive(Debug, Default)]
+struct ProofCache {
+    proofs: RwLock<HashMap<usize, Vec<rustle_tree::TreeNode>>>,
+}
+
+impl ProofCache {
+    // Returns the cached proof of `file_index`, if any. A poisoned lock only disables the cache.
+    fn get(&self, file_index: usize) -> Option<Vec<rustle_tree::TreeNode>> {
+        self.proofs.read().ok()?.get(&file_index).cloned()
+    }
+
+    // Caches the proof of `file_index` while fewer than `capacity` proofs are cached. Once full, proofs of further
+    // files are generated on every request, so the memory of the cache stays bounded.
+    fn insert(&self, file_index: usize, proof: &[rustle_tree::TreeNode], capacity: usize) {
+        if let Ok(mut proofs) = self.proofs.write() {
+            if proofs.len() < capacity {
+                proofs.insert(file_index, proof.to_vec());
+            }
+        }
+    }
+}
+
+// Default gives an empty map with no datasets until the first upload
+#[derive(Debug, Default)]
 struct GlobalState {
+    // Datasets keyed by tree id, so that uploading one dataset never clobbers another
+    trees: HashMap<String, Dataset>,
+    next_tree_id: u64,
+    // One watch channel per watched tree, created by the first `WatchRoot` subscription. A watch channel only keeps
+    // the latest value, so a slow subscriber skips intermediate roots rather than buffering them.
+    root_watchers: HashMap<String, watch::Sender<String>>,
+}
+
+impl GlobalState {
+    // Looks up a dataset by tree id
+    fn dataset(&self, tree_id: &str) -> Option<&Dataset> {
+        self.trees.get(tree_id)
+    }
+
+    // Sends the new root of `tree_id` to its subscribers. `send` fails once every subscriber has gone away, in which
+    // case the channel is dropped; the next subscription creates a new one.
+    fn publish_root(&mut self, tree_id: &str, root_hash: &str) {
+        if let Some(watcher) = self.root_watchers.get(tree_id) {
+            if watcher.send(root_hash.to_string()).is_err() {
+                self.root_watchers.remove(tree_id);
+            }
+        }
+    }
+}
+
+// Tree ids double as file names under `DATA_DIR`, so they are restricted to characters that cannot escape the directory
+fn is_valid_tree_id(tree_id: &str) -> bool {
+    !tree_id.is_empty()
+        && tree_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Writes the dataset to `<data_dir>/<tree_id>.json`, replacing any previous version of the same tree
+fn persist_dataset(data_dir: &Path, tree_id: &str, dataset: &Dataset) -> io::Result<()> {
+    let json = serde_json::to_string(dataset)?;
+    write_file(
+        &data_dir.to_string_lossy(),
+        &format!("{}.json", tree_id),
+        &json,
+    )
+}
+
+// Loads every dataset previously persisted to `data_dir`, keyed by tree id (the file stem).
+// A missing directory simply means nothing has been persisted yet.
+fn load_datasets(data_dir: &Path) -> io::Result<HashMap<String, Dataset>> {
+    let mut trees = HashMap::new();
+    if !data_dir.exists() {
+        return Ok(trees);
+    }
+
+    for entry in fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let tree_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) if is_valid_tree_id(stem) => stem.to_string(),
+            _ => continue,
+        };
+
+        let json = fs::read_to_string(&path)?;
+        let dataset: Dataset = serde_json::from_str(&json)?;
+        // A tampered file must not be able to overflow the stack of later proof requests
+        dataset
+            .merkle_tree
+            .check_depth()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        trees.insert(tree_id, dataset);
+    }
+
+    Ok(trees)
+}
+
+// Generates an id that is unique across restarts by combining the current time with a sequence number
+fn generate_id(seq: u64) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{:x}-{:x}", nanos, seq)
+}
+
+// Files of a resumable upload received so far
+#[derive(Debug)]
+struct StagedUpload {
+    tree_id: String,
     files: Vec<Vec<u8>>,
-    merkle_tree: Option<MerkleTree>,
+    file_names: Vec<String>,
+    last_activity: Instant,
 }
 
-// Give default values for the `GlobalState` struct
-impl Default for GlobalState {
+// Staging area for resumable uploads keyed by upload id. It is kept apart from `GlobalState` so that streaming
+// chunks in never contends with readers of the current tree.
+#[derive(Debug)]
+struct UploadStaging {
+    uploads: HashMap<String, StagedUpload>,
+    next_id: u64,
+    timeout: Duration,
+}
+
+impl Default for UploadStaging {
     fn default() -> Self {
-        GlobalState {
-            files: Vec::new(),
-            merkle_tree: None,
+        UploadStaging {
+            uploads: HashMap::new(),
+            next_id: 0,
+            timeout: Duration::from_secs(DEFAULT_UPLOAD_TIMEOUT_SECS),
         }
     }
 }
 
-#[derive(Debug, Default)]
+impl UploadStaging {
+    // Drops every upload that has not received a chunk within the timeout
+    fn purge_expired(&mut self) {
+        let timeout = self.timeout;
+        // `retain` keeps only the entries for which the closure returns true
+        self.uploads
+            .retain(|_, upload| upload.last_activity.elapsed() < timeout);
+    }
+}
+
+// Size limits checked before a tree is built, and while the chunks of a resumable upload are staged
+#[derive(Debug, Clone, Copy)]
+struct UploadLimits {
+    max_upload_bytes: usize,
+    max_file_bytes: usize,
+    max_file_count: usize,
+    max_staged_uploads: usize,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        UploadLimits {
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_file_count: DEFAULT_MAX_FILE_COUNT,
+            max_staged_uploads: DEFAULT_MAX_STAGED_UPLOADS,
+        }
+    }
+}
+
+impl UploadLimits {
+    // Checks the number of files of one upload, before anything is allocated for them
+    fn check_file_count(&self, file_count: usize) -> Result<(), String> {
+        if file_count > self.max_file_count {
+            return Err(format!(
+                "upload has {} files, above the limit of {} files",
+                file_count, self.max_file_count
+            ));
+        }
+        Ok(())
+    }
+
+    // Checks the sizes of the files of one upload, in order, and names the first limit that is exceeded
+    fn check(&self, file_sizes: impl IntoIterator<Item = usize>) -> Result<(), String> {
+        let mut total: usize = 0;
+        for (file_index, size) in file_sizes.into_iter().enumerate() {
+            if size > self.max_file_bytes {
+                return Err(format!(
+                    "file {} is {} bytes, above the limit of {} bytes per file",
+                    file_index, size, self.max_file_bytes
+                ));
+            }
+            total = total.saturating_add(size);
+        }
+
+        if total > self.max_upload_bytes {
+            return Err(format!(
+                "upload is {} bytes in total, above the limit of {} bytes",
+                total, self.max_upload_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Id attached to every RPC so that all log lines of one request can be correlated. It is stored in the request
+// extensions by `RequestIdInterceptor` and recorded in the span of each handler.
+#[derive(Clone, Debug)]
+struct RequestId(String);
+
+// Runs before every handler. A client may pass its own id in the `x-request-id` metadata header, e.g. to tie an upload
+// to the proof requests that follow it; otherwise the server assigns the next number of a process-wide sequence.
+#[derive(Clone, Debug, Default)]
+struct RequestIdInterceptor;
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+impl Interceptor for RequestIdInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let request_id = request
+            .metadata()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).to_string());
+
+        request.extensions_mut().insert(RequestId(request_id));
+        Ok(request)
+    }
+}
+
+// Checks the `authorization` metadata header of every request against the `API_KEY` env var. The key may be sent bare
+// or as `Bearer <key>`. Without a configured key every request is let through, which keeps local development as simple
+// as before.
+#[derive(Clone, Debug, Default)]
+struct ApiKeyInterceptor {
+    api_key: Option<String>,
+}
+
+impl Interceptor for ApiKeyInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let api_key = match &self.api_key {
+            Some(api_key) => api_key,
+            None => return Ok(request),
+        };
+
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.strip_prefix("Bearer ").unwrap_or(value));
+
+        match presented {
+            Some(presented) if keys_match(presented, api_key) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("invalid API key")),
+            None => Err(Status::unauthenticated("missing API key")),
+        }
+    }
+}
+
+// Compares the keys without stopping at the first differing byte, so the response time does not reveal how much of a
+// guessed key was right
+fn keys_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// A service takes a single interceptor, so this one chains the others. Requests are authenticated first, so rejected
+// ones do not use up request ids.
+#[derive(Clone, Debug)]
+struct ServerInterceptor {
+    api_key: ApiKeyInterceptor,
+    request_ids: RequestIdInterceptor,
+}
+
+impl Interceptor for ServerInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let request = self.api_key.call(request)?;
+        self.request_ids.call(request)
+    }
+}
+
+// Request id of `request`, or "-" when it did not pass through the interceptor (e.g. when a handler is called directly)
+fn request_id<T>(request: &Request<T>) -> String {
+    request
+        .extensions()
+        .get::<RequestId>()
+        .map_or_else(|| "-".to_string(), |id| id.0.clone())
+}
+
+#[derive(Debug)]
 pub struct MerkleTreeService {
     // For a multi-threaded server: Arc allows multiple threads to share ownership of the `global_state` and ensures that it's safe to access across threads.
-    // Since accessing mutable data from multiple threads can lead to race conditions, Mutex is used to lock the data when one thread is modifying it
-    // ensuring only one thread can modify the data at a time.
-    global_state: Arc<Mutex<GlobalState>>,
+    // Since accessing mutable data from multiple threads can lead to race conditions, RwLock is used to lock the data when one thread is modifying it
+    // ensuring only one thread can modify the data at a time. Unlike a Mutex, any number of readers (`download`, `get_merkle_proof`)
+    // can hold the lock in parallel; only `upload` takes the exclusive write lock.
+    global_state: Arc<RwLock<GlobalState>>,
+    staging: Arc<Mutex<UploadStaging>>,
+    // Directory every uploaded tree is persisted to. `None` keeps all state in memory only.
+    data_dir: Option<PathBuf>,
+    // Number of file bytes per frame of a streamed download
+    download_chunk_size: usize,
+    upload_limits: UploadLimits,
+    // Maximum number of proofs cached per tree
+    proof_cache_entries: usize,
+    // Request counts and latencies per RPC, exported on `METRICS_ADDR`
+    metrics: Metrics,
+    // Reported as the uptime by `ping`
+    started_at: Instant,
+}
+
+impl Default for MerkleTreeService {
+    fn default() -> Self {
+        MerkleTreeService {
+            global_state: Arc::default(),
+            staging: Arc::default(),
+            data_dir: None,
+            download_chunk_size: DEFAULT_DOWNLOAD_CHUNK_SIZE,
+            upload_limits: UploadLimits::default(),
+            proof_cache_entries: DEFAULT_PROOF_CACHE_ENTRIES,
+            metrics: Metrics::default(),
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl MerkleTreeService {
+    // Proof of `file_index` in `dataset`, from the dataset's proof cache when it was generated before
+    fn cached_api_proofs(
+        &self,
+        dataset: &Dataset,
+        file_index: usize,
+    ) -> Result<Vec<rustle_tree::TreeNode>, MerkleTreeError> {
+        if let Some(proofs) = dataset.proof_cache.get(file_index) {
+            self.metrics.proof_cache(true);
+            return Ok(proofs);
+        }
+
+        self.metrics.proof_cache(false);
+        let proofs = generate_api_proofs(&dataset.merkle_tree, file_index)?;
+        dataset
+            .proof_cache
+            .insert(file_index, &proofs, self.proof_cache_entries);
+        Ok(proofs)
+    }
 }
 
 #[tonic::async_trait]
 impl MerkleTreeTrait for MerkleTreeService {
+    // Server-streaming RPCs name the type of the stream they return. It is boxed so the handler can return any stream.
+    type DownloadStreamStream = Pin<Box<dyn Stream<Item = Result<DownloadChunk, Status>> + Send>>;
+    type WatchRootStream = Pin<Box<dyn Stream<Item = Result<RootUpdate, Status>> + Send>>;
+
+    // Every handler runs in a span carrying the request id and the tree (or upload) id, so all log lines of a request,
+    // including those of the `merkle` crate, can be grouped, and an upload can be tied to later requests on its tree.
+    // `skip_all` keeps the request payload (e.g. uploaded files) out of the span.
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
     async fn upload(
         &self,
         request: Request<UploadRequest>,
     ) -> Result<Response<UploadResponse>, Status> {
+        let _timer = self.metrics.track("upload");
         let req = request.into_inner();
 
+        // Reject oversized uploads before spending any work on them
+        self.upload_limits
+            .check_file_count(req.files.len())
+            .map_err(Status::resource_exhausted)?;
+        self.upload_limits
+            .check(req.files.iter().map(Vec::len))
+            .map_err(Status::resource_exhausted)?;
+
         // Build the Merkle tree from the provided files
         let merkle_tree = match merkle::MerkleTree::new(&req.files) {
             Ok(tree) => tree,
-            Err(err) => return Err(Status::internal(err.to_string())),
+            Err(err) => return Err(to_status(err)),
         };
 
-        // Store the files and Merkle tree in the global state
-        let mut global_state = self.global_state.lock().unwrap();
-        global_state.files = req.files;
-        global_state.merkle_tree = Some(merkle_tree.clone());
+        // Use the client supplied tree id, or generate a new one
+        // A poisoned lock (a thread panicked while holding it) is reported to the client instead of panicking the handler
+        let tree_id = if req.tree_id.is_empty() {
+            let mut global_state = self
+                .global_state
+                .write()
+                .map_err(|_| Status::internal("global state lock poisoned"))?;
+            let seq = global_state.next_tree_id;
+            global_state.next_tree_id += 1;
+            generate_id(seq)
+        } else if is_valid_tree_id(&req.tree_id) {
+            req.tree_id
+        } else {
+            return Err(Status::invalid_argument(
+                "tree id may only contain ASCII letters, digits, '-' and '_'",
+            ));
+        };
 
-        // Calculate the Merkle root hash
+        // Calculate the Merkle root hash before the tree is moved into the dataset, so the tree is stored as is and
+        // never cloned
         let merkle_root_hash = merkle_tree.root_hash();
 
-        println!("Uploaded all files successfully to the server");
+        let dataset = Dataset {
+            files: req.files,
+            file_names: req.file_names,
+            merkle_tree,
+            proof_cache: ProofCache::default(),
+        };
+
+        // Persist before publishing the tree, so that a tree served to clients is never lost on restart.
+        // The disk write happens outside the lock to keep readers unblocked.
+        if let Some(data_dir) = &self.data_dir {
+            if let Err(err) = persist_dataset(data_dir, &tree_id, &dataset) {
+                return Err(Status::internal(format!("failed to persist tree: {}", err)));
+            }
+        }
+
+        // Store the files and Merkle tree in the global state
+        let mut global_state = self
+            .global_state
+            .write()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
+        global_state.trees.insert(tree_id.clone(), dataset);
+        global_state.publish_root(&tree_id, &merkle_root_hash);
+
+        info!(tree_id = %tree_id, "uploaded all files successfully");
 
-        // Respond with the Merkle root hash
+        // Respond with the Merkle root hash and the id to refer to this tree
         Ok(Response::new(UploadResponse {
             merkle_root_hash: merkle_root_hash.into_bytes(),
+            tree_id,
         }))
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
     async fn download(
         &self,
         request: Request<DownloadRequest>,
     ) -> Result<Response<DownloadResponse>, Status> {
+        let _timer = self.metrics.track("download");
         let req = request.into_inner();
+
+        // A negative index would wrap around to a huge `usize` in the cast below
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
         let file_index = req.file_index as usize;
 
         // Retrieve the global state
-        let global_state = self.global_state.lock().unwrap();
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+        // Before any upload there is nothing to serve, which is not the same as asking for a wrong index or tree id
+        if global_state.trees.is_empty() {
+            return Err(Status::failed_precondition("no files uploaded yet"));
+        }
+
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
+        };
 
         // Check if the requested index is within the range of stored files
-        if file_index >= global_state.files.len() {
-            return Err(Status::not_found("File index out of range"));
+        if file_index >= dataset.files.len() {
+            return Err(Status::out_of_range("File index out of range"));
         }
 
         // Retrieve the requested file
-        let file_data = global_state.files[file_index].clone();
+        let file_data = dataset.files[file_index].clone();
+
+        // Advertise the hash of the stored bytes so the client can detect corruption in transit
+        let file_hash = calc_sha256(&file_data);
+
+        // File names are optional on upload, so `get` returns None when no name was stored for this index
+        let file_name = dataset.file_names.get(file_index).cloned();
 
-        println!("Downloaded file successfully from the server");
+        // Attach the Merkle proof when asked to, so the client can fetch and verify in a single round-trip
+        let proofs = if req.with_proof {
+            self.cached_api_proofs(dataset, file_index)
+                .map_err(to_status)?
+        } else {
+            Vec::new()
+        };
+
+        info!("downloaded file successfully");
 
         // Respond with the requested file
         Ok(Response::new(DownloadResponse {
             file_content: file_data,
+            file_name,
+            file_hash,
+            proofs,
+        }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn download_by_hash(
+        &self,
+        request: Request<DownloadByHashRequest>,
+    ) -> Result<Response<DownloadResponse>, Status> {
+        let _timer = self.metrics.track("download_by_hash");
+        let req = request.into_inner();
+
+        // The hash is resolved and the file read under the same read lock, so an upload to the same tree id in between
+        // cannot make the response carry another file than the one the hash names
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
+        };
+
+        let merkle_tree = &dataset.merkle_tree;
+        let file_index = match merkle_tree
+            .find_index_by_hash(&req.leaf_hash)
+            .and_then(|leaf_idx| merkle_tree.file_index(leaf_idx))
+        {
+            Some(file_index) => file_index,
+            None => return Err(Status::not_found("No file with this leaf hash")),
+        };
+
+        let file_data = match dataset.files.get(file_index) {
+            Some(file) => file.clone(),
+            None => return Err(Status::internal("tree and files are out of sync")),
+        };
+        let file_hash = calc_sha256(&file_data);
+        let file_name = dataset.file_names.get(file_index).cloned();
+
+        info!("downloaded file by hash successfully");
+
+        Ok(Response::new(DownloadResponse {
+            file_content: file_data,
+            file_name,
+            file_hash,
+            proofs: Vec::new(),
         }))
     }
 
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn download_stream(
+        &self,
+        request: Request<DownloadStreamRequest>,
+    ) -> Result<Response<Self::DownloadStreamStream>, Status> {
+        let _timer = self.metrics.track("download_stream");
+        let req = request.into_inner();
+
+        // A negative index would wrap around to a huge `usize` in the cast below
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
+        let file_index = req.file_index as usize;
+
+        // Copy the file out so the stream owns it and the read lock is released before the first frame is sent
+        let file_data = {
+            let global_state = self
+                .global_state
+                .read()
+                .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+            let dataset = match global_state.dataset(&req.tree_id) {
+                Some(dataset) => dataset,
+                None => return Err(Status::not_found("Unknown tree id")),
+            };
+
+            match dataset.files.get(file_index) {
+                Some(file) => file.clone(),
+                None => return Err(Status::out_of_range("File index out of range")),
+            }
+        };
+
+        let mut file_hash = calc_sha256(&file_data);
+        let chunk_size = self.download_chunk_size;
+
+        // Frames are cut lazily as the client reads them. An empty file still gets one frame carrying the hash.
+        let frame_count = file_data.len().div_ceil(chunk_size).max(1);
+        let frames = (0..frame_count)
+            .map(move |frame| {
+                let start = frame * chunk_size;
+                let end = (start + chunk_size).min(file_data.len());
+                DownloadChunk {
+                    data: file_data[start..end].to_vec(),
+                    // `take` leaves an empty string behind, so only the first frame carries the hash
+                    file_hash: std::mem::take(&mut file_hash),
+                }
+            })
+            .map(Ok);
+
+        info!(frame_count, "streaming file");
+
+        Ok(Response::new(Box::pin(tokio_stream::iter(frames))))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
     async fn get_merkle_proof(
         &self,
         request: Request<MerkleProofRequest>,
     ) -> Result<Response<MerkleProofResponse>, Status> {
+        let _timer = self.metrics.track("get_merkle_proof");
         let req = request.into_inner();
+
+        // A negative index would wrap around to a huge `usize` in the cast below
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
         let file_index = req.file_index as usize;
 
         // Retrieve the global state
-        let global_state = self.global_state.lock().unwrap();
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
 
-        // Check if the requested index is within the range of stored files
-        if file_index >= global_state.files.len() {
-            return Err(Status::not_found("File index out of range"));
+        // Before any upload there is nothing to serve, which is not the same as asking for a wrong index or tree id
+        if global_state.trees.is_empty() {
+            return Err(Status::failed_precondition("no files uploaded yet"));
         }
 
-        // Ensure the Merkle tree is available
-        let merkle_tree = match &global_state.merkle_tree {
-            Some(tree) => tree,
-            None => return Err(Status::internal("Merkle tree not found")),
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
         };
 
-        // Generate the Merkle proof for the specified file index
-        let merkle_proofs = match merkle::MerkleTree::generate_merkle_proof(merkle_tree, file_index)
-        {
-            Ok(proofs) => proofs,
-            Err(err) => return Err(Status::internal(err.to_string())),
+        // Check if the requested index is within the range of stored files
+        if file_index >= dataset.files.len() {
+            return Err(Status::out_of_range("File index out of range"));
+        }
+
+        // Generate the Merkle proof for the specified file index, or reuse the one generated by an earlier request
+        let mut owned_proofs = self
+            .cached_api_proofs(dataset, file_index)
+            .map_err(to_status)?;
+        if req.raw_hashes {
+            owned_proofs.iter_mut().for_each(compact_hash);
+        }
+
+        info!("generated merkle proofs successfully");
+
+        // Respond with the requested proofs
+        // The root spans every leaf, so its right index tells the leaf count
+        let merkle_tree = &dataset.merkle_tree;
+        Ok(Response::new(MerkleProofResponse {
+            proofs: owned_proofs,
+            root_hash: merkle_tree.root_hash(),
+            leaf_count: merkle_tree.root().map_or(0, |root| root.right_idx + 1) as i64,
+        }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn download_with_proof(
+        &self,
+        request: Request<DownloadWithProofRequest>,
+    ) -> Result<Response<DownloadWithProofResponse>, Status> {
+        let _timer = self.metrics.track("download_with_proof");
+        let req = request.into_inner();
+
+        // A negative index would wrap around to a huge `usize` in the cast below
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
+        let file_index = req.file_index as usize;
+
+        // The file, the proof and the root are all read under this one read lock, so an upload to the same tree id
+        // cannot slip in between and hand out a proof of another tree than the file came from
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+        // Before any upload there is nothing to serve, which is not the same as asking for a wrong index or tree id
+        if global_state.trees.is_empty() {
+            return Err(Status::failed_precondition("no files uploaded yet"));
+        }
+
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
         };
 
-        // Convert Vec<&TreeNode> to Vec<restle_tree::TreeNode>
-        let mut owned_proofs: Vec<rustle_tree::TreeNode> = Vec::with_capacity(merkle_proofs.len());
+        // Check if the requested index is within the range of stored files
+        if file_index >= dataset.files.len() {
+            return Err(Status::out_of_range("File index out of range"));
+        }
+
+        let proofs = self
+            .cached_api_proofs(dataset, file_index)
+            .map_err(to_status)?;
 
-        for proof in merkle_proofs {
-            let mut api_proof = rustle_tree::TreeNode {
-                hash: proof.hash.clone(), // Assuming hash is of type Vec<u8> or similar
-                left_idx: proof.left_idx as i64,
-                right_idx: proof.right_idx as i64,
-                left: None,
-                right: None,
-            };
+        info!("downloaded file with its proof successfully");
 
-            // If there's a left child, create a TreeNode for it
-            if let Some(left) = &proof.left {
-                api_proof.left = Some(Box::new(rustle_tree::TreeNode {
-                    hash: left.hash.clone(),
-                    left_idx: left.left_idx as i64,
-                    right_idx: left.right_idx as i64,
-                    left: None,
-                    right: None,
-                }));
-            }
+        // The root spans every leaf, so its right index tells the leaf count
+        let merkle_tree = &dataset.merkle_tree;
+        Ok(Response::new(DownloadWithProofResponse {
+            file_content: dataset.files[file_index].clone(),
+            proofs,
+            root_hash: merkle_tree.root_hash(),
+            leaf_count: merkle_tree.root().map_or(0, |root| root.right_idx + 1) as i64,
+        }))
+    }
 
-            // If there's a right child, create a TreeNode for it
-            if let Some(right) = &proof.right {
-                api_proof.right = Some(Box::new(rustle_tree::TreeNode {
-                    hash: right.hash.clone(),
-                    left_idx: right.left_idx as i64,
-                    right_idx: right.right_idx as i64,
-                    left: None,
-                    right: None,
-                }));
-            }
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn get_leaf_hash(
+        &self,
+        request: Request<LeafHashRequest>,
+    ) -> Result<Response<LeafHashResponse>, Status> {
+        let _timer = self.metrics.track("get_leaf_hash");
+        let req = request.into_inner();
 
-            owned_proofs.push(api_proof);
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
         }
 
-        println!("Successfully generated merkle proofs");
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
 
-        // Respond with the requested proofs
-        Ok(Response::new(MerkleProofResponse {
-            proofs: owned_proofs,
+        if global_state.trees.is_empty() {
+            return Err(Status::failed_precondition("no files uploaded yet"));
+        }
+
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
+        };
+
+        let file_index = req.file_index as usize;
+        if file_index >= dataset.files.len() {
+            return Err(Status::out_of_range("File index out of range"));
+        }
+
+        // Only the tree is read, so this is much cheaper than downloading the file to hash it
+        let leaf_hash = dataset
+            .merkle_tree
+            .leaf_hash(file_index)
+            .map_err(to_status)?;
+
+        Ok(Response::new(LeafHashResponse {
+            leaf_hash,
+            root_hash: dataset.merkle_tree.root_hash(),
         }))
     }
-}
 
-// Tokio is an event-driven, non-blocking I/O platform for writing asynchronous applications with the Rust programming language.
-// With #[tokio::main], we can have an async main function, as the macro manages the runtime setup and allows asynchronous operations inside main.
-// This macro helps set up a Runtime without requiring the user to use Runtime or Builder directly.
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // .ok() suppresses any errors (e.g., if the file doesn't exist).
-    dotenv().ok();
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn get_tree(
+        &self,
+        request: Request<GetTreeRequest>,
+    ) -> Result<Response<GetTreeResponse>, Status> {
+        let _timer = self.metrics.track("get_tree");
+        let req = request.into_inner();
 
-    let addr = env::var("SERVER_ADDRESS")
-        .unwrap_or_else(|_| "[::1]:50051".to_string())
-        .parse()?;
+        // Retrieve the global state
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
 
-    println!("gRPC server running on {:?}", addr);
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
+        };
 
-    let global_state = Arc::new(Mutex::new(GlobalState::default()));
+        // Serialize with the same versioned envelope the CLI writes to disk
+        let mut tree_json = Vec::new();
+        dataset
+            .merkle_tree
+            .write_json(&mut tree_json)
+            .map_err(|e| Status::internal(e.to_string()))?;
 
-    // Cloning the Arc means another reference to the same data is created, INCREMENTING the reference count.
-    // No actual data copy (cloning) happens, so performance is maintained while allowing multiple tasks to share the same state.
-    let service = MerkleTreeService {
-        global_state: global_state.clone(),
-    };
+        Ok(Response::new(GetTreeResponse { tree_json }))
+    }
 
-    Server::builder()
-        .add_service(MerkleTreeServer::new(service))
-        .serve(addr)
-        .await?;
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn watch_root(
+        &self,
+        request: Request<WatchRootRequest>,
+    ) -> Result<Response<Self::WatchRootStream>, Status> {
+        let _timer = self.metrics.track("watch_root");
+        let req = request.into_inner();
 
-    Ok(())
+        // Subscribe under the write lock, so that an upload cannot slip in between reading the root and subscribing
+        let receiver = {
+            let mut global_state = self
+                .global_state
+                .write()
+                .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+            let root_hash = match global_state.dataset(&req.tree_id) {
+                Some(dataset) => dataset.merkle_tree.root_hash(),
+                None => return Err(Status::not_found("Unknown tree id")),
+            };
+
+            global_state
+                .root_watchers
+                .entry(req.tree_id.clone())
+                .or_insert_with(|| watch::channel(root_hash).0)
+                .subscribe()
+        };
+
+        info!("client subscribed to the root");
+
+        // `WatchStream` yields the current root first, then every root published afterwards
+        let tree_id = req.tree_id;
+        let updates = WatchStream::new(receiver)
+            .map(move |root_hash| RootUpdate {
+                root_hash,
+                tree_id: tree_id.clone(),
+            })
+            .map(Ok);
+
+        Ok(Response::new(Box::pin(updates)))
+    }
+
+    // Not instrumented: probes call it every few seconds and it logs nothing
+    async fn ping(&self, _request: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
+        let _timer = self.metrics.track("ping");
+
+        let tree_count = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?
+            .trees
+            .len();
+
+        Ok(Response::new(PingResponse {
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            tree_loaded: tree_count > 0,
+            tree_count: tree_count as u64,
+        }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn get_batch_proof(
+        &self,
+        request: Request<BatchProofRequest>,
+    ) -> Result<Response<BatchProofResponse>, Status> {
+        let _timer = self.metrics.track("get_batch_proof");
+        let req = request.into_inner();
+
+        // Retrieve the global state
+        let global_state = self
+            .global_state
+            .read()
+            .map_err(|_| Status::internal("global state lock poisoned"))?;
+
+        let dataset = match global_state.dataset(&req.tree_id) {
+            Some(dataset) => dataset,
+            None => return Err(Status::not_found("Unknown tree id")),
+        };
+
+        if req.file_indices.iter().any(|&idx| idx < 0) {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
+        let file_indices: Vec<usize> = req.file_indices.iter().map(|&idx| idx as usize).collect();
+
+        // Shared proof nodes are included once; each entry refers to its nodes by position
+        let batch_proof = match dataset.merkle_tree.generate_batch_proof(&file_indices) {
+            Ok(batch_proof) => batch_proof,
+            Err(err) => return Err(to_status(err)),
+        };
+
+        let nodes = batch_proof
+            .nodes
+            .iter()
+            .map(ToProofNode::to_proof_node)
+            .collect();
+
+        let entries = batch_proof
+            .entries
+            .into_iter()
+            .map(|entry| BatchProofEntry {
+                file_index: entry.leaf_idx as i64,
+                node_positions: entry
+                    .node_positions
+                    .into_iter()
+                    .map(|pos| pos as i64)
+                    .collect(),
+            })
+            .collect();
+
+        info!("generated batch merkle proof successfully");
+
+        Ok(Response::new(BatchProofResponse { nodes, entries }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), tree_id = %request.get_ref().tree_id))]
+    async fn begin_upload(
+        &self,
+        request: Request<BeginUploadRequest>,
+    ) -> Result<Response<BeginUploadResponse>, Status> {
+        let _timer = self.metrics.track("begin_upload");
+        let req = request.into_inner();
+        if req.file_count <= 0 {
+            return Err(Status::invalid_argument("file count must be positive"));
+        }
+        // The count comes from the client and sizes the allocation below, so it is bounded first. Counts that do not
+        // even fit a `usize` are above any limit.
+        let file_count = usize::try_from(req.file_count).unwrap_or(usize::MAX);
+        self.upload_limits
+            .check_file_count(file_count)
+            .map_err(Status::resource_exhausted)?;
+
+        let mut staging = self
+            .staging
+            .lock()
+            .map_err(|_| Status::internal("upload staging lock poisoned"))?;
+        staging.purge_expired();
+        if staging.uploads.len() >= self.upload_limits.max_staged_uploads {
+            return Err(Status::resource_exhausted(format!(
+                "{} resumable uploads are already in progress",
+                staging.uploads.len()
+            )));
+        }
+
+        let upload_id = generate_id(staging.next_id);
+        staging.next_id += 1;
+
+        staging.uploads.insert(
+            upload_id.clone(),
+            StagedUpload {
+                tree_id: req.tree_id,
+                files: vec![Vec::new(); file_count],
+                file_names: req.file_names,
+                last_activity: Instant::now(),
+            },
+        );
+
+        info!(upload_id = %upload_id, "started resumable upload");
+
+        Ok(Response::new(BeginUploadResponse { upload_id }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), upload_id = %request.get_ref().upload_id))]
+    async fn upload_chunk(
+        &self,
+        request: Request<UploadChunkRequest>,
+    ) -> Result<Response<UploadChunkResponse>, Status> {
+        let _timer = self.metrics.track("upload_chunk");
+        let req = request.into_inner();
+
+        let mut staging = self
+            .staging
+            .lock()
+            .map_err(|_| Status::internal("upload staging lock poisoned"))?;
+        let upload = match staging.uploads.get_mut(&req.upload_id) {
+            Some(upload) => upload,
+            None => return Err(Status::not_found("Unknown upload id")),
+        };
+
+        if req.file_index < 0 {
+            return Err(Status::invalid_argument("file index must be non-negative"));
+        }
+        if req.file_index as usize >= upload.files.len() {
+            return Err(Status::out_of_range("File index out of range"));
+        }
+        if req.offset < 0 {
+            return Err(Status::invalid_argument("offset must be non-negative"));
+        }
+
+        let file_index = req.file_index as usize;
+        let offset = req.offset as usize;
+
+        // Staged chunks are held in memory too, so the limits are enforced as the files grow rather than only when
+        // the upload is finished
+        let new_len = usize::max(upload.files[file_index].len(), offset + req.data.len());
+        let file_sizes = upload.files.iter().enumerate().map(|(idx, file)| {
+            if idx == file_index {
+                new_len
+            } else {
+                file.len()
+            }
+        });
+        self.upload_limits
+            .check(file_sizes)
+            .map_err(Status::resource_exhausted)?;
+
+        let file = &mut upload.files[file_index];
+
+        // Chunks are appended in order. A chunk starting before the received length is a resend after a dropped
+        // connection, so only its unseen tail is appended; a chunk starting past it would leave a gap.
+        if offset > file.len() {
+            return Err(Status::failed_precondition(format!(
+                "chunk offset {} is past the {} bytes received so far",
+                offset,
+                file.len()
+            )));
+        }
+        let already_received = file.len() - offset;
+        if already_received < req.data.len() {
+            file.extend_from_slice(&req.data[already_received..]);
+        }
+
+        let received = file.len() as i64;
+        upload.last_activity = Instant::now();
+
+        Ok(Response::new(UploadChunkResponse { received }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), upload_id = %request.get_ref().upload_id))]
+    async fn get_upload_status(
+        &self,
+        request: Request<UploadStatusRequest>,
+    ) -> Result<Response<UploadStatusResponse>, Status> {
+        let _timer = self.metrics.track("get_upload_status");
+        let req = request.into_inner();
+
+        let staging = self
+            .staging
+            .lock()
+            .map_err(|_| Status::internal("upload staging lock poisoned"))?;
+        let upload = match staging.uploads.get(&req.upload_id) {
+            Some(upload) => upload,
+            None => return Err(Status::not_found("Unknown upload id")),
+        };
+
+        Ok(Response::new(UploadStatusResponse {
+            received_offsets: upload.files.iter().map(|f| f.len() as i64).collect(),
+        }))
+    }
+
+    #[instrument(skip_all, fields(request_id = %request_id(&request), upload_id = %request.get_ref().upload_id))]
+    async fn finish_upload(
+        &self,
+        request: Request<FinishUploadRequest>,
+    ) -> Result<Response<UploadResponse>, Status> {
+        let _timer = self.metrics.track("finish_upload");
+        let req = request.get_ref();
+
+        // Take the staged files out of the staging area; the lock is released at the end of this block
+        let upload = {
+            let mut staging = self
+                .staging
+                .lock()
+                .map_err(|_| Status::internal("upload staging lock poisoned"))?;
+            match staging.uploads.remove(&req.upload_id) {
+                Some(upload) => upload,
+                None => return Err(Status::not_found("Unknown upload id")),
+            }
+        };
+
+        info!("finishing resumable upload");
+
+        // From here on a resumable upload is handled exactly like a regular one, under the same request id. It is
+        // therefore counted in the `upload` metrics as well as in the `finish_upload` ones.
+        let mut upload_request = Request::new(UploadRequest {
+            files: upload.files,
+            file_names: upload.file_names,
+            tree_id: upload.tree_id,
+        });
+        if let Some(request_id) = request.extensions().get::<RequestId>() {
+            upload_request.extensions_mut().insert(request_id.clone());
+        }
+        self.upload(upload_request).await
+    }
+}
+
+// Maps a MerkleTreeError onto the gRPC status code matching its kind, so that client mistakes such as an out-of-range index
+// are not reported as server failures.
+fn to_status(err: MerkleTreeError) -> Status {
+    match err.kind() {
+        MerkleErrorKind::IndexOutOfBounds => Status::out_of_range(err.to_string()),
+        MerkleErrorKind::EmptyInput => Status::invalid_argument(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+// Generates the Merkle proof for `file_index` and converts the `merkle::TreeNode`s to `rustle_tree::TreeNode`s so that
+// it can be sent over the wire. The proof is read from the tree's flattened hash cache, so the cost per request is
+// proportional to the tree height only. Proof nodes carry no children; verifiers only need their hash and range.
+fn generate_api_proofs(
+    merkle_tree: &MerkleTree,
+    file_index: usize,
+) -> Result<Vec<rustle_tree::TreeNode>, MerkleTreeError> {
+    let merkle_proofs = merkle_tree.generate_merkle_proof(file_index)?;
+    Ok(merkle_proofs
+        .iter()
+        .map(ToProofNode::to_proof_node)
+        .collect())
+}
+
+// Moves the hex `hash` of `node` into `hash_bytes` as raw digest bytes. Hashes come from the tree, so they always
+// decode; if one did not, it would simply stay in hex, which clients accept too.
+fn compact_hash(node: &mut rustle_tree::TreeNode) {
+    if let Some(bytes) = hex_to_bytes(&node.hash) {
+        node.hash_bytes = bytes;
+        node.hash.clear();
+    }
+}
+
+// Tokio is an event-driven, non-blocking I/O platform for writing asynchronous applications with the Rust programming language.
+// With #[tokio::main], we can have an async main function, as the macro manages the runtime setup and allows asynchronous operations inside main.
+// This macro helps set up a Runtime without requiring the user to use Runtime or Builder directly.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // .ok() suppresses any errors (e.g., if the file doesn't exist).
+    dotenv().ok();
+
+    // Structured logs on stdout. The level is taken from `RUST_LOG` (e.g. `RUST_LOG=debug`) and defaults to info.
+    // Log records of the `merkle` crate end up here too, inside the span of the request that produced them.
+    // Colors are only used on a terminal, so that log files and collectors receive plain text.
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_ansi(io::stdout().is_terminal())
+        .init();
+
+    let addr = env::var("SERVER_ADDRESS")
+        .unwrap_or_else(|_| "[::1]:50051".to_string())
+        .parse()?;
+
+    info!("gRPC server running on {:?}", addr);
+
+    // Persist uploaded trees to `DATA_DIR` and reload them on startup. Without it, state lives in memory only.
+    let data_dir = env::var("DATA_DIR").ok().map(PathBuf::from);
+    let trees = match &data_dir {
+        Some(data_dir) => {
+            let trees = load_datasets(data_dir)?;
+            info!(
+                "loaded {} persisted tree(s) from {:?}",
+                trees.len(),
+                data_dir
+            );
+            trees
+        }
+        None => HashMap::new(),
+    };
+
+    let global_state = Arc::new(RwLock::new(GlobalState {
+        trees,
+        ..GlobalState::default()
+    }));
+
+    let upload_timeout = env::var("UPLOAD_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_UPLOAD_TIMEOUT_SECS);
+    let staging = Arc::new(Mutex::new(UploadStaging {
+        timeout: Duration::from_secs(upload_timeout),
+        ..UploadStaging::default()
+    }));
+
+    // Cloning the Arc means another reference to the same data is created, INCREMENTING the reference count.
+    // No actual data copy (cloning) happens, so performance is maintained while allowing multiple tasks to share the same state.
+    let download_chunk_size = env::var("DOWNLOAD_CHUNK_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .filter(|&size| size > 0)
+        .unwrap_or(DEFAULT_DOWNLOAD_CHUNK_SIZE);
+
+    let read_limit = |var: &str, default: usize| {
+        env::var(var)
+            .ok()
+            .and_then(|bytes| bytes.parse().ok())
+            .unwrap_or(default)
+    };
+    let upload_limits = UploadLimits {
+        max_upload_bytes: read_limit("MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES),
+        max_file_bytes: read_limit("MAX_FILE_BYTES", DEFAULT_MAX_FILE_BYTES),
+        max_file_count: read_limit("MAX_FILE_COUNT", DEFAULT_MAX_FILE_COUNT),
+        max_staged_uploads: read_limit("MAX_STAGED_UPLOADS", DEFAULT_MAX_STAGED_UPLOADS),
+    };
+
+    let service = MerkleTreeService {
+        global_state: global_state.clone(),
+        staging: staging.clone(),
+        data_dir,
+        download_chunk_size,
+        upload_limits,
+        proof_cache_entries: read_limit("PROOF_CACHE_ENTRIES", DEFAULT_PROOF_CACHE_ENTRIES),
+        metrics: Metrics::default(),
+        started_at: Instant::now(),
+    };
+
+    // Prometheus metrics are served on their own port, next to the gRPC server, when `METRICS_ADDR` is set
+    if let Ok(metrics_addr) = env::var("METRICS_ADDR") {
+        let metrics_addr = metrics_addr.parse()?;
+        let metrics = service.metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_addr, metrics).await {
+                warn!("metrics endpoint stopped: {}", e);
+            }
+        });
+    }
+
+    // Periodically drop resumable uploads that were abandoned by their clients
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Ok(mut staging) = staging.lock() {
+                staging.purge_expired();
+            }
+        }
+    });
+
+    let api_key = env::var("API_KEY").ok().filter(|key| !key.is_empty());
+    if api_key.is_none() {
+        warn!("API_KEY is not set, authentication is disabled");
+    }
+    let interceptor = ServerInterceptor {
+        api_key: ApiKeyInterceptor { api_key },
+        request_ids: RequestIdInterceptor,
+    };
+
+    // Server reflection lets `grpcurl` and similar tools discover the service without the `.proto` file. It only
+    // describes the API, so it is served without the API key interceptor.
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(rustle_tree::FILE_DESCRIPTOR_SET)
+        .build()?;
+
+    // Stop accepting new connections on Ctrl-C or SIGTERM, but let in-flight requests complete before exiting
+    Server::builder()
+        .add_service(MerkleTreeServer::with_interceptor(service, interceptor))
+        .add_service(reflection)
+        .serve_with_shutdown(addr, shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+// Resolves on Ctrl-C, or on SIGTERM on Unix, which is how Kubernetes asks a pod to stop
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    // Whichever signal arrives first triggers the shutdown
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("shutting down gracefully");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle::ProofFile;
+    use tokio_stream::StreamExt;
+
+    const TREE_ID: &str = "test";
+
+    fn sample_files() -> Vec<Vec<u8>> {
+        (b'A'..=b'E').map(|c| vec![c]).collect()
+    }
+
+    // Uploads `files` to a fresh service and returns it together with the root hash
+    async fn upload_files(files: Vec<Vec<u8>>) -> (MerkleTreeService, String) {
+        let service = MerkleTreeService::default();
+        let response = service
+            .upload(Request::new(UploadRequest {
+                files,
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let root_hash = String::from_utf8(response.merkle_root_hash).unwrap();
+        (service, root_hash)
+    }
+
+    // Converts the wire proof nodes back into `merkle::TreeNode`s
+    fn to_merkle_nodes(proofs: &[rustle_tree::TreeNode]) -> Vec<merkle::TreeNode> {
+        proofs
+            .iter()
+            .map(rustle_tree::TreeNode::to_tree_node)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn empty_upload_is_invalid_argument() {
+        let service = MerkleTreeService::default();
+        let status = service
+            .upload(Request::new(UploadRequest {
+                files: Vec::new(),
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_downloads_during_upload() {
+        let (service, _) = upload_files(sample_files()).await;
+        let service = Arc::new(service);
+
+        // A large upload competes with many concurrent downloads for the lock
+        let uploader = {
+            let service = service.clone();
+            tokio::spawn(async move {
+                let files: Vec<Vec<u8>> = (0..4096u32).map(|i| i.to_be_bytes().to_vec()).collect();
+                service
+                    .upload(Request::new(UploadRequest {
+                        files,
+                        file_names: Vec::new(),
+                        tree_id: TREE_ID.to_string(),
+                    }))
+                    .await
+                    .unwrap();
+            })
+        };
+
+        let downloads: Vec<_> = (0..64)
+            .map(|i| {
+                let service = service.clone();
+                tokio::spawn(async move {
+                    service
+                        .download(Request::new(DownloadRequest {
+                            file_index: i % 5,
+                            with_proof: true,
+                            tree_id: TREE_ID.to_string(),
+                        }))
+                        .await
+                        .unwrap();
+                })
+            })
+            .collect();
+
+        let all = async {
+            uploader.await.unwrap();
+            for download in downloads {
+                download.await.unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(30), all)
+            .await
+            .expect("concurrent downloads deadlocked");
+    }
+
+    #[tokio::test]
+    async fn resumable_upload_after_dropped_connection() {
+        let files = vec![b"first file contents".to_vec(), b"second".to_vec()];
+        let (_, expected_root) = upload_files(files.clone()).await;
+
+        let service = MerkleTreeService::default();
+        let upload_id = service
+            .begin_upload(Request::new(BeginUploadRequest {
+                file_count: 2,
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .upload_id;
+
+        let send = |file_index: usize, offset: usize, len: usize| {
+            let data = files[file_index][offset..offset + len].to_vec();
+            service.upload_chunk(Request::new(UploadChunkRequest {
+                upload_id: upload_id.clone(),
+                file_index: file_index as i64,
+                offset: offset as i64,
+                data,
+            }))
+        };
+
+        // The first 10 bytes of file 0 arrive, then the connection drops
+        send(0, 0, 10).await.unwrap();
+
+        // The client asks where to resume from
+        let status = service
+            .get_upload_status(Request::new(UploadStatusRequest {
+                upload_id: upload_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.received_offsets, vec![10, 0]);
+
+        // Resending an overlapping chunk is harmless, a gap is rejected
+        send(0, 5, 10).await.unwrap();
+        assert!(send(1, 3, 3).await.is_err());
+
+        send(0, 15, files[0].len() - 15).await.unwrap();
+        send(1, 0, files[1].len()).await.unwrap();
+
+        let response = service
+            .finish_upload(Request::new(FinishUploadRequest {
+                upload_id: upload_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            String::from_utf8(response.merkle_root_hash).unwrap(),
+            expected_root
+        );
+
+        // The staged upload is gone once finished
+        assert!(service
+            .get_upload_status(Request::new(UploadStatusRequest { upload_id }))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn abandoned_uploads_are_purged() {
+        let mut staging = UploadStaging {
+            timeout: Duration::ZERO,
+            ..UploadStaging::default()
+        };
+        staging.uploads.insert(
+            "abandoned".to_string(),
+            StagedUpload {
+                tree_id: TREE_ID.to_string(),
+                files: vec![Vec::new()],
+                file_names: Vec::new(),
+                last_activity: Instant::now(),
+            },
+        );
+
+        staging.purge_expired();
+        assert!(staging.uploads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn multiple_named_trees() {
+        let (service, first_root) = upload_files(sample_files()).await;
+
+        // A second upload without an id gets a generated one and leaves the first tree untouched
+        let response = service
+            .upload(Request::new(UploadRequest {
+                files: vec![b"other".to_vec()],
+                file_names: Vec::new(),
+                tree_id: String::new(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!response.tree_id.is_empty());
+        assert_ne!(response.tree_id, TREE_ID);
+
+        let download = |tree_id: String| {
+            service.download(Request::new(DownloadRequest {
+                file_index: 0,
+                with_proof: false,
+                tree_id,
+            }))
+        };
+
+        let first = download(TREE_ID.to_string()).await.unwrap().into_inner();
+        assert_eq!(first.file_content, b"A");
+        let second = download(response.tree_id).await.unwrap().into_inner();
+        assert_eq!(second.file_content, b"other");
+
+        let status = download("missing".to_string()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        // The first tree's root is still served
+        let response = service
+            .get_merkle_proof(Request::new(MerkleProofRequest {
+                file_index: 0,
+                tree_id: TREE_ID.to_string(),
+                raw_hashes: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.root_hash, first_root);
+        assert_eq!(response.leaf_count, sample_files().len() as i64);
+        let nodes = to_merkle_nodes(&response.proofs);
+        let proof =
+            ProofFile::from_nodes(first_root, 0, &nodes.iter().collect::<Vec<_>>()).unwrap();
+        assert!(proof.verify(b"A").unwrap());
+    }
+
+    #[tokio::test]
+    async fn persisted_trees_survive_restart() {
+        let data_dir = env::temp_dir().join(format!("rustle-tree-{}", generate_id(0)));
+        let service = MerkleTreeService {
+            data_dir: Some(data_dir.clone()),
+            ..MerkleTreeService::default()
+        };
+        let response = service
+            .upload(Request::new(UploadRequest {
+                files: sample_files(),
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // Simulate a restart by loading the persisted trees into a fresh service
+        let restarted = MerkleTreeService {
+            global_state: Arc::new(RwLock::new(GlobalState {
+                trees: load_datasets(&data_dir).unwrap(),
+                ..GlobalState::default()
+            })),
+            ..MerkleTreeService::default()
+        };
+        let download = restarted
+            .download(Request::new(DownloadRequest {
+                file_index: 2,
+                with_proof: false,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(download.file_content, b"C");
+
+        let root_hash = restarted
+            .global_state
+            .read()
+            .unwrap()
+            .dataset(TREE_ID)
+            .unwrap()
+            .merkle_tree
+            .root_hash();
+        assert_eq!(root_hash.into_bytes(), response.merkle_root_hash);
+
+        // Ids that could escape the data directory are rejected
+        let status = service
+            .upload(Request::new(UploadRequest {
+                files: sample_files(),
+                file_names: Vec::new(),
+                tree_id: "../escape".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_proof_is_smaller_than_independent_proofs() {
+        let files: Vec<Vec<u8>> = (0..8u8).map(|c| vec![c]).collect();
+        let (service, root_hash) = upload_files(files.clone()).await;
+        let indices = [0i64, 1, 2, 3];
+
+        let batch = service
+            .get_batch_proof(Request::new(BatchProofRequest {
+                file_indices: indices.to_vec(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut independent = 0;
+        for idx in indices {
+            let proofs = service
+                .get_merkle_proof(Request::new(MerkleProofRequest {
+                    file_index: idx,
+                    tree_id: TREE_ID.to_string(),
+                    raw_hashes: false,
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+                .proofs;
+            independent += proofs.len();
+        }
+        assert!(batch.nodes.len() < independent);
+
+        // Each file's proof is reconstructed from the shared node set and verifies on its own
+        let nodes = to_merkle_nodes(&batch.nodes);
+        for entry in &batch.entries {
+            let proofs: Vec<&merkle::TreeNode> = entry
+                .node_positions
+                .iter()
+                .map(|&pos| &nodes[pos as usize])
+                .collect();
+            let idx = entry.file_index as usize;
+            let proof = ProofFile::from_nodes(root_hash.clone(), idx, &proofs).unwrap();
+            assert!(proof.verify(&files[idx]).unwrap());
+        }
+        assert_eq!(batch.entries.len(), indices.len());
+    }
+
+    #[tokio::test]
+    async fn download_stream_reassembles_file() {
+        let large: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let (service, _) = upload_files(vec![b"small".to_vec(), large.clone()]).await;
+        let service = MerkleTreeService {
+            download_chunk_size: 4096,
+            ..service
+        };
+
+        let frames: Vec<DownloadChunk> = service
+            .download_stream(Request::new(DownloadStreamRequest {
+                file_index: 1,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].file_hash, calc_sha256(&large));
+        assert!(frames[1..].iter().all(|frame| frame.file_hash.is_empty()));
+        let reassembled: Vec<u8> = frames.into_iter().flat_map(|frame| frame.data).collect();
+        assert_eq!(reassembled, large);
+    }
+
+    #[tokio::test]
+    async fn get_tree_returns_the_whole_tree() {
+        let (service, root_hash) = upload_files(sample_files()).await;
+
+        let get_tree = |tree_id: &str| {
+            service.get_tree(Request::new(GetTreeRequest {
+                tree_id: tree_id.to_string(),
+            }))
+        };
+
+        let tree_json = get_tree(TREE_ID).await.unwrap().into_inner().tree_json;
+        let merkle_tree = MerkleTree::from_json(std::str::from_utf8(&tree_json).unwrap()).unwrap();
+        assert_eq!(merkle_tree, MerkleTree::new(&sample_files()).unwrap());
+        assert_eq!(merkle_tree.root_hash(), root_hash);
+
+        let status = get_tree("missing").await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn nothing_uploaded_is_a_failed_precondition() {
+        let service = MerkleTreeService::default();
+
+        let status = service
+            .download(Request::new(DownloadRequest {
+                file_index: 0,
+                with_proof: false,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        let status = service
+            .get_merkle_proof(Request::new(MerkleProofRequest {
+                file_index: 0,
+                tree_id: TREE_ID.to_string(),
+                raw_hashes: false,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn negative_file_index_is_invalid() {
+        let (service, _) = upload_files(sample_files()).await;
+
+        let status = service
+            .download(Request::new(DownloadRequest {
+                file_index: -1,
+                with_proof: false,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let status = service
+            .get_merkle_proof(Request::new(MerkleProofRequest {
+                file_index: -1,
+                tree_id: TREE_ID.to_string(),
+                raw_hashes: false,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn file_index_past_the_end_is_out_of_range() {
+        let files = sample_files();
+        let (service, _) = upload_files(files.clone()).await;
+
+        let status = service
+            .download(Request::new(DownloadRequest {
+                file_index: files.len() as i64,
+                with_proof: false,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+
+        let status = service
+            .get_merkle_proof(Request::new(MerkleProofRequest {
+                file_index: files.len() as i64,
+                tree_id: TREE_ID.to_string(),
+                raw_hashes: false,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    async fn download_by_hash() {
+        let (service, _) = upload_files(sample_files()).await;
+
+        let download = |leaf_hash: String| {
+            service.download_by_hash(Request::new(DownloadByHashRequest {
+                leaf_hash,
+                tree_id: TREE_ID.to_string(),
+            }))
+        };
+
+        let response = download(calc_sha256(b"D")).await.unwrap().into_inner();
+        assert_eq!(response.file_content, b"D");
+        assert_eq!(response.file_hash, calc_sha256(b"D"));
+
+        let status = download(calc_sha256(b"Z")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn download_with_proof_verifies_against_root() {
+        let files = sample_files();
+        let (service, root_hash) = upload_files(files.clone()).await;
+
+        for (idx, file) in files.iter().enumerate() {
+            let response = service
+                .download(Request::new(DownloadRequest {
+                    file_index: idx as i64,
+                    with_proof: true,
+                    tree_id: TREE_ID.to_string(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+
+            assert_eq!(&response.file_content, file);
+
+            let nodes = to_merkle_nodes(&response.proofs);
+            let proof =
+                ProofFile::from_nodes(root_hash.clone(), idx, &nodes.iter().collect::<Vec<_>>())
+                    .unwrap();
+            assert!(proof.verify(&response.file_content).unwrap());
+        }
+
+        // Without the flag no proof is attached
+        let response = service
+            .download(Request::new(DownloadRequest {
+                file_index: 0,
+                with_proof: false,
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.proofs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stored_tree_serves_correct_proofs() {
+        let files: Vec<Vec<u8>> = (0..100u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let (service, root_hash) = upload_files(files.clone()).await;
+
+        // The tree moved into the dataset is the one whose root was returned to the client
+        let expected = MerkleTree::new(&files).unwrap();
+        {
+            let global_state = service.global_state.read().unwrap();
+            let stored = &global_state.dataset(TREE_ID).unwrap().merkle_tree;
+            assert_eq!(stored.root_hash(), root_hash);
+            assert_eq!(*stored, expected);
+        }
+
+        for (idx, file) in files.iter().enumerate() {
+            let response = service
+                .get_merkle_proof(Request::new(MerkleProofRequest {
+                    file_index: idx as i64,
+                    tree_id: TREE_ID.to_string(),
+                    raw_hashes: false,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+
+            let nodes = to_merkle_nodes(&response.proofs);
+            let proof =
+                ProofFile::from_nodes(root_hash.clone(), idx, &nodes.iter().collect::<Vec<_>>())
+                    .unwrap();
+            assert!(proof.verify(file).unwrap());
+        }
+    }
+
+    // Waits for the next update of a `WatchRoot` stream and returns its root hash
+    async fn next_root(
+        updates: &mut <MerkleTreeService as MerkleTreeTrait>::WatchRootStream,
+    ) -> String {
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.next())
+            .await
+            .expect("no root update")
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.tree_id, TREE_ID);
+        update.root_hash
+    }
+
+    #[tokio::test]
+    async fn watch_root_streams_new_roots() {
+        let (service, first_root) = upload_files(sample_files()).await;
+
+        let mut updates = service
+            .watch_root(Request::new(WatchRootRequest {
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        // The current root arrives first, then the root of every new upload
+        assert_eq!(next_root(&mut updates).await, first_root);
+
+        let second_root = service
+            .upload(Request::new(UploadRequest {
+                files: vec![b"new".to_vec()],
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .merkle_root_hash;
+        assert_eq!(next_root(&mut updates).await.into_bytes(), second_root);
+
+        let status = service
+            .watch_root(Request::new(WatchRootRequest {
+                tree_id: "missing".to_string(),
+            }))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn oversized_uploads_are_rejected() {
+        let service = MerkleTreeService {
+            upload_limits: UploadLimits {
+                max_upload_bytes: 10,
+                max_file_bytes: 4,
+                ..UploadLimits::default()
+            },
+            ..MerkleTreeService::default()
+        };
+        let upload = |files: Vec<Vec<u8>>| {
+            service.upload(Request::new(UploadRequest {
+                files,
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+        };
+
+        let status = upload(vec![vec![0; 4], vec![0; 5]]).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(status.message().contains("file 1 is 5 bytes"));
+
+        let status = upload(vec![vec![0; 4]; 3]).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(status.message().contains("12 bytes in total"));
+
+        assert!(upload(vec![vec![0; 4], vec![0; 4]]).await.is_ok());
+
+        // Resumable uploads are checked chunk by chunk
+        let upload_id = service
+            .begin_upload(Request::new(BeginUploadRequest {
+                file_count: 1,
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .upload_id;
+        let send = |offset: i64, len: usize| {
+            service.upload_chunk(Request::new(UploadChunkRequest {
+                upload_id: upload_id.clone(),
+                file_index: 0,
+                offset,
+                data: vec![0; len],
+            }))
+        };
+        send(0, 3).await.unwrap();
+        let status = send(3, 2).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[test]
+    fn interceptor_assigns_request_ids() {
+        let mut interceptor = RequestIdInterceptor;
+
+        // Every request gets its own id
+        let first = interceptor.call(Request::new(())).unwrap();
+        let second = interceptor.call(Request::new(())).unwrap();
+        assert_ne!(request_id(&first), request_id(&second));
+        assert_ne!(request_id(&first), "-");
+
+        // An id sent by the client is kept
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("x-request-id", "upload-42".parse().unwrap());
+        let request = interceptor.call(request).unwrap();
+        assert_eq!(request_id(&request), "upload-42");
+
+        assert_eq!(request_id(&Request::new(())), "-");
+    }
+
+    #[test]
+    fn api_key_is_checked_when_configured() {
+        let with_auth = |value: &str| {
+            let mut request = Request::new(());
+            request
+                .metadata_mut()
+                .insert("authorization", value.parse().unwrap());
+            request
+        };
+
+        // Without a key, everything is let through
+        let mut open = ApiKeyInterceptor::default();
+        assert!(open.call(Request::new(())).is_ok());
+
+        let mut interceptor = ApiKeyInterceptor {
+            api_key: Some("s3cret".to_string()),
+        };
+        assert!(interceptor.call(with_auth("s3cret")).is_ok());
+        assert!(interceptor.call(with_auth("Bearer s3cret")).is_ok());
+
+        for request in [Request::new(()), with_auth("wrong"), with_auth("s3cret2")] {
+            let status = interceptor.call(request).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[tokio::test]
+    async fn handlers_record_metrics() {
+        let (service, _) = upload_files(sample_files()).await;
+        for file_index in [0, 1, 99] {
+            let _ = service
+                .download(Request::new(DownloadRequest {
+                    file_index,
+                    with_proof: false,
+                    tree_id: TREE_ID.to_string(),
+                }))
+                .await;
+        }
+
+        // Failed requests are counted and timed too
+        let metrics = service.metrics.render();
+        assert!(metrics.contains("rustle_tree_requests_total{rpc=\"upload\"} 1"));
+        assert!(metrics.contains("rustle_tree_requests_total{rpc=\"download\"} 3"));
+        assert!(metrics.contains("rustle_tree_request_duration_seconds_count{rpc=\"download\"} 3"));
+    }
+
+    #[tokio::test]
+    async fn raw_hash_proofs_are_half_the_size() {
+        use prost::Message;
+
+        let files: Vec<Vec<u8>> = (0..1024u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let (service, _) = upload_files(files).await;
+        let proof = |raw_hashes| {
+            service.get_merkle_proof(Request::new(MerkleProofRequest {
+                file_index: 7,
+                tree_id: TREE_ID.to_string(),
+                raw_hashes,
+            }))
+        };
+        let hex = proof(false).await.unwrap().into_inner();
+        let raw = proof(true).await.unwrap().into_inner();
+
+        assert_eq!(hex.proofs.len(), raw.proofs.len());
+        for (hex_node, raw_node) in hex.proofs.iter().zip(&raw.proofs) {
+            assert!(hex_node.hash_bytes.is_empty());
+            assert!(raw_node.hash.is_empty());
+            assert_eq!(raw_node.hash_bytes.len(), 32);
+            assert_eq!(util::bytes_to_hex(&raw_node.hash_bytes), hex_node.hash);
+        }
+
+        let proofs_len = |response: &MerkleProofResponse| {
+            response
+                .proofs
+                .iter()
+                .map(Message::encoded_len)
+                .sum::<usize>()
+        };
+        assert!(proofs_len(&raw) * 10 < proofs_len(&hex) * 6);
+    }
+
+    #[tokio::test]
+    async fn ping_reports_readiness() {
+        async fn ping(service: &MerkleTreeService) -> PingResponse {
+            let response = service.ping(Request::new(PingRequest {})).await;
+            response.unwrap().into_inner()
+        }
+
+        let starting = MerkleTreeService::default();
+        let response = ping(&starting).await;
+        assert!(!response.tree_loaded);
+        assert_eq!(response.tree_count, 0);
+
+        let (ready, _) = upload_files(sample_files()).await;
+        let response = ping(&ready).await;
+        assert!(response.tree_loaded);
+        assert_eq!(response.tree_count, 1);
+    }
+
+    #[tokio::test]
+    async fn leaf_hash_matches_the_local_file() {
+        async fn leaf_hash(
+            service: &MerkleTreeService,
+            file_index: i64,
+        ) -> Result<LeafHashResponse, Status> {
+            service
+                .get_leaf_hash(Request::new(LeafHashRequest {
+                    file_index,
+                    tree_id: TREE_ID.to_string(),
+                }))
+                .await
+                .map(Response::into_inner)
+        }
+
+        let files = sample_files();
+        let (service, root_hash) = upload_files(files.clone()).await;
+
+        for (idx, file) in files.iter().enumerate() {
+            let response = leaf_hash(&service, idx as i64).await.unwrap();
+            assert_eq!(response.leaf_hash, calc_sha256(file));
+            assert_eq!(response.root_hash, root_hash);
+        }
+
+        let status = leaf_hash(&service, files.len() as i64).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+        let status = leaf_hash(&service, -1).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn repeated_proofs_come_from_the_cache() {
+        async fn proof(
+            service: &MerkleTreeService,
+            raw_hashes: bool,
+        ) -> Vec<rustle_tree::TreeNode> {
+            service
+                .get_merkle_proof(Request::new(MerkleProofRequest {
+                    file_index: 1,
+                    tree_id: TREE_ID.to_string(),
+                    raw_hashes,
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+                .proofs
+        }
+
+        let (service, _) = upload_files(sample_files()).await;
+        let first = proof(&service, false).await;
+        assert_eq!(proof(&service, false).await, first);
+
+        // The cache keeps hex hashes, so raw digests are still derived per request
+        let raw = proof(&service, true).await;
+        assert!(raw.iter().all(|node| node.hash.is_empty()));
+
+        // Only the first request walked the tree
+        let metrics = service.metrics.render();
+        assert!(metrics.contains("rustle_tree_proof_cache_total{result=\"miss\"} 1"));
+        assert!(metrics.contains("rustle_tree_proof_cache_total{result=\"hit\"} 2"));
+
+        // An upload to the same tree id starts a new cache, so the proof matches the new tree
+        let files: Vec<Vec<u8>> = (b'V'..=b'Z').map(|c| vec![c]).collect();
+        service
+            .upload(Request::new(UploadRequest {
+                files: files.clone(),
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap();
+
+        let expected = merkle::MerkleTree::new(&files)
+            .unwrap()
+            .generate_merkle_proof(1)
+            .unwrap();
+        assert_eq!(to_merkle_nodes(&proof(&service, false).await), expected);
+        assert!(service
+            .metrics
+            .render()
+            .contains("rustle_tree_proof_cache_total{result=\"miss\"} 2"));
+    }
+
+    #[test]
+    fn reflection_describes_the_merkle_tree_service() {
+        assert!(tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(rustle_tree::FILE_DESCRIPTOR_SET)
+            .build()
+            .is_ok());
+
+        // The generated service name must appear in the descriptors that reflection serves
+        let name = b"MerkleTree";
+        assert!(rustle_tree::FILE_DESCRIPTOR_SET
+            .windows(name.len())
+            .any(|window| window == name));
+    }
+
+    #[tokio::test]
+    async fn download_with_proof_returns_a_verifiable_file() {
+        async fn download_with_proof(
+            service: &MerkleTreeService,
+            file_index: i64,
+        ) -> Result<DownloadWithProofResponse, Status> {
+            service
+                .download_with_proof(Request::new(DownloadWithProofRequest {
+                    file_index,
+                    tree_id: TREE_ID.to_string(),
+                }))
+                .await
+                .map(Response::into_inner)
+        }
+
+        let files = sample_files();
+        let (service, root_hash) = upload_files(files.clone()).await;
+
+        for (idx, file) in files.iter().enumerate() {
+            let response = download_with_proof(&service, idx as i64).await.unwrap();
+            assert_eq!(&response.file_content, file);
+            assert_eq!(response.root_hash, root_hash);
+            assert_eq!(response.leaf_count, files.len() as i64);
+
+            let proofs: Vec<merkle::TreeNode> = response
+                .proofs
+                .iter()
+                .map(rustle_tree::TreeNode::to_tree_node)
+                .collect();
+            let proof_file = ProofFile::from_nodes(
+                response.root_hash.clone(),
+                idx,
+                &proofs.iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+            assert!(proof_file.verify(&response.file_content).unwrap());
+        }
+
+        let status = download_with_proof(&service, files.len() as i64)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+        let status = download_with_proof(&service, -1).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn huge_file_counts_are_rejected() {
+        let service = MerkleTreeService {
+            upload_limits: UploadLimits {
+                max_file_count: 10,
+                max_staged_uploads: 2,
+                ..UploadLimits::default()
+            },
+            ..MerkleTreeService::default()
+        };
+        let begin = |file_count: i64| {
+            service.begin_upload(Request::new(BeginUploadRequest {
+                file_count,
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+        };
+
+        // Rejected before anything is allocated, so this neither panics nor runs out of memory
+        for file_count in [11, 1_000_000_000, i64::MAX] {
+            let status = begin(file_count).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+            assert!(status.message().contains("above the limit of 10 files"));
+        }
+
+        // Only `max_staged_uploads` uploads can be in progress at once
+        begin(10).await.unwrap();
+        begin(1).await.unwrap();
+        let status = begin(1).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        let status = service
+            .upload(Request::new(UploadRequest {
+                files: vec![vec![0]; 11],
+                file_names: Vec::new(),
+                tree_id: TREE_ID.to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
 }
@@ -2,14 +2,32 @@
 // it automatically reads and parses arguments passed from the command line and maps them to fields in your struct.
 use clap::Parser;
 use grpc_client::{
-    download, get_merkle_proof, rustle_tree::TreeNode as RustleTreeNode, setup_grpc_client, upload,
+    download, get_leaf_hash, get_merkle_proof, setup_grpc_client, upload_with_names, ProofNode,
 };
 
-use merkle::TreeNode;
+use merkle::{HashAlgorithm, HashMode, ProofFile, TreeNode};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, BufWriter, IsTerminal, Read};
+use std::path::{Path, PathBuf};
 use tokio::runtime::Runtime;
-use util::{calc_sha256, read_files_from_dir, write_file};
+use util::{
+    append_root_log, read_files_from_dir, read_files_from_dir_gz, read_files_from_tar,
+    read_files_with_names, verify_root_in_log, write_file,
+};
+
+// Format of the verification result printed by `-v` and `--verify-standalone`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+// Encoding of the Merkle tree file written by `-b` and read by `-v`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TreeFormat {
+    Json,
+    Bincode,
+}
 
 /// Rustle Tree CLI for uploading files, building merkle trees, downloading files by index, generating and verifying Merkle proofs.
 #[derive(Parser, Debug)]
@@ -29,13 +47,49 @@ struct Args {
     #[arg(short = 'v', long, action = clap::ArgAction::SetTrue)]
     verify_proof: bool,
 
+    // Verifies a proof file against the single file in `--files-dir`, without the Merkle tree or the other files
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    verify_standalone: bool,
+
+    // Prints every step of the proof at `--proof-path` for the file at `--file-index` of `--files-dir`: the sibling hash,
+    // its side and the running hash, then the computed root next to the expected one
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    explain_proof: bool,
+
+    // Compares the hash of the file at `--file-index` of `--files-dir` with the leaf hash the server stores for it,
+    // without downloading the file
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    check_file: bool,
+
+    // Builds the tree locally and prints its root hash, without contacting the server
+    #[arg(short = 'r', long, action = clap::ArgAction::SetTrue)]
+    root_hash: bool,
+
+    // Reads the leaves from stdin instead of a directory (one per line), builds the tree and prints its root hash
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    stdin: bool,
+
+    // Splits stdin on NUL bytes instead of newlines, e.g. for `find -print0`
+    #[arg(long, action = clap::ArgAction::SetTrue, requires = "stdin")]
+    null: bool,
+
     // PathBuf: cross-platform owned mutable path
     #[arg(short = 'f', long, value_name = "DIR_PATH")]
     files_dir: Option<PathBuf>,
 
+    // Gunzips the files of `-r` and `-b` whose name ends in `.gz`, so the tree commits to their decompressed contents
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "tar")]
+    gunzip: bool,
+
+    // Reads the files of `-r` and `-b` from a tar archive instead of `--files-dir`, ordered by their path in the archive
+    #[arg(long, value_name = "TAR_PATH", conflicts_with = "files_dir")]
+    tar: Option<PathBuf>,
+
     #[arg(short = 'O', long, value_name = "MERKLE_ROOT_HASH_PATH")]
     merkle_root_hash_path: Option<PathBuf>,
 
+    // Where `-b` stores the tree. `-v` does not need it, since the proof file is self-contained, but cross-checks the
+    // tree against the proof and the stored root when it is given.
     #[arg(short = 'P', long, value_name = "MERKLE_TREE_PATH")]
     merkle_tree_path: Option<PathBuf>,
 
@@ -55,13 +109,37 @@ struct Args {
     )]
     output_path: Option<PathBuf>,
 
-    #[arg(
-        short = 'p',
-        long,
-        value_name = "PROOF_PATH",
-        requires = "verify_proof"
-    )]
+    #[arg(short = 'p', long, value_name = "PROOF_PATH")]
     proof_path: Option<PathBuf>,
+
+    // The server keeps one Merkle tree per id, so several datasets can be hosted side by side
+    #[arg(short = 't', long, value_name = "TREE_ID", default_value = "default")]
+    tree_id: String,
+
+    // Hash function used by `-b` to build the tree (sha256 when omitted). `-v` reads the algorithm back from the tree JSON,
+    // so the flag only needs to be passed there to insist on a particular algorithm.
+    #[arg(long, value_name = "sha256|sha512|blake3")]
+    hash_algorithm: Option<HashAlgorithm>,
+
+    // Suppresses the colored verification result. `-v` and `--verify-standalone` still exit with status 1 on failure,
+    // so scripts can rely on the exit code alone.
+    #[arg(short = 'q', long, action = clap::ArgAction::SetTrue)]
+    quiet: bool,
+
+    // `bincode` stores the tree in a compact binary encoding, roughly half the size of the JSON. `-v` must be given
+    // the same format the tree was built with.
+    #[arg(long, value_enum, default_value_t = TreeFormat::Json)]
+    format: TreeFormat,
+
+    // `json` prints the verification result as `{"verified": .., "file_index": .., "root_hash": ..}` for pipelines
+    // (and the result of `--check-file` as `{"matches": .., ..}`); `text` prints a message that is colored only when
+    // stdout is a terminal
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    // Append-only log of published roots: `-u` records the new root in it and `-v` only accepts roots found in it
+    #[arg(long, value_name = "ROOT_LOG_PATH")]
+    root_log: Option<PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -79,12 +157,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Run an asynchronous function within the sync main function using `block_on` and waits for its completion. Blocks until the current thread
     // is complete. It's purpose is to write async code in a sync way.
-    let mut client = rt.block_on(setup_grpc_client())?;
+    // Only upload, download and proof requests call this closure, so the other commands work without a running server.
+    // The error is boxed right away, as `ClientError` carries a whole `tonic::Status`.
+    let connect = || {
+        rt.block_on(setup_grpc_client())
+            .map_err(Box::<dyn std::error::Error>::from)
+    };
+
+    if args.stdin {
+        let mut input = Vec::new();
+        io::stdin().lock().read_to_end(&mut input)?;
 
-    if args.upload {
+        let separator = if args.null { b'\0' } else { b'\n' };
+        let leaves = split_records(&input, separator);
+
+        let hash_algorithm = args.hash_algorithm.unwrap_or_default();
+        let merkle_tree = merkle::MerkleTree::new_with_algorithm(&leaves, hash_algorithm)?;
+        println!("{}", merkle_tree.root_hash());
+    } else if args.root_hash {
+        let files = read_leaves(&args)?;
+
+        let hash_algorithm = args.hash_algorithm.unwrap_or_default();
+        let merkle_tree = merkle::MerkleTree::new_with_algorithm(&files, hash_algorithm)?;
+        let root_hash = merkle_tree.root_hash();
+
+        // Print only the hash on stdout so it can be captured by scripts
+        println!("{}", root_hash);
+
+        if let Some(merkle_root_hash_path) = args.merkle_root_hash_path {
+            write_file(
+                merkle_root_hash_path.parent().unwrap().to_str().unwrap(),
+                merkle_root_hash_path.file_name().unwrap().to_str().unwrap(),
+                &root_hash,
+            )?;
+            eprintln!("Merkle root hash stored at {:?}", merkle_root_hash_path);
+        }
+    } else if args.upload {
         let files_dir = args.files_dir.expect("Files directory required"); // panic if `files_dir` argument is not provided
-        let files = read_files_from_dir(files_dir.to_str().unwrap())?;
-        let response = rt.block_on(upload(&mut client, files))?;
+        let files = read_files_with_names(files_dir.to_str().unwrap())?;
+        let leaf_count = files.len();
+        let mut client = connect()?;
+        let response = rt.block_on(upload_with_names(&mut client, &args.tree_id, files))?;
+
+        if let Some(root_log) = &args.root_log {
+            append_root_log(root_log.to_str().unwrap(), &response.root_hash, leaf_count)?;
+            println!("Merkle root hash recorded in {:?}", root_log);
+        }
 
         // Execute only if `Some(...)` and not None
         if let Some(merkle_root_hash_path) = args.merkle_root_hash_path {
@@ -99,12 +217,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else if args.download {
         let file_index = args.file_index.expect("File index required");
         println!("Requesting file with index: {}", file_index);
-        let response = rt.block_on(download(&mut client, file_index))?;
+        let mut client = connect()?;
+        let response = rt.block_on(download(&mut client, &args.tree_id, file_index))?;
 
         if let Some(output_path) = args.output_path {
             let output_path = if output_path.is_dir() {
-                // Append file name if output path is a directory
-                let file_name = format!("file{}.txt", file_index); // e.g., "file0.txt"
+                // Append file name if output path is a directory. Prefer the original name returned by the server
+                // and fall back to the index based name when none was stored.
+                // Only the final path component is kept so a name like "../x" cannot escape the output directory.
+                let file_name = response
+                    .file_name
+                    .as_deref()
+                    .and_then(|name| Path::new(name).file_name())
+                    .map(|name| name.to_os_string())
+                    .unwrap_or_else(|| format!("file{}.txt", file_index).into()); // e.g., "file0.txt"
                 output_path.join(file_name)
             } else {
                 // Otherwise treat it as a full file path - clone() is necessary because PathBuf implements the Clone trait to create a deep copy of the path.
@@ -115,9 +241,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             fs::write(&output_path, response.file)?;
             println!("File downloaded and stored at {:?}", output_path);
         }
+    } else if args.check_file {
+        let file_dir = args.files_dir.expect("File directory required");
+        let file_idx = args.file_index.expect("File index required");
+
+        let files = read_files_from_dir(file_dir.to_str().unwrap())?;
+        let file = files
+            .get(file_idx as usize)
+            .ok_or_else(|| format!("no file with index {} in {:?}", file_idx, file_dir))?;
+
+        // The server builds its trees in the classic mode; only the algorithm can differ
+        let local_hash = args.hash_algorithm.unwrap_or_default().hash_leaf(
+            HashMode::Classic,
+            file_idx as usize,
+            file,
+        );
+        let mut client = connect()?;
+        let response = rt.block_on(get_leaf_hash(&mut client, &args.tree_id, file_idx))?;
+
+        report_check(
+            file_idx,
+            &local_hash,
+            &response.leaf_hash,
+            &response.root_hash,
+            args.output,
+            args.quiet,
+        );
     } else if args.get_merkle_proofs {
         let file_index = args.file_index.expect("File index required");
-        let response = rt.block_on(get_merkle_proof(&mut client, file_index))?;
+        let merkle_root_hash_path = args
+            .merkle_root_hash_path
+            .expect("Merkle root hash path required");
+        let mut client = connect()?;
+        let response = rt.block_on(get_merkle_proof(&mut client, &args.tree_id, file_index))?;
 
         if let Some(output_path) = args.output_path {
             let output_path = if output_path.is_dir() {
@@ -130,12 +286,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             // .iter() creates an iterator over the references to each proof node in response.proofs i.e. allow you to traverse the elements of a
             // collection one by one, without consuming or altering the original collection.
-            //.collect::<Vec<_>>() consumes the iterator and collects these references into a vector (Vec<&ProofNode>).
-            // Vec<_> indicates that we're collecting the iterator's items into a new vector, where `_` is a placeholder that infers the type automatically
-            // based on the iterator's output. The `&` in front passes a reference to this vector (&Vec<&ProofNode>).
-            let merkle_proofs =
-                convert_to_merkle_tree_nodes(&response.proofs.iter().collect::<Vec<_>>());
-            let proofs_str = serde_json::to_string(&merkle_proofs)?;
+            // Each wire node is converted with the same `to_tree_node` the client library and the server use.
+            let merkle_proofs: Vec<TreeNode> = response
+                .proofs
+                .iter()
+                .map(ProofNode::to_tree_node)
+                .collect();
+
+            // Store the proof as a self-contained `ProofFile` (root hash + sibling steps) so it can later be verified
+            // from the leaf bytes alone instead of dumping the nested tree nodes.
+            let root_hash = fs::read_to_string(merkle_root_hash_path)?
+                .trim()
+                .to_string();
+            let proof_file = ProofFile::from_nodes(
+                root_hash,
+                file_index as usize,
+                &merkle_proofs.iter().collect::<Vec<_>>(),
+            )?;
+            let proofs_str = proof_file.to_json()?;
 
             write_file(
                 output_path.parent().unwrap().to_str().unwrap(),
@@ -146,27 +314,81 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     } else if args.build_merkle_tree {
         // New build Merkle tree functionality
-        let files_dir = args.files_dir.expect("Files directory required");
-        let files = read_files_from_dir(files_dir.to_str().unwrap())?;
-
-        // Build the Merkle tree from files
-        let merkle_tree = merkle::MerkleTree::new(&files)?;
+        let files = read_leaves(&args)?;
 
-        // Serialize the entire Merkle tree to JSON
-        let merkle_tree_json = serde_json::to_string(&merkle_tree)?;
+        // Build the Merkle tree from files with the requested hash algorithm, which is recorded in the serialized tree.
+        // Progress is reported while the leaves are hashed.
+        let merkle_tree = merkle::MerkleTree::builder()
+            .algorithm(args.hash_algorithm.unwrap_or_default())
+            .progress(print_progress)
+            .build(&files)?;
 
-        // Save the Merkle tree to the specified path
         if let Some(merkle_tree_path) = args.merkle_tree_path {
-            write_file(
-                merkle_tree_path.parent().unwrap().to_str().unwrap(),
-                merkle_tree_path.file_name().unwrap().to_str().unwrap(),
-                &merkle_tree_json,
-            )?;
+            if let Some(parent) = merkle_tree_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match args.format {
+                // Stream the JSON to the file without buffering the whole string in memory
+                TreeFormat::Json => {
+                    let file = fs::File::create(&merkle_tree_path)?;
+                    merkle_tree.write_json(BufWriter::new(file))?;
+                }
+                TreeFormat::Bincode => fs::write(&merkle_tree_path, merkle_tree.to_bincode())?,
+            }
             println!("Merkle tree stored at {:?}", merkle_tree_path);
         }
+    } else if args.explain_proof {
+        let file_dir = args.files_dir.expect("File directory required");
+        let file_idx = args.file_index.expect("File index required");
+        let proof_path = args.proof_path.expect("Proof path required");
+
+        let files = read_files_from_dir(file_dir.to_str().unwrap())?;
+        let file = files
+            .get(file_idx as usize)
+            .ok_or_else(|| format!("no file with index {} in {:?}", file_idx, file_dir))?;
+        let proof_file = ProofFile::from_json(&fs::read_to_string(proof_path)?)?;
+
+        // The root stored by `-u` is the one to trust; without it, the proof's own root is shown
+        let expected_root = match args.merkle_root_hash_path {
+            Some(merkle_root_hash_path) => fs::read_to_string(merkle_root_hash_path)?
+                .trim()
+                .to_string(),
+            None => proof_file.root_hash.clone(),
+        };
+
+        explain_proof(&proof_file, file_idx, file, &expected_root);
+    } else if args.verify_standalone {
+        let file_dir = args.files_dir.expect("File directory required");
+        let file_idx = args.file_index.expect("File index required");
+        let proof_path = args.proof_path.expect("Proof path required");
+
+        // The directory holds only the file being checked
+        let mut files = read_files_from_dir(file_dir.to_str().unwrap())?;
+        if files.len() != 1 {
+            return Err(format!(
+                "expected exactly one file in {:?}, found {}",
+                file_dir,
+                files.len()
+            )
+            .into());
+        }
+        let file = files.remove(0);
+
+        // The proof file is self-contained: it carries the root hash, leaf count, hash algorithm and mode, and the
+        // sibling steps
+        let proof_file = ProofFile::from_json(&fs::read_to_string(proof_path)?)?;
+
+        let is_valid = proof_file.leaf_idx == file_idx as usize && proof_file.verify(&file)?;
+
+        report_verification(
+            is_valid,
+            file_idx,
+            &proof_file.root_hash,
+            args.output,
+            args.quiet,
+        );
     } else if args.verify_proof {
         // New verify proof functionality
-        let merkle_tree_path = args.merkle_tree_path.expect("Merkle tree path required");
         let merkle_root_hash_path = args
             .merkle_root_hash_path
             .expect("Merkle root hash path required");
@@ -174,76 +396,280 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let file_idx = args.file_index.expect("File index required");
         let proof_path = args.proof_path.expect("Proof path directory required");
 
-        // Read Merkle tree from file and de-serialize it to get the `merkle::MerkleTree` struct
-        let merkle_tree_json = fs::read_to_string(merkle_tree_path)?;
-        let merkle_tree: merkle::MerkleTree = serde_json::from_str(&merkle_tree_json)?;
-
         // Read Merkle root hash - `trim()` removes any leading or trailing whitespace that might have been included in the file.
         let root_hash = fs::read_to_string(merkle_root_hash_path)?
             .trim()
             .to_string();
 
-        // Read file hash for the file at the provided index
+        // Read the file at the provided index
         let files = read_files_from_dir(file_dir.to_str().unwrap())?;
         let file = &files[file_idx as usize];
-        let file_hash = calc_sha256(file);
 
         // Read Merkle proof from the file and de-serialize to retrive the proof struct
         let proofs_json = fs::read_to_string(proof_path)?;
-        let proofs: Vec<merkle::TreeNode> = serde_json::from_str(&proofs_json)?;
+        let proof_file = ProofFile::from_json(&proofs_json)?;
+
+        // The proof file carries everything needed to verify it, so the tree is optional. When `-P` is given, the tree
+        // is loaded only to cross-check that it was built with the proof's algorithm and has the stored root.
+        let tree_matches = match args.merkle_tree_path {
+            Some(merkle_tree_path) => {
+                // The envelope's format version is checked first, so an incompatible or corrupted file fails with a
+                // clear error
+                let merkle_tree = match args.format {
+                    TreeFormat::Json => {
+                        merkle::MerkleTree::from_json(&fs::read_to_string(merkle_tree_path)?)?
+                    }
+                    TreeFormat::Bincode => {
+                        merkle::MerkleTree::from_bincode(&fs::read(merkle_tree_path)?)?
+                    }
+                };
+                // The stored root is only worth comparing if the tree's internal hashes actually lead to it
+                merkle_tree.validate()?;
+                check_hash_algorithm(
+                    "Merkle tree was built",
+                    merkle_tree.hash_algorithm,
+                    args.hash_algorithm,
+                )?;
+                merkle_tree.hash_algorithm == proof_file.hash_algorithm
+                    && merkle_tree.root_hash() == root_hash
+            }
+            None => {
+                check_hash_algorithm(
+                    "Proof was generated",
+                    proof_file.hash_algorithm,
+                    args.hash_algorithm,
+                )?;
+                true
+            }
+        };
+
+        // The proof must attest to the requested index and commit to the same root the client stored. Only then is the
+        // proof folded from the file bytes up to the root.
+        let is_valid = tree_matches
+            && proof_file.leaf_idx == file_idx as usize
+            && proof_file.root_hash == root_hash
+            && proof_file.verify(file)?;
+
+        // When a root log is given, the root must also have been committed to it beforehand
+        let is_valid = match &args.root_log {
+            Some(root_log) if is_valid => {
+                match verify_root_in_log(root_log.to_str().unwrap(), &root_hash)? {
+                    Some((timestamp, leaf_count)) => {
+                        eprintln!(
+                            "Root was recorded at {} (unix time) over {} leaves",
+                            timestamp, leaf_count
+                        );
+                        true
+                    }
+                    None => {
+                        eprintln!("Root was not found in {:?}", root_log);
+                        false
+                    }
+                }
+            }
+            _ => is_valid,
+        };
+
+        report_verification(is_valid, file_idx, &root_hash, args.output, args.quiet);
+    }
 
-        // Call the verify_merkle_proof function
-        // Conv. the proofs into a vector of references to TreeNode structs, which is needed for the verification.
-        let is_valid = merkle_tree.verify_merkle_proof(
-            &root_hash,
-            &file_hash,
-            file_idx as usize,
-            &proofs.iter().collect::<Vec<_>>(),
-        )?;
+    Ok(())
+}
 
-        if is_valid {
-            println!("\x1b[32mProof verified successfully.\x1b[0m");
-        } else {
-            println!("\x1b[31mFailed to verify proof.\x1b[0m");
+// Leaves of the tree built by `-r` and `-b`: the files of the `--tar` archive when given, otherwise those of `-f`,
+// gunzipped with `--gunzip`
+fn read_leaves(args: &Args) -> io::Result<Vec<Vec<u8>>> {
+    if let Some(tar) = &args.tar {
+        return read_files_from_tar(tar.to_str().unwrap());
+    }
+
+    let files_dir = args.files_dir.as_ref().expect("Files directory required");
+    if args.gunzip {
+        read_files_from_dir_gz(files_dir.to_str().unwrap())
+    } else {
+        read_files_from_dir(files_dir.to_str().unwrap())
+    }
+}
+
+// The tree and the proof file record the algorithm they were built with; an explicit `--hash-algorithm` must agree
+fn check_hash_algorithm(
+    what: &str,
+    hash_algorithm: HashAlgorithm,
+    flag: Option<HashAlgorithm>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match flag {
+        Some(expected) if expected != hash_algorithm => Err(format!(
+            "{} with {} but --hash-algorithm is {}",
+            what, hash_algorithm, expected
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+// Prints the verification result unless `quiet` is set: as a JSON object in `json` mode, or as a message that is only
+// colored when stdout is a terminal in `text` mode. A failed verification exits the process with status 1 so that
+// scripts and CI can detect it; success returns and `main` exits with 0.
+fn report_verification(
+    is_valid: bool,
+    file_index: i64,
+    root_hash: &str,
+    output: OutputFormat,
+    quiet: bool,
+) {
+    if !quiet {
+        match output {
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "verified": is_valid,
+                    "file_index": file_index,
+                    "root_hash": root_hash,
+                })
+            ),
+            OutputFormat::Text => {
+                let (color, message) = if is_valid {
+                    ("\x1b[32m", "Proof verified successfully.")
+                } else {
+                    ("\x1b[31m", "Failed to verify proof.")
+                };
+                // Escape codes would garble logs when the output is piped or redirected
+                if io::stdout().is_terminal() {
+                    println!("{}{}\x1b[0m", color, message);
+                } else {
+                    println!("{}", message);
+                }
+            }
         }
     }
 
-    Ok(())
+    if !is_valid {
+        std::process::exit(1);
+    }
 }
 
-// iter(): Borrows each element (&T), so the original collection remains unchanged.
-fn convert_to_merkle_tree_nodes(nodes: &[&RustleTreeNode]) -> Vec<TreeNode> {
-    nodes
-        .iter()
-        .map(|node| TreeNode {
-            hash: node.hash.clone(),
-            left_idx: node.left_idx as usize,
-            right_idx: node.right_idx as usize,
-            left: node
-                .left
-                .as_ref()
-                .map(|left_node| Box::new(convert_to_merkle_tree_node(left_node))),
-            right: node
-                .right
-                .as_ref()
-                .map(|right_node| Box::new(convert_to_merkle_tree_node(right_node))),
-        })
-        .collect()
+// Same as `report_verification` for `--check-file`: prints whether the local file hashes to the leaf hash stored on
+// the server, and exits with status 1 if it does not
+fn report_check(
+    file_index: i64,
+    local_hash: &str,
+    server_hash: &str,
+    root_hash: &str,
+    output: OutputFormat,
+    quiet: bool,
+) {
+    let matches = local_hash == server_hash;
+    if !quiet {
+        match output {
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "matches": matches,
+                    "file_index": file_index,
+                    "local_hash": local_hash,
+                    "server_hash": server_hash,
+                    "root_hash": root_hash,
+                })
+            ),
+            OutputFormat::Text => {
+                let (color, message) = if matches {
+                    ("\x1b[32m", "Local file matches the server's leaf hash.")
+                } else {
+                    (
+                        "\x1b[31m",
+                        "Local file does not match the server's leaf hash.",
+                    )
+                };
+                if io::stdout().is_terminal() {
+                    println!("{}{}\x1b[0m", color, message);
+                } else {
+                    println!("{}", message);
+                }
+                if !matches {
+                    println!("  local:  {}\n  server: {}", local_hash, server_hash);
+                }
+            }
+        }
+    }
+
+    if !matches {
+        std::process::exit(1);
+    }
+}
+
+// Prints the fold of `proof_file` over `file` one level at a time, so a failing proof shows the level at which it
+// diverges from the tree
+fn explain_proof(proof_file: &ProofFile, file_idx: i64, file: &[u8], expected_root: &str) {
+    let hashes = proof_file.running_hashes(file);
+
+    println!(
+        "proof for leaf {} of {} ({}, {:?})",
+        proof_file.leaf_idx, proof_file.leaf_count, proof_file.hash_algorithm, proof_file.hash_mode
+    );
+    if proof_file.leaf_idx != file_idx as usize {
+        println!(
+            "warning: the proof is for leaf {}, not for file index {}",
+            proof_file.leaf_idx, file_idx
+        );
+    }
+    println!("leaf hash:     {}", hashes[0]);
+    for (level, (step, running)) in proof_file.steps.iter().zip(&hashes[1..]).enumerate() {
+        println!("step {}:", level + 1);
+        println!("  sibling:     {} ({:?})", step.hash, step.side);
+        println!("  running:     {}", running);
+    }
+
+    let computed_root = &hashes[hashes.len() - 1];
+    println!("computed root: {}", computed_root);
+    println!("expected root: {}", expected_root);
+    println!(
+        "{}",
+        if computed_root == expected_root {
+            "roots match"
+        } else {
+            "roots differ"
+        }
+    );
 }
 
-// Recursive conversion of node and its children
-fn convert_to_merkle_tree_node(node: &RustleTreeNode) -> TreeNode {
-    TreeNode {
-        hash: node.hash.clone(),
-        left_idx: node.left_idx as usize,
-        right_idx: node.right_idx as usize,
-        left: node
-            .left
-            .as_ref()
-            .map(|left_node| Box::new(convert_to_merkle_tree_node(left_node))),
-        right: node
-            .right
-            .as_ref()
-            .map(|right_node| Box::new(convert_to_merkle_tree_node(right_node))),
+// Renders a single-line progress bar on stderr, redrawn in place with `\r`. It is only redrawn when the
+// percentage changes so that huge directories do not flood the terminal.
+fn print_progress(leaves_hashed: usize, total: usize) {
+    const WIDTH: usize = 40;
+
+    let percent = leaves_hashed * 100 / total;
+    if leaves_hashed != total && percent == (leaves_hashed - 1) * 100 / total {
+        return;
+    }
+
+    let filled = leaves_hashed * WIDTH / total;
+    eprint!(
+        "\rHashing leaves [{}{}] {}/{}",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        leaves_hashed,
+        total
+    );
+    if leaves_hashed == total {
+        eprintln!();
     }
 }
+
+// Splits `data` into records on `separator`. A trailing separator does not start an extra empty record, so both
+// "a\nb" and "a\nb\n" give two leaves; a trailing '\r' is dropped from newline separated records.
+fn split_records(data: &[u8], separator: u8) -> Vec<Vec<u8>> {
+    let data = data.strip_suffix(&[separator]).unwrap_or(data);
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    data.split(|&byte| byte == separator)
+        .map(|record| {
+            if separator == b'\n' {
+                record.strip_suffix(b"\r").unwrap_or(record).to_vec()
+            } else {
+                record.to_vec()
+            }
+        })
+        .collect()
+}
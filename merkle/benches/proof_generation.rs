@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use merkle::{MerkleTree, TreeNode};
+
+// Compares proof generation by tree search against the flattened hash cache used by `generate_merkle_proof` on a 10k
+// leaf tree. Run with `cargo bench -p merkle`.
+fn proof_generation(c: &mut Criterion) {
+    let files: Vec<Vec<u8>> = (0..10_000u32).map(|i| i.to_le_bytes().to_vec()).collect();
+    let merkle_tree = MerkleTree::new(&files).unwrap();
+    let root = merkle_tree.root().unwrap();
+
+    // A spread of leaves across the tree, so neither side of the root is favoured
+    let indices: Vec<usize> = (0..files.len()).step_by(997).collect();
+
+    let mut group = c.benchmark_group("proof_generation_10k");
+    group.sample_size(10);
+
+    group.bench_function("tree_search", |b| {
+        b.iter(|| {
+            for &idx in &indices {
+                black_box(search_proof(root, idx));
+            }
+        })
+    });
+
+    group.bench_function("hash_cache", |b| {
+        b.iter(|| {
+            for &idx in &indices {
+                black_box(merkle_tree.generate_merkle_proof(idx).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// The search the cache replaced: every level looks the parent of the current node up with a full pre-order walk
+fn search_proof(root: &TreeNode, leaf_idx: usize) -> Vec<&TreeNode> {
+    let mut node = root
+        .iter()
+        .find(|node| node.left.is_none() && node.left_idx == leaf_idx)
+        .unwrap();
+    let mut proof = Vec::new();
+
+    while !std::ptr::eq(node, root) {
+        let parent = root
+            .iter()
+            .find(|parent| {
+                [parent.left.as_deref(), parent.right.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|child| child == node)
+            })
+            .unwrap();
+        let sibling = [parent.left.as_deref(), parent.right.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|child| *child != node)
+            .unwrap();
+        proof.push(sibling);
+        node = parent;
+    }
+
+    proof
+}
+
+criterion_group!(benches, proof_generation);
+criterion_main!(benches);
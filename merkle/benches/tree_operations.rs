@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle::MerkleTree;
+use util::calc_sha256;
+
+// Baseline timings for the three core operations at growing leaf counts, so a regression (or the suspected quadratic
+// proof generation) shows up as a change in slope. Run with `cargo bench -p merkle --bench tree_operations`.
+const LEAF_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+
+fn files(n: usize) -> Vec<Vec<u8>> {
+    (0..n as u32).map(|i| i.to_le_bytes().to_vec()).collect()
+}
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    group.sample_size(10);
+
+    for n in LEAF_COUNTS {
+        let files = files(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &files, |b, files| {
+            b.iter(|| black_box(MerkleTree::new(files).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+fn generate_proof(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_merkle_proof");
+
+    for n in LEAF_COUNTS {
+        let merkle_tree = MerkleTree::new(&files(n)).unwrap();
+        // The last leaf sits on the deepest path when the leaf count is not a power of two
+        let idx = n - 1;
+        group.bench_with_input(BenchmarkId::from_parameter(n), &idx, |b, &idx| {
+            b.iter(|| black_box(merkle_tree.generate_merkle_proof(idx).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+fn verify_proof(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify_merkle_proof");
+
+    for n in LEAF_COUNTS {
+        let files = files(n);
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+        let idx = n - 1;
+        let leaf_hash = calc_sha256(&files[idx]);
+        let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &idx, |b, &idx| {
+            b.iter(|| {
+                black_box(
+                    merkle_tree
+                        .verify_merkle_proof(&root_hash, &leaf_hash, idx, &proofs)
+                        .unwrap(),
+                )
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, build, generate_proof, verify_proof);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+use crate::{MerkleErrorKind, MerkleTree, MerkleTreeError, TreeNode};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Proof metadata of a single leaf in a `BatchProof`: the positions (into `BatchProof::nodes`) of its proof nodes,
+// ordered from the leaf up to the root exactly like `generate_merkle_proof`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BatchProofEntry {
+    pub leaf_idx: usize,
+    pub node_positions: Vec<usize>,
+}
+
+// BatchProof proves several leaves of the same tree at once. Leaves close to each other share most of their proof
+// (e.g. the sibling of a common ancestor), so every distinct proof node is stored only once in `nodes` and each entry
+// refers to it by position. The nodes carry no children, only their hash and index range.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BatchProof {
+    pub nodes: Vec<TreeNode>,
+    pub entries: Vec<BatchProofEntry>,
+}
+
+impl BatchProof {
+    // Reconstructs the individual proof of `leaf_idx`, ready to be passed to `verify_merkle_proof` or `ProofFile::from_nodes`.
+    // Returns None if the leaf is not part of the batch or an entry points outside `nodes`.
+    pub fn proof_for(&self, leaf_idx: usize) -> Option<Vec<&TreeNode>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.leaf_idx == leaf_idx)?;
+        entry
+            .node_positions
+            .iter()
+            .map(|&pos| self.nodes.get(pos))
+            .collect()
+    }
+}
+
+impl MerkleTree {
+    // Generates a deduplicated proof for all `leaf_indices`. Proof nodes are identified by their index range, which is
+    // unique within a tree, so a node shared by several leaves is included only once.
+    pub fn generate_batch_proof(
+        &self,
+        leaf_indices: &[usize],
+    ) -> Result<BatchProof, MerkleTreeError> {
+        if leaf_indices.is_empty() {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::EmptyInput,
+                "empty leaf index list",
+            ));
+        }
+
+        let mut nodes: Vec<TreeNode> = Vec::new();
+        // Maps a node's `(left_idx, right_idx)` to its position in `nodes`
+        let mut positions: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut entries = Vec::with_capacity(leaf_indices.len());
+
+        for &leaf_idx in leaf_indices {
+            let proofs = self.generate_merkle_proof(leaf_idx)?;
+
+            let node_positions = proofs
+                .into_iter()
+                .map(|proof| {
+                    // `entry` inserts the node only the first time its range is seen
+                    *positions
+                        .entry((proof.left_idx, proof.right_idx))
+                        .or_insert_with(|| {
+                            nodes.push(proof);
+                            nodes.len() - 1
+                        })
+                })
+                .collect();
+
+            entries.push(BatchProofEntry {
+                leaf_idx,
+                node_positions,
+            });
+        }
+
+        Ok(BatchProof { nodes, entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::calc_sha256;
+
+    #[test]
+    fn batch_proof_shares_nodes() {
+        let files: Vec<Vec<u8>> = (0..8u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+        let indices = [0, 1, 2, 3];
+
+        let batch = merkle_tree.generate_batch_proof(&indices).unwrap();
+
+        // Four independent proofs of a balanced 8-leaf tree carry 3 nodes each
+        let independent: usize = indices
+            .iter()
+            .map(|&idx| merkle_tree.generate_merkle_proof(idx).unwrap().len())
+            .sum();
+        assert_eq!(independent, 12);
+        assert!(batch.nodes.len() < independent);
+
+        for idx in indices {
+            let proofs = batch.proof_for(idx).unwrap();
+            assert!(merkle_tree
+                .verify_merkle_proof(&root_hash, &calc_sha256(&files[idx]), idx, &proofs)
+                .unwrap());
+        }
+
+        assert!(batch.proof_for(4).is_none());
+        assert!(merkle_tree.generate_batch_proof(&[]).is_err());
+        assert!(merkle_tree.generate_batch_proof(&[0, 8]).is_err());
+    }
+}
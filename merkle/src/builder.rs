@@ -0,0 +1,145 @@
+use crate::{Encoding, HashAlgorithm, HashMode, MerkleTree, MerkleTreeError, OrderingMode};
+
+// MerkleTreeBuilder collects the optional build settings in one place instead of one `new_with_*` constructor per
+// combination. Every setting starts at the behavior of `MerkleTree::new`, so `MerkleTree::builder().build(files)`
+// builds exactly the same tree as `MerkleTree::new(files)`.
+// The lifetime `'a` lets the progress callback borrow from the caller (e.g. push into a local `Vec`).
+#[derive(Default)]
+pub struct MerkleTreeBuilder<'a> {
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    encoding: Encoding,
+    strict: bool,
+    progress: Option<Box<dyn FnMut(usize, usize) + 'a>>,
+}
+
+impl MerkleTree {
+    // Starts a builder with the default settings: SHA-256, classic hashing, positional ordering, hex output, duplicates
+    // allowed, no progress reporting
+    pub fn builder<'a>() -> MerkleTreeBuilder<'a> {
+        MerkleTreeBuilder::default()
+    }
+}
+
+impl<'a> MerkleTreeBuilder<'a> {
+    // Hash function used for the leaves and the internal nodes
+    pub fn algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    // Leaf and node hashing rule, e.g. `HashMode::Rfc6962` for domain separation
+    pub fn hash_mode(mut self, hash_mode: HashMode) -> Self {
+        self.hash_mode = hash_mode;
+        self
+    }
+
+    // Concatenation order of child hashes, e.g. `OrderingMode::SortedPair` for position-free proofs
+    pub fn ordering(mut self, ordering: OrderingMode) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    // Encoding of the digests the tree hands out (see `Encoding`). Node hashes and roots are the same in every encoding.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    // When enabled, `build` fails with a `DuplicateLeaf` error if any two leaves hash to the same value
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Calls `progress(leaves_hashed, total)` each time a leaf hash has been computed
+    pub fn progress<F: FnMut(usize, usize) + 'a>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    // Builds the tree over `files` with the configured settings. Consumes the builder, since the progress callback is
+    // moved into the build.
+    pub fn build(self, files: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+        let total = files.len();
+        let mut leaves_hashed = 0;
+        let mut progress = self.progress;
+
+        let mut merkle_tree = MerkleTree::build(
+            files,
+            self.hash_algorithm,
+            self.hash_mode,
+            self.ordering,
+            &mut || {
+                leaves_hashed += 1;
+                if let Some(progress) = progress.as_mut() {
+                    progress(leaves_hashed, total);
+                }
+            },
+        )?;
+
+        merkle_tree.encoding = self.encoding;
+
+        if self.strict {
+            merkle_tree.check_distinct_leaves()?;
+        }
+
+        Ok(merkle_tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleErrorKind;
+
+    #[test]
+    fn builder_combines_settings() {
+        let files: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i]).collect();
+
+        // The defaults match `new`
+        assert_eq!(
+            MerkleTree::builder().build(&files).unwrap(),
+            MerkleTree::new(&files).unwrap()
+        );
+
+        // Algorithm and progress together, which no single constructor offers
+        let mut reports = Vec::new();
+        let merkle_tree = MerkleTree::builder()
+            .algorithm(HashAlgorithm::Blake3)
+            .progress(|hashed, total| reports.push((hashed, total)))
+            .strict(true)
+            .build(&files)
+            .unwrap();
+        assert_eq!(
+            merkle_tree,
+            MerkleTree::new_with_algorithm(&files, HashAlgorithm::Blake3).unwrap()
+        );
+        assert_eq!(reports.len(), files.len());
+        assert_eq!(reports.last(), Some(&(6, 6)));
+
+        let duplicates = vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()];
+        assert!(MerkleTree::builder().build(&duplicates).is_ok());
+        let err = MerkleTree::builder()
+            .strict(true)
+            .build(&duplicates)
+            .unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::DuplicateLeaf);
+
+        // The encoding changes the output, not the hashes
+        let hex_tree = MerkleTree::new(&files).unwrap();
+        let base64_tree = MerkleTree::builder()
+            .encoding(Encoding::Base64)
+            .build(&files)
+            .unwrap();
+        assert_eq!(base64_tree.root_hash(), hex_tree.root_hash());
+        assert_eq!(hex_tree.encoded_root_hash().unwrap(), hex_tree.root_hash());
+        let digest = util::hex_to_bytes(&hex_tree.root_hash()).unwrap();
+        assert_eq!(base64_tree.encoded_root_hash().unwrap().len(), 44);
+        assert_eq!(
+            base64_tree.encoded_root_hash().unwrap(),
+            Encoding::Base64.encode(&digest)
+        );
+    }
+}
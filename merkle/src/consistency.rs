@@ -0,0 +1,371 @@
+use crate::{
+    checked_idx, HashAlgorithm, HashMode, MerkleErrorKind, MerkleTree, MerkleTreeError,
+    OrderingMode, TreeNode,
+};
+use std::collections::HashMap;
+
+// A consistency proof shows that the tree over the first `old_size` leaves is a prefix of the current tree, in the
+// spirit of RFC 6962. Trees here are split at the midpoint rather than at a power of two, so the old tree is generally
+// not a subtree of the new one. The proof therefore consists of:
+// 1. the hashes of the maximal nodes of the old tree that are also nodes of the new tree. They cover the old leaves,
+//    and since a node's shape only depends on its size, such a node has the same hash in both trees.
+// 2. the hashes of the maximal nodes of the new tree that only cover appended leaves.
+// The verifier derives both lists of ranges from the two sizes alone and recomputes both roots from the same hashes.
+
+impl MerkleTree {
+    // Returns the node hashes proving that the tree built from the first `old_size` leaves is a prefix of this tree,
+    // in the order expected by `verify_consistency`.
+    pub fn consistency_proof(&self, old_size: usize) -> Result<Vec<String>, MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "merkle tree is empty",
+                ))
+            }
+        };
+
+        let new_size = checked_idx(root.right_idx.checked_add(1), MerkleErrorKind::Internal)?;
+        check_sizes(old_size, new_size)?;
+
+        consistency_ranges(old_size, new_size)
+            .into_iter()
+            .map(|(left, right)| {
+                find_node(root, left, right)
+                    .map(|node| node.hash.clone())
+                    .ok_or_else(|| {
+                        MerkleTreeError::new(MerkleErrorKind::Internal, "tree shape mismatch")
+                    })
+            })
+            .collect()
+    }
+}
+
+// Verifies a consistency proof between the SHA-256 roots of a tree with `old_size` leaves and one with `new_size`
+// leaves. Only the two roots and sizes are needed, not the trees.
+pub fn verify_consistency(
+    old_root: &str,
+    new_root: &str,
+    old_size: usize,
+    new_size: usize,
+    proof: &[String],
+) -> Result<bool, MerkleTreeError> {
+    verify_consistency_with_algorithm(
+        HashAlgorithm::Sha256,
+        old_root,
+        new_root,
+        old_size,
+        new_size,
+        proof,
+    )
+}
+
+// Same as `verify_consistency` for trees built with another hash algorithm
+pub fn verify_consistency_with_algorithm(
+    hash_algorithm: HashAlgorithm,
+    old_root: &str,
+    new_root: &str,
+    old_size: usize,
+    new_size: usize,
+    proof: &[String],
+) -> Result<bool, MerkleTreeError> {
+    verify_consistency_with_mode(
+        hash_algorithm,
+        HashMode::Classic,
+        OrderingMode::Positional,
+        old_root,
+        new_root,
+        old_size,
+        new_size,
+        proof,
+    )
+}
+
+// Same as `verify_consistency_with_algorithm` for trees whose nodes are combined with `hash_mode` and `ordering`, e.g.
+// RFC 6962 or sorted-pair trees
+#[allow(clippy::too_many_arguments)]
+pub fn verify_consistency_with_mode(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    old_root: &str,
+    new_root: &str,
+    old_size: usize,
+    new_size: usize,
+    proof: &[String],
+) -> Result<bool, MerkleTreeError> {
+    check_sizes(old_size, new_size)?;
+
+    let ranges = consistency_ranges(old_size, new_size);
+    if proof.len() != ranges.len() {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "unexpected proof length",
+        ));
+    }
+
+    // Both roots are folded from the same proof hashes, each following the midpoint split of its own size
+    let known: HashMap<(usize, usize), &str> = ranges
+        .into_iter()
+        .zip(proof.iter().map(String::as_str))
+        .collect();
+
+    let fold = |size: usize| {
+        fold_range(hash_algorithm, hash_mode, ordering, &known, 0, size - 1).ok_or_else(|| {
+            MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "proof does not cover the tree",
+            )
+        })
+    };
+
+    Ok(fold(old_size)? == old_root && fold(new_size)? == new_root)
+}
+
+fn check_sizes(old_size: usize, new_size: usize) -> Result<(), MerkleTreeError> {
+    if old_size == 0 {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::EmptyInput,
+            "old tree size must be at least 1",
+        ));
+    }
+    if old_size > new_size {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::IndexOutOfBounds,
+            "old tree size exceeds the current tree size",
+        ));
+    }
+    Ok(())
+}
+
+// Ranges whose hashes make up the consistency proof: first the shared nodes covering the old leaves, then the nodes
+// covering only appended leaves, each from left to right.
+fn consistency_ranges(old_size: usize, new_size: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    shared_ranges(0, old_size - 1, new_size, &mut ranges);
+    appended_ranges(0, new_size - 1, old_size, &mut ranges);
+    ranges
+}
+
+// Walks the old tree top-down and keeps the first nodes that also exist in the new tree. Leaves exist in both trees,
+// so the walk always stops.
+fn shared_ranges(left: usize, right: usize, new_size: usize, ranges: &mut Vec<(usize, usize)>) {
+    if is_node(left, right, new_size) {
+        ranges.push((left, right));
+        return;
+    }
+    let mid = left + (right - left) / 2;
+    shared_ranges(left, mid, new_size, ranges);
+    shared_ranges(mid + 1, right, new_size, ranges);
+}
+
+// Walks the new tree top-down and keeps the first nodes made of appended leaves only. Nodes made of old leaves are
+// skipped: they are built from the shared nodes.
+fn appended_ranges(left: usize, right: usize, old_size: usize, ranges: &mut Vec<(usize, usize)>) {
+    if left >= old_size {
+        ranges.push((left, right));
+        return;
+    }
+    if right < old_size {
+        return;
+    }
+    let mid = left + (right - left) / 2;
+    appended_ranges(left, mid, old_size, ranges);
+    appended_ranges(mid + 1, right, old_size, ranges);
+}
+
+// Whether `[left, right]` is the index range of a node in the tree with `size` leaves
+fn is_node(left: usize, right: usize, size: usize) -> bool {
+    let (mut lo, mut hi) = (0, size - 1);
+    loop {
+        if (lo, hi) == (left, right) {
+            return true;
+        }
+        if lo == hi || left < lo || right > hi {
+            return false;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if right <= mid {
+            hi = mid;
+        } else if left > mid {
+            lo = mid + 1;
+        } else {
+            return false;
+        }
+    }
+}
+
+// Hash of the node over `[left, right]`, taken from the proof or combined from its children
+fn fold_range(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    known: &HashMap<(usize, usize), &str>,
+    left: usize,
+    right: usize,
+) -> Option<String> {
+    if let Some(hash) = known.get(&(left, right)) {
+        return Some(hash.to_string());
+    }
+    if left == right {
+        return None;
+    }
+    let mid = left + (right - left) / 2;
+    let left_hash = fold_range(hash_algorithm, hash_mode, ordering, known, left, mid)?;
+    let right_hash = fold_range(hash_algorithm, hash_mode, ordering, known, mid + 1, right)?;
+    Some(hash_algorithm.hash_pair(hash_mode, ordering, &left_hash, &right_hash))
+}
+
+// Descends from `root` to the node spanning exactly `[left, right]`
+fn find_node(root: &TreeNode, left: usize, right: usize) -> Option<&TreeNode> {
+    let mut node = root;
+    while (node.left_idx, node.right_idx) != (left, right) {
+        node = [node.left.as_deref(), node.right.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|child| child.left_idx <= left && right <= child.right_idx)?;
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consistency_proofs_between_all_prefixes() {
+        let files: Vec<Vec<u8>> = (0..13u8).map(|i| vec![i]).collect();
+        let roots: Vec<String> = (1..=files.len())
+            .map(|size| MerkleTree::new(&files[..size]).unwrap().root_hash())
+            .collect();
+
+        for new_size in 1..=files.len() {
+            let new_tree = MerkleTree::new(&files[..new_size]).unwrap();
+            for old_size in 1..=new_size {
+                let proof = new_tree.consistency_proof(old_size).unwrap();
+                let (old_root, new_root) = (&roots[old_size - 1], &roots[new_size - 1]);
+                assert!(
+                    verify_consistency(old_root, new_root, old_size, new_size, &proof).unwrap()
+                );
+
+                // A root that is not a prefix of the new tree fails
+                if old_size < new_size {
+                    assert!(!verify_consistency(
+                        &roots[old_size],
+                        new_root,
+                        old_size,
+                        new_size,
+                        &proof
+                    )
+                    .unwrap());
+                }
+            }
+        }
+
+        let tree = MerkleTree::new(&files).unwrap();
+        assert!(tree.consistency_proof(0).is_err());
+        assert!(tree.consistency_proof(files.len() + 1).is_err());
+    }
+
+    #[test]
+    fn consistency_proofs_follow_the_hash_mode() {
+        let files: Vec<Vec<u8>> = (0..9u8).map(|i| vec![i]).collect();
+        let old_tree = MerkleTree::new_rfc6962(&files[..5]).unwrap();
+        let new_tree = MerkleTree::new_rfc6962(&files).unwrap();
+        let proof = new_tree.consistency_proof(5).unwrap();
+
+        let verify = |hash_mode| {
+            verify_consistency_with_mode(
+                HashAlgorithm::Sha256,
+                hash_mode,
+                OrderingMode::Positional,
+                &old_tree.root_hash(),
+                &new_tree.root_hash(),
+                5,
+                files.len(),
+                &proof,
+            )
+            .unwrap()
+        };
+        assert!(verify(HashMode::Rfc6962));
+        // The classic rule does not rebuild RFC 6962 roots
+        assert!(!verify(HashMode::Classic));
+    }
+
+    #[test]
+    fn consistency_proofs_follow_the_ordering() {
+        let files: Vec<Vec<u8>> = (0..9u8).map(|i| vec![i]).collect();
+        let build = |files: &[Vec<u8>]| {
+            MerkleTree::builder()
+                .ordering(OrderingMode::SortedPair)
+                .build(files)
+                .unwrap()
+        };
+        let new_tree = build(&files);
+
+        let mut positional_failures = 0;
+        for old_size in 1..files.len() {
+            let old_root = build(&files[..old_size]).root_hash();
+            let proof = new_tree.consistency_proof(old_size).unwrap();
+            let verify = |ordering| {
+                verify_consistency_with_mode(
+                    HashAlgorithm::Sha256,
+                    HashMode::Classic,
+                    ordering,
+                    &old_root,
+                    &new_tree.root_hash(),
+                    old_size,
+                    files.len(),
+                    &proof,
+                )
+                .unwrap()
+            };
+            assert!(verify(OrderingMode::SortedPair));
+            if !verify(OrderingMode::Positional) {
+                positional_failures += 1;
+            }
+        }
+        // The positional rule only rebuilds a sorted root when every pair happens to be in order already
+        assert!(positional_failures > 0);
+    }
+
+    #[test]
+    fn consistency_proofs_of_indexed_trees() {
+        // Appending keeps the index of every old leaf, so the shared nodes of an indexed tree match as well
+        let files: Vec<Vec<u8>> = (0..9u8).map(|i| vec![i]).collect();
+        let new_tree = MerkleTree::new_indexed(&files).unwrap();
+        for old_size in 1..=files.len() {
+            let old_root = MerkleTree::new_indexed(&files[..old_size])
+                .unwrap()
+                .root_hash();
+            let proof = new_tree.consistency_proof(old_size).unwrap();
+            assert!(verify_consistency_with_mode(
+                HashAlgorithm::Sha256,
+                HashMode::Indexed,
+                OrderingMode::Positional,
+                &old_root,
+                &new_tree.root_hash(),
+                old_size,
+                files.len(),
+                &proof,
+            )
+            .unwrap());
+        }
+
+        // The old root of a plain tree over the same files is not a prefix of the indexed tree
+        let plain_root = MerkleTree::new(&files[..4]).unwrap().root_hash();
+        let proof = new_tree.consistency_proof(4).unwrap();
+        assert!(!verify_consistency_with_mode(
+            HashAlgorithm::Sha256,
+            HashMode::Indexed,
+            OrderingMode::Positional,
+            &plain_root,
+            &new_tree.root_hash(),
+            4,
+            files.len(),
+            &proof,
+        )
+        .unwrap());
+    }
+}
@@ -0,0 +1,194 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use util::{calc_blake3, calc_sha256, calc_sha512};
+
+// HashAlgorithm selects the function used to hash leaves and combine child hashes. It is stored alongside a serialized
+// tree or proof, so that whoever verifies it later uses the same function that built it.
+// `rename_all = "lowercase"` serializes the variants as "sha256", "sha512" and "blake3".
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+// HashMode decides how leaves and internal nodes are hashed.
+// - `Classic` hashes the leaf content as is and an internal node as the plain concatenation of its child hashes. A
+//   forger can then present the two child hashes of an internal node as the content of a leaf (second preimage).
+// - `Rfc6962` adds domain separation as in Certificate Transparency: leaf content is prefixed with a 0x00 byte and
+//   the concatenated child hashes with a 0x01 byte, so a leaf hash can never be mistaken for an internal node hash.
+// - `Indexed` hashes a leaf as its index (8 bytes, big endian) followed by its content, and internal nodes as in
+//   `Classic`. Every leaf hash is then bound to its position, so swapping two files changes the root even where the
+//   plain leaf hashes would happen to fold to the same one.
+// The modes produce different roots, so proofs from one mode do not verify against a tree built in another.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HashMode {
+    #[default]
+    Classic,
+    Rfc6962,
+    Indexed,
+}
+
+// OrderingMode decides in which order two child hashes are concatenated.
+// - `Positional` puts the left child first, so a proof must record on which side each sibling sits.
+// - `SortedPair` puts the lexicographically smaller hash first at every level, as in OpenZeppelin's `MerkleProof`.
+//   The sibling sides then no longer matter for the root, which lets verifiers that only see the sibling hashes
+//   (e.g. smart contracts) check a proof without knowing the leaf position.
+// The two modes produce different roots, so proofs and trees record the mode and verification uses the same one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OrderingMode {
+    #[default]
+    Positional,
+    SortedPair,
+}
+
+impl OrderingMode {
+    // Returns the pair in the order it is concatenated in
+    pub fn order<'a>(&self, left: &'a str, right: &'a str) -> (&'a str, &'a str) {
+        match self {
+            OrderingMode::SortedPair if right < left => (right, left),
+            _ => (left, right),
+        }
+    }
+}
+
+const RFC6962_LEAF_PREFIX: u8 = 0x00;
+const RFC6962_NODE_PREFIX: u8 = 0x01;
+
+impl HashAlgorithm {
+    // Hashes `data` and returns the hex encoded digest
+    pub fn hash(&self, data: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Sha256 => calc_sha256(data),
+            HashAlgorithm::Sha512 => calc_sha512(data),
+            HashAlgorithm::Blake3 => calc_blake3(data),
+        }
+    }
+
+    // Hashes the content of the leaf at `leaf_idx`. Only `HashMode::Indexed` mixes the index into the hash.
+    pub fn hash_leaf(&self, hash_mode: HashMode, leaf_idx: usize, data: &[u8]) -> String {
+        match hash_mode {
+            HashMode::Classic => self.hash(data),
+            HashMode::Rfc6962 => self.hash(&[&[RFC6962_LEAF_PREFIX], data].concat()),
+            HashMode::Indexed => self.hash(&[&(leaf_idx as u64).to_be_bytes()[..], data].concat()),
+        }
+    }
+
+    // Hashes an internal node from the hashes of its left and right children
+    pub fn hash_children(&self, hash_mode: HashMode, left: &str, right: &str) -> String {
+        match hash_mode {
+            HashMode::Classic | HashMode::Indexed => {
+                self.hash(&[left.as_bytes(), right.as_bytes()].concat())
+            }
+            HashMode::Rfc6962 => {
+                self.hash(&[&[RFC6962_NODE_PREFIX], left.as_bytes(), right.as_bytes()].concat())
+            }
+        }
+    }
+
+    // Same as `hash_children`, after putting the pair in the order given by `ordering`
+    pub fn hash_pair(
+        &self,
+        hash_mode: HashMode,
+        ordering: OrderingMode,
+        left: &str,
+        right: &str,
+    ) -> String {
+        let (left, right) = ordering.order(left, right);
+        self.hash_children(hash_mode, left, right)
+    }
+
+    // Length in bytes of a raw (not hex encoded) digest
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Encoding selects how a tree hands out its digests, e.g. through `MerkleTree::encoded_root_hash`. The node hashes
+// themselves stay hex, since internal nodes hash the hex text of their children: the encoding never changes a root.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    // Encodes the raw bytes of a digest
+    pub fn encode(&self, digest: &[u8]) -> String {
+        match self {
+            Encoding::Hex => util::bytes_to_hex(digest),
+            Encoding::Base64 => BASE64.encode(digest),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "base64",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64),
+            _ => Err(format!("unknown encoding '{}' (expected hex or base64)", s)),
+        }
+    }
+}
+
+// Parsing from a string lets command line parsers (e.g. clap) accept the algorithm name directly
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(format!(
+                "unknown hash algorithm '{}' (expected sha256, sha512 or blake3)",
+                s
+            )),
+        }
+    }
+}
@@ -1,21 +1,83 @@
-use log::info;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use util::calc_sha256;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing::info;
+use util::calc_sha256_reader;
+
+mod batch;
+mod builder;
+mod consistency;
+mod hash;
+mod nested;
+mod proof;
+mod range;
+#[cfg(feature = "serde")]
+mod serialized;
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+mod wasm;
+
+pub use batch::{BatchProof, BatchProofEntry};
+pub use builder::MerkleTreeBuilder;
+pub use consistency::{
+    verify_consistency, verify_consistency_with_algorithm, verify_consistency_with_mode,
+};
+pub use hash::{Encoding, HashAlgorithm, HashMode, OrderingMode};
+pub use nested::{verify_nested, NestedProof};
+#[cfg(feature = "serde")]
+pub use proof::verify_json;
+pub use proof::{
+    compute_root_from_proof, compute_root_from_proof_with_algorithm, verify, verify_any,
+    verify_file, verify_file_with_algorithm, verify_nodes, verify_with_algorithm, Attestation,
+    MerkleProof, ProofFile, ProofStep, ProofVerifier, Side,
+};
+pub use range::{verify_range_proof, RangeProof};
+#[cfg(feature = "serde")]
+pub use serialized::{SerializedTree, TREE_FORMAT_VERSION};
+#[cfg(all(target_arch = "wasm32", feature = "serde"))]
+pub use wasm::verify_proof_js;
+
+// MerkleErrorKind classifies a `MerkleTreeError` so callers (e.g. the gRPC server) can react to the
+// category of failure without matching on the message string.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MerkleErrorKind {
+    EmptyInput,
+    IndexOutOfBounds,
+    RootHashMismatch,
+    ProofStructureInvalid,
+    UnsupportedFormat,
+    DuplicateLeaf,
+    LeafNotFound,
+    ShapeMismatch,
+    NodeHashMismatch,
+    Io,
+    Internal,
+}
 
 #[derive(Debug)]
 pub struct MerkleTreeError {
+    kind: MerkleErrorKind,
     details: String,
 }
 
 // Define a `new` method for error
 impl MerkleTreeError {
-    fn new(msg: &str) -> MerkleTreeError {
+    fn new(kind: MerkleErrorKind, msg: &str) -> MerkleTreeError {
         MerkleTreeError {
+            kind,
             details: (msg.to_string()),
         }
     }
+
+    // Category of the error
+    pub fn kind(&self) -> MerkleErrorKind {
+        self.kind
+    }
 }
 
 // implement `Display` trait on MerkleTreeError to format the error in a custom-defined way.
@@ -33,7 +95,8 @@ impl Error for MerkleTreeError {}
 // smart pointers, ensuring that the size of the struct remains finite while allowing flexible recursive structures.
 // Without Box, Rust would try to allocate the entire tree on the stack, which is not feasible because stack frames have a fixed size.
 // The Box pointer stores the TreeNode on the heap, allowing Rust to handle this recursive structure safely and efficiently.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct TreeNode {
     pub hash: String,
     pub left_idx: usize,
@@ -42,31 +105,202 @@ pub struct TreeNode {
     pub right: Option<Box<TreeNode>>,
 }
 
+// Extra levels a tree may have beyond the height of the midpoint split over its leaves, see `MerkleTree::check_depth`
+pub const MAX_DEPTH_SLACK: usize = 2;
+
+// Deepest nesting of `TreeNode`s accepted while deserializing. With `usize` indices no midpoint split is more than
+// `usize::BITS` levels deep, so anything beyond is forged.
+pub const MAX_NODE_DEPTH: usize = usize::BITS as usize + MAX_DEPTH_SLACK;
+
+// How often a parallel build turns the number of hashed leaves into progress reports
+#[cfg(feature = "parallel")]
+const PROGRESS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+// Height of the tree the midpoint split builds over `leaf_count` leaves, i.e. ceil(log2(leaf_count))
+pub fn balanced_height(leaf_count: usize) -> usize {
+    match leaf_count {
+        0 | 1 => 0,
+        n => (usize::BITS - (n - 1).leading_zeros()) as usize,
+    }
+}
+
+// The derived `Deserialize` recurses once per level, so a forged file with deeply nested children would overflow the
+// stack before any check could run on the loaded tree. This impl decodes the same layout through a private twin of
+// `TreeNode`, while a per-thread counter tracks the nesting and fails beyond `MAX_NODE_DEPTH`.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for TreeNode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<TreeNode, D::Error> {
+        use std::cell::Cell;
+
+        // Field names and order must match `TreeNode`, so JSON and bincode read the same as with the derive
+        #[derive(Deserialize)]
+        #[serde(rename = "TreeNode")]
+        struct Fields {
+            hash: String,
+            left_idx: usize,
+            right_idx: usize,
+            left: Option<Box<TreeNode>>,
+            right: Option<Box<TreeNode>>,
+        }
+
+        thread_local! {
+            static DEPTH: Cell<usize> = const { Cell::new(0) };
+        }
+
+        // Leaves the level again on every exit path, errors included
+        struct Level;
+        impl Drop for Level {
+            fn drop(&mut self) {
+                DEPTH.with(|depth| depth.set(depth.get() - 1));
+            }
+        }
+
+        let depth = DEPTH.with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        });
+        let _level = Level;
+        if depth > MAX_NODE_DEPTH {
+            return Err(serde::de::Error::custom(format!(
+                "tree nodes are nested deeper than {} levels",
+                MAX_NODE_DEPTH
+            )));
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        Ok(TreeNode {
+            hash: fields.hash,
+            left_idx: fields.left_idx,
+            right_idx: fields.right_idx,
+            left: fields.left,
+            right: fields.right,
+        })
+    }
+}
+
 // implement clone trait for TreeNode to allow deep copy
-// as_ref() method safely accesses the contents of an Option without taking ownership since we only want to borrow the value to clone it
-// map() method applies a function to the contents of an Option if it contains Some, allowing transformations like deep cloning
-// double dereferencing is required as Box<TreeNode> is still a smart pointer to the TreeNode
+// A recursive clone uses one stack frame per level, so a deep enough tree (e.g. one built by hand or loaded from an
+// untrusted file) would overflow the stack. Instead the nodes are listed in pre-order with `iter()` and rebuilt in
+// reverse: by the time a node is reached, the copies of its children are on top of `built`, left child first.
 impl Clone for TreeNode {
     fn clone(&self) -> Self {
-        TreeNode {
-            hash: self.hash.clone(),
-            left_idx: self.left_idx,
-            right_idx: self.right_idx,
-            left: self
-                .left
-                .as_ref()
-                .map(|left_node| Box::new((**left_node).clone())),
-            right: self
-                .right
-                .as_ref()
-                .map(|right_node| Box::new((**right_node).clone())),
+        let nodes: Vec<&TreeNode> = self.iter().collect();
+        let mut built: Vec<TreeNode> = Vec::with_capacity(nodes.len());
+
+        for node in nodes.into_iter().rev() {
+            let left = node.left.as_ref().and_then(|_| built.pop()).map(Box::new);
+            let right = node.right.as_ref().and_then(|_| built.pop()).map(Box::new);
+            built.push(TreeNode {
+                hash: node.hash.clone(),
+                left_idx: node.left_idx,
+                right_idx: node.right_idx,
+                left,
+                right,
+            });
         }
+
+        built
+            .pop()
+            .expect("the copy of the root is always built last")
+    }
+}
+
+// A derived Debug would print the whole subtree, which makes a logged proof or tree unreadable. Only the start of the
+// hash, the index range and the number of children are shown; use `iter()` to walk the subtree.
+impl fmt::Debug for TreeNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let children = self.left.is_some() as usize + self.right.is_some() as usize;
+        f.debug_struct("TreeNode")
+            .field("hash", &hash_prefix(&self.hash))
+            .field("left_idx", &self.left_idx)
+            .field("right_idx", &self.right_idx)
+            .field("children", &children)
+            .finish()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// First 8 characters of a hash, enough to tell nodes apart when reading logs
+fn hash_prefix(hash: &str) -> String {
+    match hash.get(..8) {
+        Some(prefix) if prefix.len() < hash.len() => format!("{}…", prefix),
+        _ => hash.to_string(),
+    }
+}
+
+impl TreeNode {
+    // One-line description of the node, e.g. `3f2a9c01… [0, 3]`, for printing proofs
+    pub fn summary(&self) -> String {
+        format!(
+            "{} [{}, {}]",
+            hash_prefix(&self.hash),
+            self.left_idx,
+            self.right_idx
+        )
+    }
+
+    // Iterates over this node and all of its descendants in pre-order (node, left subtree, right subtree). The
+    // traversal keeps its own stack on the heap, so it works for trees of any depth.
+    pub fn iter(&self) -> Nodes<'_> {
+        Nodes { stack: vec![self] }
+    }
+}
+
+// Pre-order iterator returned by `TreeNode::iter`
+pub struct Nodes<'a> {
+    stack: Vec<&'a TreeNode>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a TreeNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        // Right is pushed first so the left subtree is visited first
+        self.stack.extend(node.right.as_deref());
+        self.stack.extend(node.left.as_deref());
+        Some(node)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MerkleTree {
-    pub root: Option<Box<TreeNode>>,
+    // Private so that every change goes through `root_mut`, which drops the hash cache derived from it
+    root: Option<Box<TreeNode>>,
+    // For trees whose leaves were reordered or deduplicated, `leaf_map[leaf_idx]` is the original file index of that leaf.
+    // `None` means leaf indices and file indices are the same. `serde(default)` keeps older JSON without the field loadable.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub leaf_map: Option<Vec<usize>>,
+    // Hash function the tree was built with. Trees serialized before the field existed were always SHA-256.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_algorithm: HashAlgorithm,
+    // Leaf and node hashing rule (see `HashMode`); trees serialized before the field existed are `Classic`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_mode: HashMode,
+    // Concatenation order of child hashes (see `OrderingMode`); trees serialized before the field existed are `Positional`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordering: OrderingMode,
+    // Encoding of the digests returned by `encoded_root_hash`. It only affects output, never a hash, so it is not
+    // serialized and takes no part in equality; a loaded tree uses hex.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub encoding: Encoding,
+    // Flattened node hashes in pre-order, so node ids follow from the index ranges: the left child of node `id` over
+    // `[left, right]` is `id + 1` and the right child is `id + 2 * (mid - left + 1)`. Proof generation indexes into it
+    // instead of searching the tree. It is filled right after building, or on first use after deserialization
+    // (`OnceLock` allows the one-time initialization through `&self`), and is never serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hash_cache: OnceLock<Vec<String>>,
+}
+
+// The hash cache is derived from `root` and the encoding only affects output, so neither takes part in equality
+impl PartialEq for MerkleTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+            && self.leaf_map == other.leaf_map
+            && self.hash_algorithm == other.hash_algorithm
+            && self.hash_mode == other.hash_mode
+            && self.ordering == other.ordering
+    }
 }
 
 // Unlike the Copy trait, which makes shallow copies, Clone can handle more complex types like heap-allocated data (Box).
@@ -78,30 +312,472 @@ impl Clone for MerkleTree {
                 .root
                 .as_ref()
                 .map(|root_node| Box::new((**root_node).clone())),
+            leaf_map: self.leaf_map.clone(),
+            hash_algorithm: self.hash_algorithm,
+            hash_mode: self.hash_mode,
+            ordering: self.ordering,
+            encoding: self.encoding,
+            hash_cache: self.hash_cache.clone(),
         }
     }
 }
 
 impl MerkleTree {
-    // Constructor for Merkle Tree
+    // Constructor for Merkle Tree. An empty file list is an `EmptyInput` error, but an empty file is a leaf like any
+    // other: its leaf hash is the hash of the empty input.
     pub fn new(files: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::new_with_algorithm(files, HashAlgorithm::Sha256)
+    }
+
+    // Same as `new` but hashes leaves and internal nodes with the given algorithm
+    pub fn new_with_algorithm(
+        files: &[Vec<u8>],
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::build(
+            files,
+            hash_algorithm,
+            HashMode::Classic,
+            OrderingMode::Positional,
+            &mut || {},
+        )
+    }
+
+    // Same as `new` but with RFC 6962 domain separation (see `HashMode::Rfc6962`), which protects against
+    // second-preimage attacks. Proofs of such a tree only verify in the same mode; `ProofFile`, `MerkleProof` and
+    // `Attestation` record the mode so their `verify` methods pick the matching rule.
+    pub fn new_rfc6962(files: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::builder()
+            .hash_mode(HashMode::Rfc6962)
+            .build(files)
+    }
+
+    // Same as `new` but every leaf hashes `index || file` (see `HashMode::Indexed`), so a file only verifies at the
+    // position it was built at. Leaf hashes, roots and proofs differ from the plain mode and are not compatible with
+    // it: verify with `verify_file` or the `verify` methods of `MerkleProof`, `ProofFile` and `Attestation`, which
+    // rehash the leaf with its index.
+    pub fn new_indexed(files: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::builder()
+            .hash_mode(HashMode::Indexed)
+            .build(files)
+    }
+
+    // Same as `new` but calls `progress(leaves_hashed, total)` each time a leaf hash has been computed, so that long
+    // builds over many files can report their progress (e.g. render a progress bar).
+    pub fn new_with_progress<F: FnMut(usize, usize)>(
+        files: &[Vec<u8>],
+        progress: F,
+    ) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::builder().progress(progress).build(files)
+    }
+
+    // Shared by all constructors. `on_leaf` is a trait object (`dyn FnMut`) rather than a generic parameter so that
+    // the recursion in `build_tree` is compiled only once, whatever closure is passed in.
+    fn build(
+        files: &[Vec<u8>],
+        hash_algorithm: HashAlgorithm,
+        hash_mode: HashMode,
+        ordering: OrderingMode,
+        on_leaf: &mut dyn FnMut(),
+    ) -> Result<MerkleTree, MerkleTreeError> {
         let n = files.len();
         if n == 0 {
-            return Err(MerkleTreeError::new("empty file list"));
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::EmptyInput,
+                "empty file list",
+            ));
         }
 
         info!("creating a new Merkle tree with {} files", files.len());
-        let root = MerkleTree::build_tree(files, 0, n - 1);
-        Ok(MerkleTree {
+
+        // With the `parallel` feature every leaf is hashed up front on all cores, then the tree is assembled from those
+        // hashes with the same midpoint split, so the root is bit-identical to the sequential build.
+        // The workers count the hashed leaves and this thread turns the count into `on_leaf` calls while they run, so
+        // progress is still reported per leaf during the expensive phase and the callback needs no `Send` or `Sync`.
+        #[cfg(feature = "parallel")]
+        let root = {
+            use rayon::prelude::*;
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::thread;
+
+            let hashed = AtomicUsize::new(0);
+            let caller = thread::current();
+            let leaf_hashes: Vec<String> = thread::scope(|scope| {
+                let workers = scope.spawn(|| {
+                    let leaf_hashes = files
+                        .par_iter()
+                        .enumerate()
+                        .map(|(idx, file)| {
+                            let leaf_hash = hash_algorithm.hash_leaf(hash_mode, idx, file);
+                            hashed.fetch_add(1, Ordering::Relaxed);
+                            leaf_hash
+                        })
+                        .collect();
+                    // Wakes the caller up right away instead of at its next poll
+                    caller.unpark();
+                    leaf_hashes
+                });
+
+                let mut reported = 0;
+                while !workers.is_finished() {
+                    let done = hashed.load(Ordering::Relaxed);
+                    for _ in reported..done {
+                        on_leaf();
+                    }
+                    reported = done;
+                    thread::park_timeout(PROGRESS_POLL_INTERVAL);
+                }
+                let leaf_hashes = workers.join().expect("leaf hashing does not panic");
+                // Every leaf has been hashed once the workers are joined
+                for _ in reported..n {
+                    on_leaf();
+                }
+                leaf_hashes
+            });
+
+            MerkleTree::build_tree_from_hashes(
+                &mut leaf_hashes.into_iter(),
+                0,
+                n - 1,
+                hash_algorithm,
+                hash_mode,
+                ordering,
+                &mut || {},
+            )
+        };
+
+        #[cfg(not(feature = "parallel"))]
+        let root = MerkleTree::build_tree(
+            files,
+            0,
+            n - 1,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            on_leaf,
+        );
+
+        Ok(MerkleTree::with_root(
+            root,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+        ))
+    }
+
+    // Builds a SHA-256 tree over the files at `paths`, in the given order. Each file is hashed as a stream with
+    // `calc_sha256_reader`, so only one read buffer is in memory at a time rather than the contents of every file.
+    // The root is the same as `new` over the file contents.
+    pub fn from_paths(paths: &[PathBuf]) -> Result<MerkleTree, MerkleTreeError> {
+        if paths.is_empty() {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::EmptyInput,
+                "empty file list",
+            ));
+        }
+
+        info!("creating a new Merkle tree from {} paths", paths.len());
+
+        let leaf_hashes = paths
+            .iter()
+            .map(|path| {
+                File::open(path).and_then(calc_sha256_reader).map_err(|e| {
+                    MerkleTreeError::new(MerkleErrorKind::Io, &format!("{:?}: {}", path, e))
+                })
+            })
+            .collect::<Result<Vec<String>, MerkleTreeError>>()?;
+
+        let root = MerkleTree::build_tree_from_hashes(
+            &mut leaf_hashes.into_iter(),
+            0,
+            paths.len() - 1,
+            HashAlgorithm::Sha256,
+            HashMode::Classic,
+            OrderingMode::Positional,
+            &mut || {},
+        );
+
+        Ok(MerkleTree::with_root(
+            root,
+            HashAlgorithm::Sha256,
+            HashMode::Classic,
+            OrderingMode::Positional,
+        ))
+    }
+
+    // Builds a tree over one large file split into `chunk_size` byte chunks, the last of which may be shorter, as in
+    // BitTorrent or IPFS. Leaf `i` is chunk `i`, so the usual proofs let a downloader check each chunk on its own as it
+    // arrives. Empty data has no chunks and is rejected like an empty file list.
+    pub fn from_file_chunks(data: &[u8], chunk_size: usize) -> Result<MerkleTree, MerkleTreeError> {
+        if chunk_size == 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::EmptyInput,
+                "chunk size must be positive",
+            ));
+        }
+
+        let chunks: Vec<Vec<u8>> = data.chunks(chunk_size).map(<[u8]>::to_vec).collect();
+        MerkleTree::new(&chunks)
+    }
+
+    // A tree without any leaves, e.g. for a dataset that has no data yet. `new` rejects an empty file list, so this is
+    // the only way to build one. Its root hash is the SHA-256 of the empty input, as in RFC 6962, and no proof
+    // verifies against it.
+    pub fn empty() -> MerkleTree {
+        MerkleTree::empty_with_algorithm(HashAlgorithm::Sha256)
+    }
+
+    // Same as `empty` for another hash algorithm, whose hash of the empty input becomes the root hash
+    pub fn empty_with_algorithm(hash_algorithm: HashAlgorithm) -> MerkleTree {
+        MerkleTree {
+            root: None,
+            leaf_map: None,
+            hash_algorithm,
+            hash_mode: HashMode::Classic,
+            ordering: OrderingMode::Positional,
+            encoding: Encoding::Hex,
+            hash_cache: OnceLock::new(),
+        }
+    }
+
+    fn with_root(
+        root: TreeNode,
+        hash_algorithm: HashAlgorithm,
+        hash_mode: HashMode,
+        ordering: OrderingMode,
+    ) -> MerkleTree {
+        let merkle_tree = MerkleTree {
             root: Some(Box::new(root)),
+            leaf_map: None,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            encoding: Encoding::Hex,
+            hash_cache: OnceLock::new(),
+        };
+
+        // Precompute the flattened hashes so that the first proof request does not pay for it
+        merkle_tree.node_hashes();
+        merkle_tree
+    }
+
+    // Flattened node hashes in pre-order, computed once per tree
+    fn node_hashes(&self) -> &[String] {
+        self.hash_cache.get_or_init(|| {
+            let mut hashes = Vec::with_capacity(self.node_count());
+            if let Some(root) = self.root.as_deref() {
+                collect_hashes(root, &mut hashes);
+            }
+            hashes
         })
     }
 
+    // Opt-in strict mode: same as `new`, but fails with a `DuplicateLeaf` error if any two leaves hash to the same
+    // value, since identical leaves make `find_index_by_hash` ambiguous. `new` keeps allowing duplicates.
+    pub fn new_strict(files: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::builder().strict(true).build(files)
+    }
+
+    // Fails with a `DuplicateLeaf` error naming the first two leaves with the same hash
+    fn check_distinct_leaves(&self) -> Result<(), MerkleTreeError> {
+        // The deepest level holds every leaf, from left to right
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (leaf_idx, _, hash) in self.nodes_at_depth(self.height()) {
+            if let Some(first_idx) = seen.insert(hash, leaf_idx) {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::DuplicateLeaf,
+                    &format!(
+                        "files {} and {} have the same leaf hash",
+                        first_idx, leaf_idx
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Builds a tree over the distinct file contents only, keeping the first occurrence of each content in its original
+    // order. The resulting `leaf_map` records, for each leaf, the index of the file it was taken from. The returned
+    // `Vec` goes the other way: element `i` is the leaf index of file `i`, so duplicates share a leaf index.
+    // Trade-off: the tree, and therefore every proof, is per unique content, not per original position. A proof of a
+    // duplicated file shows that its content is in the dataset, not at which of the positions holding it.
+    pub fn new_deduplicated(
+        files: &[Vec<u8>],
+    ) -> Result<(MerkleTree, Vec<usize>), MerkleTreeError> {
+        let mut leaf_of_content: HashMap<&[u8], usize> = HashMap::new();
+        let mut unique_files: Vec<Vec<u8>> = Vec::new();
+        let mut leaf_map: Vec<usize> = Vec::new();
+        let mut file_to_leaf: Vec<usize> = Vec::with_capacity(files.len());
+
+        for (file_idx, file) in files.iter().enumerate() {
+            // The first occurrence of a content gets the next leaf; later ones reuse it
+            let leaf_idx = *leaf_of_content.entry(file.as_slice()).or_insert_with(|| {
+                unique_files.push(file.clone());
+                leaf_map.push(file_idx);
+                unique_files.len() - 1
+            });
+            file_to_leaf.push(leaf_idx);
+        }
+
+        let mut merkle_tree = MerkleTree::new(&unique_files)?;
+        merkle_tree.leaf_map = Some(leaf_map);
+        Ok((merkle_tree, file_to_leaf))
+    }
+
+    // Translates a leaf index into the original file index
+    pub fn file_index(&self, leaf_idx: usize) -> Option<usize> {
+        match &self.leaf_map {
+            Some(leaf_map) => leaf_map.get(leaf_idx).copied(),
+            None => Some(leaf_idx),
+        }
+    }
+
+    // Translates an original file index into its leaf index, or None if that file is not a leaf of the tree
+    pub fn leaf_index(&self, file_idx: usize) -> Option<usize> {
+        match &self.leaf_map {
+            Some(leaf_map) => leaf_map.iter().position(|&idx| idx == file_idx),
+            None => Some(file_idx),
+        }
+    }
+
+    // Rebuilds the tree from the files it was built from with another hash algorithm, e.g. to migrate from SHA-256 to
+    // SHA-512. The hash mode, the ordering and the leaf order (including the `leaf_map` of a deduplicated tree) are
+    // kept. `files` must have one file per leaf, and every file is first checked against its current leaf hash, so a
+    // missing, extra or swapped file fails with a `ShapeMismatch` or `NodeHashMismatch` error instead of silently
+    // producing a tree over other data.
+    pub fn rehash(
+        &self,
+        files: &[Vec<u8>],
+        new_algo: HashAlgorithm,
+    ) -> Result<MerkleTree, MerkleTreeError> {
+        let leaf_count = self.root.as_deref().map_or(0, |root| root.right_idx + 1);
+
+        // A deduplicated tree keeps only some of the files, in the order given by its leaf map
+        let mapped: Vec<Vec<u8>>;
+        let leaves: &[Vec<u8>] = match &self.leaf_map {
+            Some(leaf_map) => {
+                mapped = leaf_map
+                    .iter()
+                    .map(|&file_idx| files.get(file_idx).cloned())
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| {
+                        MerkleTreeError::new(
+                            MerkleErrorKind::ShapeMismatch,
+                            "the leaf map refers to a file that was not provided",
+                        )
+                    })?;
+                &mapped
+            }
+            None => files,
+        };
+        if leaves.len() != leaf_count {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!(
+                    "expected one file per leaf ({}), got {}",
+                    leaf_count,
+                    leaves.len()
+                ),
+            ));
+        }
+
+        if leaf_count == 0 {
+            let mut merkle_tree = MerkleTree::empty_with_algorithm(new_algo);
+            merkle_tree.hash_mode = self.hash_mode;
+            merkle_tree.ordering = self.ordering;
+            return Ok(merkle_tree);
+        }
+
+        for (leaf_idx, leaf) in leaves.iter().enumerate() {
+            if self
+                .hash_algorithm
+                .hash_leaf(self.hash_mode, leaf_idx, leaf)
+                != self.leaf_hash(leaf_idx)?
+            {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::NodeHashMismatch,
+                    &format!("file for leaf {} does not match the tree", leaf_idx),
+                ));
+            }
+        }
+
+        let mut merkle_tree =
+            MerkleTree::build(leaves, new_algo, self.hash_mode, self.ordering, &mut || {})?;
+        merkle_tree.leaf_map = self.leaf_map.clone();
+        Ok(merkle_tree)
+    }
+
+    // Same recursion as `build_tree`, but over leaf hashes that were already computed (in parallel, or streamed from
+    // disk). The hashes are popped in order from `leaves`, which matches the left-to-right order in which the recursion
+    // reaches the leaves.
+    fn build_tree_from_hashes(
+        leaves: &mut impl Iterator<Item = String>,
+        left: usize,
+        right: usize,
+        hash_algorithm: HashAlgorithm,
+        hash_mode: HashMode,
+        ordering: OrderingMode,
+        on_leaf: &mut dyn FnMut(),
+    ) -> TreeNode {
+        if left == right {
+            on_leaf();
+            return TreeNode {
+                hash: leaves.next().expect("one hash per leaf"),
+                left_idx: left,
+                right_idx: right,
+                left: None,
+                right: None,
+            };
+        }
+
+        let mid = left + (right - left) / 2;
+        let left_child = MerkleTree::build_tree_from_hashes(
+            leaves,
+            left,
+            mid,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            on_leaf,
+        );
+        let right_child = MerkleTree::build_tree_from_hashes(
+            leaves,
+            mid + 1,
+            right,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            on_leaf,
+        );
+
+        let combined_hash =
+            hash_algorithm.hash_pair(hash_mode, ordering, &left_child.hash, &right_child.hash);
+
+        TreeNode {
+            hash: combined_hash,
+            left_idx: left,
+            right_idx: right,
+            left: Some(Box::new(left_child)),
+            right: Some(Box::new(right_child)),
+        }
+    }
+
     // Recursively build the Merkle tree
-    fn build_tree(files: &[Vec<u8>], left: usize, right: usize) -> TreeNode {
+    #[cfg_attr(feature = "parallel", allow(dead_code))]
+    fn build_tree(
+        files: &[Vec<u8>],
+        left: usize,
+        right: usize,
+        hash_algorithm: HashAlgorithm,
+        hash_mode: HashMode,
+        ordering: OrderingMode,
+        on_leaf: &mut dyn FnMut(),
+    ) -> TreeNode {
         if left == right {
+            on_leaf();
             return TreeNode {
-                hash: calc_sha256(&files[left]),
+                hash: hash_algorithm.hash_leaf(hash_mode, left, &files[left]),
                 left_idx: left,
                 right_idx: right,
                 left: None,
@@ -110,11 +786,27 @@ impl MerkleTree {
         }
 
         let mid = left + (right - left) / 2;
-        let left_child = MerkleTree::build_tree(files, left, mid);
-        let right_child = MerkleTree::build_tree(files, mid + 1, right);
+        let left_child = MerkleTree::build_tree(
+            files,
+            left,
+            mid,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            on_leaf,
+        );
+        let right_child = MerkleTree::build_tree(
+            files,
+            mid + 1,
+            right,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+            on_leaf,
+        );
 
         let combined_hash =
-            calc_sha256(format!("{}{}", left_child.hash, right_child.hash).as_bytes());
+            hash_algorithm.hash_pair(hash_mode, ordering, &left_child.hash, &right_child.hash);
 
         TreeNode {
             hash: combined_hash,
@@ -125,31 +817,96 @@ impl MerkleTree {
         }
     }
 
-    // GenerateMerkleProof generates a Merkle proof for the given leaf index.
-    // The use of as_deref() simplifies the conversion of an Option<Box<TreeNode>> to Option<&TreeNode>,
-    // allowing us to work with a borrowed reference instead of an owned value. `as_deref()` works with smart pointers.
-    // expect call will unwrap the `Option` and will panic only if the root is `None`.
-    // Outputs a Vec because the proof is a sequence of references collected during the proof generation process. The Vec allows the function to
-    // create and return a new collection that is owned by the caller, while the references inside the Vec point to data owned by the original MerkleTree.
-    pub fn generate_merkle_proof(
-        &self,
-        leaf_idx: usize,
-    ) -> Result<Vec<&TreeNode>, MerkleTreeError> {
+    // Number of steps in the proof of `leaf_idx`, i.e. the depth of that leaf, without generating the proof. The path is
+    // replayed with the midpoint split from the root's range, so nothing is hashed or allocated. Useful to pre-allocate
+    // buffers or to reject a received proof of implausible length before folding it. A single-leaf tree has no steps,
+    // although `generate_merkle_proof` returns the leaf itself there.
+    pub fn proof_len(&self, leaf_idx: usize) -> Result<usize, MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
+        };
+
+        if leaf_idx < root.left_idx || leaf_idx > root.right_idx {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::IndexOutOfBounds,
+                "index out of bounds",
+            ));
+        }
+
+        let leaf_count = checked_idx(root.right_idx.checked_add(1), MerkleErrorKind::Internal)?;
+        Ok(expected_proof_len(leaf_count, leaf_idx))
+    }
+
+    // GenerateMerkleProof generates a Merkle proof for the given leaf index, ordered from the leaf up.
+    // The proof is read from the flattened hash cache: the path from the root to the leaf is replayed with the midpoint
+    // split (see `sibling_ranges`) and every sibling hash is looked up by its node id, so no subtree is ever searched.
+    // The returned nodes are owned and carry no children, only their hash and index range.
+    // An empty tree (see `MerkleTree::empty`) has no root and therefore no proofs.
+    pub fn generate_merkle_proof(&self, leaf_idx: usize) -> Result<Vec<TreeNode>, MerkleTreeError> {
         info!(
             "[merkle-tree] starting to generate merkle proof for file index {}",
             leaf_idx
         );
-        gen_proof(self.root.as_deref().expect("no root"), leaf_idx)
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
+        };
+
+        let hashes = self.node_hashes();
+        // A tree that was not built by the midpoint split (e.g. hand-edited JSON) may not match the id scheme
+        sibling_ranges(root, leaf_idx)?
+            .into_iter()
+            .map(|(id, left_idx, right_idx)| match hashes.get(id) {
+                Some(hash) => Ok(TreeNode {
+                    hash: hash.clone(),
+                    left_idx,
+                    right_idx,
+                    left: None,
+                    right: None,
+                }),
+                None => Err(MerkleTreeError::new(
+                    MerkleErrorKind::Internal,
+                    "hash cache does not match the tree shape",
+                )),
+            })
+            .collect()
     }
 
-    // Passes only a borrowed slice of references as `proofs: &[&TreeNode]` since it doesn't need to modify or own the proof data.
+    // Same as `verify_merkle_proof`, but hashes `file` itself with the tree's algorithm and hash mode. Callers then
+    // cannot pass a leaf hash computed with another algorithm than the one the tree was built with.
+    pub fn verify_file(
+        &self,
+        root_hash: &str,
+        file: &[u8],
+        file_idx: usize,
+        proofs: &[impl Borrow<TreeNode>],
+    ) -> Result<bool, MerkleTreeError> {
+        let file_hash = self
+            .hash_algorithm
+            .hash_leaf(self.hash_mode, file_idx, file);
+        self.verify_merkle_proof(root_hash, &file_hash, file_idx, proofs)
+    }
+
+    // Passes only a borrowed slice as `proofs` since it doesn't need to modify or own the proof data.
     // Slices are more lightweight than vectors and sufficient for the verification task, which only reads the data.
+    // `impl Borrow<TreeNode>` accepts both the owned nodes of `generate_merkle_proof` and a slice of references.
     pub fn verify_merkle_proof(
         &self,
         root_hash: &str,
         file_hash: &str,
         file_idx: usize,
-        proofs: &[&TreeNode],
+        proofs: &[impl Borrow<TreeNode>],
     ) -> Result<bool, MerkleTreeError> {
         info!(
             "[merkle-tree] verifying merkle proof for file index {} with merkle root hash {}",
@@ -158,13 +915,21 @@ impl MerkleTree {
 
         let root = match &self.root {
             Some(root) => root,
-            None => return Err(MerkleTreeError::new("empty root")),
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
         };
 
         // Deref Coercion: No need to manually dereference the Box with (**root).
         // Rust applies deref coercion to automatically dereference smart pointers like Box making the code simpler and more readable.
         if root.hash != root_hash {
-            return Err(MerkleTreeError::new("merkle root hash mismatch"));
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::RootHashMismatch,
+                "merkle root hash mismatch",
+            ));
         }
 
         let mut merkle_hash = file_hash.to_string();
@@ -176,6 +941,15 @@ impl MerkleTree {
 
         // If the root has either a left or right child
         if root.left.is_some() || root.right.is_some() {
+            // A valid proof has exactly one sibling per level between the leaf and the root. Rejecting any other
+            // length up front avoids folding arbitrarily long (or truncated) proofs.
+            if proofs.len() != self.proof_len(file_idx)? {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::ProofStructureInvalid,
+                    "unexpected proof length",
+                ));
+            }
+
             // Manually create a new MUTABLE `TreeNode` instance
             let mut curr = TreeNode {
                 hash: leaf.hash.clone(),
@@ -185,79 +959,369 @@ impl MerkleTree {
                 right_idx: leaf.right_idx,
             };
 
-            for proof in proofs {
-                if curr.left_idx < proof.left_idx && curr.right_idx < proof.right_idx {
-                    merkle_hash =
-                        calc_sha256(&[merkle_hash.as_bytes(), proof.hash.as_bytes()].concat());
+            for proof in proofs.iter().map(Borrow::borrow) {
+                // The current node and the proof node are the two children of a shared parent whose range is the union
+                // of both ranges. The current node is the LEFT child iff its `left_idx` equals the parent's `left_idx`,
+                // in which case its hash comes first in the concatenation; otherwise the proof node is the left child.
+                let parent_left_idx = usize::min(curr.left_idx, proof.left_idx);
+                let parent_right_idx = usize::max(curr.right_idx, proof.right_idx);
+
+                if curr.left_idx == parent_left_idx {
+                    merkle_hash = self.hash_algorithm.hash_pair(
+                        self.hash_mode,
+                        self.ordering,
+                        &merkle_hash,
+                        &proof.hash,
+                    );
                 } else {
-                    merkle_hash =
-                        calc_sha256(&[proof.hash.as_bytes(), merkle_hash.as_bytes()].concat());
+                    merkle_hash = self.hash_algorithm.hash_pair(
+                        self.hash_mode,
+                        self.ordering,
+                        &proof.hash,
+                        &merkle_hash,
+                    );
                 }
 
-                // Update the indices in the mutable curr node
-                curr.left_idx = usize::min(curr.left_idx, proof.left_idx);
-                curr.right_idx = usize::max(curr.right_idx, proof.right_idx);
+                // The parent becomes the current node for the next level
+                curr.left_idx = parent_left_idx;
+                curr.right_idx = parent_right_idx;
             }
         }
 
         Ok(root.hash == merkle_hash && root_hash == merkle_hash)
     }
 
+    // Hash of the leaf at `leaf_idx` as stored in the tree, so a client can compare it with the hash of its local copy
+    // (computed with `hash_algorithm.hash_leaf`) before downloading or proving anything
+    pub fn leaf_hash(&self, leaf_idx: usize) -> Result<String, MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
+        };
+
+        if leaf_idx < root.left_idx || leaf_idx > root.right_idx {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::IndexOutOfBounds,
+                "index out of bounds",
+            ));
+        }
+
+        Ok(find_leaf(root, leaf_idx)?.hash.clone())
+    }
+
+    // Checks that a tree loaded from untrusted JSON or bincode is internally consistent: every internal node's hash is
+    // recomputed bottom-up from its children with the tree's algorithm, hash mode and ordering, and every node's
+    // children must split its range at the midpoint, since proofs are generated from those ranges. Fails on the first
+    // mismatch with the offending index range. Leaf hashes cannot be checked without the files themselves.
+    pub fn validate(&self) -> Result<(), MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        // The walk below recurses once per level, so the depth is bounded first
+        self.check_depth()?;
+
+        if root.left_idx != 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!(
+                    "root range [{}, {}] does not start at 0",
+                    root.left_idx, root.right_idx
+                ),
+            ));
+        }
+        validate_node(self.hash_algorithm, self.hash_mode, self.ordering, root)
+    }
+
+    // Rejects a tree that is deeper than the midpoint split over its leaves allows, i.e. `balanced_height(leaf_count)`
+    // plus `MAX_DEPTH_SLACK` levels. The recursive walks (`validate`, `find_leaf`, ...) use one stack frame per
+    // level, so trees loaded from untrusted input are checked here before any of them runs. The height is computed
+    // without recursion.
+    pub fn check_depth(&self) -> Result<(), MerkleTreeError> {
+        let leaf_count = self
+            .root
+            .as_deref()
+            .map_or(0, |root| root.right_idx.saturating_add(1));
+        let max_height = balanced_height(leaf_count) + MAX_DEPTH_SLACK;
+        let height = self.height();
+        if height > max_height {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!(
+                    "tree of {} leaves is {} levels deep, expected at most {}",
+                    leaf_count, height, max_height
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    // Hash of the node covering exactly the leaves `left..=right`, e.g. to check that a range of files matches an
+    // expected aggregate hash. Only the ranges produced by the midpoint split have a node: in a tree of 5 leaves
+    // `[0, 2]` and `[3, 4]` do, `[1, 2]` does too, but `[2, 3]` straddles the root split and is a `ShapeMismatch` error.
+    pub fn subtree_root(&self, left: usize, right: usize) -> Result<String, MerkleTreeError> {
+        let mut node = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
+        };
+
+        if left > right || left < node.left_idx || right > node.right_idx {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::IndexOutOfBounds,
+                &format!(
+                    "range [{}, {}] is not within the tree's [{}, {}]",
+                    left, right, node.left_idx, node.right_idx
+                ),
+            ));
+        }
+
+        // Descend into the child that contains the whole range until a node matches it exactly. When neither child
+        // contains it, the range spans the split of the current node and no node covers exactly that range.
+        while node.left_idx != left || node.right_idx != right {
+            node = [node.left.as_deref(), node.right.as_deref()]
+                .into_iter()
+                .flatten()
+                .find(|child| child.left_idx <= left && right <= child.right_idx)
+                .ok_or_else(|| {
+                    MerkleTreeError::new(
+                        MerkleErrorKind::ShapeMismatch,
+                        &format!(
+                            "no node covers exactly [{}, {}]; ranges must follow the midpoint split",
+                            left, right
+                        ),
+                    )
+                })?;
+        }
+        Ok(node.hash.clone())
+    }
+
+    // Root node, or `None` for an empty tree
+    pub fn root(&self) -> Option<&TreeNode> {
+        self.root.as_deref()
+    }
+
+    // Mutable access to the root node. The flattened hash cache is cleared, so proofs generated after the edit are
+    // taken from the edited nodes instead of stale copies.
+    pub fn root_mut(&mut self) -> Option<&mut TreeNode> {
+        self.hash_cache = OnceLock::new();
+        self.root.as_deref_mut()
+    }
+
     // Helper function tobe consumed by other module
+    // An empty tree has the hash of the empty input as its root, so "no data yet" has a well-defined root as well
     pub fn root_hash(&self) -> String {
         match &self.root {
             Some(root) => root.hash.clone(),
-            None => String::new(),
+            None => self.hash_algorithm.hash(b""),
         }
     }
-}
 
-// gen_proof generates a Merkle proof for the given leaf index.
-fn gen_proof(root: &TreeNode, leaf_idx: usize) -> Result<Vec<&TreeNode>, MerkleTreeError> {
-    // Check for errors: root bring none or leaf index out of bounds
-    if leaf_idx < root.left_idx || leaf_idx > root.right_idx {
-        return Err(MerkleTreeError::new("index out of bounds"));
+    // Root hash in the tree's `encoding`. Fails only if the root hash is not a hex digest, which can happen when
+    // `root` was edited by hand.
+    pub fn encoded_root_hash(&self) -> Result<String, MerkleTreeError> {
+        let root_hash = self.root_hash();
+        let digest = util::hex_to_bytes(&root_hash).ok_or_else(|| {
+            MerkleTreeError::new(
+                MerkleErrorKind::Internal,
+                &format!("root hash {} is not a hex digest", root_hash),
+            )
+        })?;
+        Ok(self.encoding.encode(&digest))
     }
 
-    // If node is a leaf
-    if root.left.is_none() && root.right.is_none() {
-        return Ok(vec![root]);
+    // Returns the index of the leftmost leaf whose hash equals `leaf_hash`, or None if no leaf matches.
+    // Complements `find_leaf`, which needs the index up front, for clients that only retained file hashes.
+    pub fn find_index_by_hash(&self, leaf_hash: &str) -> Option<usize> {
+        self.root
+            .as_deref()
+            .and_then(|root| find_leaf_by_hash(root, leaf_hash))
+            .map(|leaf| leaf.left_idx)
+    }
+
+    // Membership check by hash
+    pub fn contains_leaf_hash(&self, leaf_hash: &str) -> bool {
+        self.find_index_by_hash(leaf_hash).is_some()
+    }
+
+    // Longest root-to-leaf path counted in edges: a single leaf has height 0. Returns 0 for an empty tree.
+    pub fn height(&self) -> usize {
+        self.root.as_deref().map_or(0, subtree_height)
+    }
+
+    // Total number of internal and leaf nodes. Returns 0 for an empty tree.
+    pub fn node_count(&self) -> usize {
+        self.root.as_deref().map_or(0, subtree_node_count)
     }
 
-    let mut result: Vec<&TreeNode> = Vec::new();
+    // Returns `(left_idx, right_idx, hash)` for every node at the given depth (root = 0), ordered left to right.
+    // Leaves that end above `depth` (unbalanced trees, or a depth beyond the tree height) are returned in place of
+    // their missing descendants, so the result always covers every leaf index exactly once.
+    pub fn nodes_at_depth(&self, depth: usize) -> Vec<(usize, usize, String)> {
+        let mut nodes = Vec::new();
+        if let Some(root) = self.root.as_deref() {
+            collect_nodes_at_depth(root, depth, &mut nodes);
+        }
+        nodes
+    }
 
-    // Find the sibling of the node at leaf_idx
-    let sibling = find_sibling_by_leaf_index(root, leaf_idx)?;
-    result.push(sibling);
+    // Returns the indices of the leaves whose hashes differ between the two trees, in ascending order. Both trees are
+    // walked top-down together and subtrees with equal hashes are pruned, so comparing two mostly identical
+    // directories only visits the paths to the files that changed. Both trees must have the same leaf count (and
+    // therefore the same shape) and hash algorithm.
+    pub fn diff(&self, other: &MerkleTree) -> Result<Vec<usize>, MerkleTreeError> {
+        let (root, other_root) = match (self.root.as_deref(), other.root.as_deref()) {
+            (Some(root), Some(other_root)) => (root, other_root),
+            _ => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "cannot diff an empty tree",
+                ))
+            }
+        };
 
-    // Find the parent of the node at leaf_idx
-    let mut parent = find_parent_by_leaf_index(root, leaf_idx)?;
+        if root.right_idx != other_root.right_idx {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!(
+                    "trees have different leaf counts ({} and {})",
+                    root.right_idx.saturating_add(1),
+                    other_root.right_idx.saturating_add(1)
+                ),
+            ));
+        }
+        let mismatch = if self.hash_algorithm != other.hash_algorithm {
+            Some(format!(
+                "trees use different hash algorithms ({} and {})",
+                self.hash_algorithm, other.hash_algorithm
+            ))
+        } else if self.hash_mode != other.hash_mode {
+            Some(format!(
+                "trees use different hash modes ({:?} and {:?})",
+                self.hash_mode, other.hash_mode
+            ))
+        } else if self.ordering != other.ordering {
+            Some(format!(
+                "trees use different orderings ({:?} and {:?})",
+                self.ordering, other.ordering
+            ))
+        } else {
+            None
+        };
+        if let Some(mismatch) = mismatch {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &mismatch,
+            ));
+        }
 
-    while parent != root {
-        let sibling = find_sibling(root, parent)?;
-        result.push(sibling);
-        parent = find_parent(root, parent)?;
+        let mut differing = Vec::new();
+        collect_differing_leaves(root, other_root, &mut differing);
+        Ok(differing)
     }
+}
+
+// Post-order walk for `MerkleTree::validate`: both children are checked before their parent, so the first error names
+// the lowest corrupted node
+fn validate_node(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    node: &TreeNode,
+) -> Result<(), MerkleTreeError> {
+    let shape_error = |msg: &str| {
+        MerkleTreeError::new(
+            MerkleErrorKind::ShapeMismatch,
+            &format!("node [{}, {}] {}", node.left_idx, node.right_idx, msg),
+        )
+    };
+
+    match (node.left.as_deref(), node.right.as_deref()) {
+        (None, None) if node.left_idx == node.right_idx => Ok(()),
+        (None, None) => Err(shape_error("is a leaf covering more than one index")),
+        (Some(left), Some(right)) => {
+            let width = checked_idx(
+                node.right_idx.checked_sub(node.left_idx),
+                MerkleErrorKind::ShapeMismatch,
+            )?;
+            let mid_idx = node.left_idx + width / 2;
+            if left.left_idx != node.left_idx
+                || left.right_idx != mid_idx
+                || mid_idx.checked_add(1) != Some(right.left_idx)
+                || right.right_idx != node.right_idx
+            {
+                return Err(shape_error("is not split at its midpoint"));
+            }
 
-    Ok(result)
+            validate_node(hash_algorithm, hash_mode, ordering, left)?;
+            validate_node(hash_algorithm, hash_mode, ordering, right)?;
+
+            if hash_algorithm.hash_pair(hash_mode, ordering, &left.hash, &right.hash) != node.hash {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::NodeHashMismatch,
+                    &format!(
+                        "hash of node [{}, {}] does not match the hash of its children",
+                        node.left_idx, node.right_idx
+                    ),
+                ));
+            }
+            Ok(())
+        }
+        _ => Err(shape_error("has only one child")),
+    }
 }
 
-// find_sibling_by_leaf_index finds the sibling node of the leaf node corresponding to the given leaf index.
-fn find_sibling_by_leaf_index(
-    root: &TreeNode,
-    leaf_idx: usize,
-) -> Result<&TreeNode, MerkleTreeError> {
-    let leaf = find_leaf(root, leaf_idx)?;
-    find_sibling(root, leaf)
+// node_count returns the total number of nodes (leaves + internal) that `MerkleTree::new` creates for `leaf_count` leaves.
+// Every midpoint split turns one range into two, so a tree with n leaves always has n - 1 internal nodes.
+// Useful for capacity planning before the tree is built.
+pub fn node_count(leaf_count: usize) -> usize {
+    (2 * leaf_count).saturating_sub(1)
 }
 
-// find_parent_by_leaf_index finds the sibling node of the leaf node corresponding to the given leaf index.
-fn find_parent_by_leaf_index(
-    root: &TreeNode,
-    leaf_idx: usize,
-) -> Result<&TreeNode, MerkleTreeError> {
-    let leaf = find_leaf(root, leaf_idx)?;
-    find_parent(root, leaf)
+// bytes_estimate approximates the memory held by a tree of `leaf_count` leaves whose hashes are `hash_len` bytes long
+// (64 for hex encoded SHA-256). Each node owns its `TreeNode` allocation plus the heap buffer of its hash string.
+pub fn bytes_estimate(leaf_count: usize, hash_len: usize) -> usize {
+    node_count(leaf_count) * (std::mem::size_of::<TreeNode>() + hash_len)
+}
+
+// Pushes the hashes of the subtree in pre-order (node, left subtree, right subtree)
+fn collect_hashes(node: &TreeNode, hashes: &mut Vec<String>) {
+    hashes.extend(node.iter().map(|node| node.hash.clone()));
+}
+
+// Unwraps the result of a `checked_*` operation on indices that come from a received proof or a deserialized tree.
+// Such indices are not trusted, so an overflow or underflow becomes an error of the given kind instead of a panic
+// (debug builds) or a silent wrap around (release builds).
+fn checked_idx(idx: Option<usize>, kind: MerkleErrorKind) -> Result<usize, MerkleTreeError> {
+    idx.ok_or_else(|| MerkleTreeError::new(kind, "index arithmetic overflow"))
+}
+
+// expected_proof_len returns the number of proof steps for `leaf_idx` in a tree of `leaf_count` leaves, i.e. the depth
+// of that leaf. It replays the midpoint split used by `build_tree` without touching the tree itself.
+fn expected_proof_len(leaf_count: usize, leaf_idx: usize) -> usize {
+    let (mut left, mut right) = (0, leaf_count.saturating_sub(1));
+    let mut len = 0;
+
+    while left < right {
+        let mid = left + (right - left) / 2;
+        if leaf_idx <= mid {
+            right = mid;
+        } else {
+            left = mid + 1;
+        }
+        len += 1;
+    }
+
+    len
 }
 
 //find_leaf finds the leaf node corresponding to the given leaf index.
@@ -274,19 +1338,24 @@ fn find_leaf(root: &TreeNode, leaf_idx: usize) -> Result<&TreeNode, MerkleTreeEr
             Ok(root)
         }
         _ => {
-            let mid_idx = root.left_idx + (root.right_idx - root.left_idx) / 2;
+            // A hand-edited tree may have a node whose range is reversed
+            let width = checked_idx(
+                root.right_idx.checked_sub(root.left_idx),
+                MerkleErrorKind::Internal,
+            )?;
+            let mid_idx = root.left_idx + width / 2;
             if leaf_idx <= mid_idx {
                 find_leaf(
-                    root.left
-                        .as_ref()
-                        .ok_or_else(|| MerkleTreeError::new("invalid left node"))?,
+                    root.left.as_ref().ok_or_else(|| {
+                        MerkleTreeError::new(MerkleErrorKind::Internal, "invalid left node")
+                    })?,
                     leaf_idx,
                 )
             } else {
                 find_leaf(
-                    root.right
-                        .as_ref()
-                        .ok_or_else(|| MerkleTreeError::new("invalid right node"))?,
+                    root.right.as_ref().ok_or_else(|| {
+                        MerkleTreeError::new(MerkleErrorKind::Internal, "invalid right node")
+                    })?,
                     leaf_idx,
                 )
             }
@@ -294,99 +1363,145 @@ fn find_leaf(root: &TreeNode, leaf_idx: usize) -> Result<&TreeNode, MerkleTreeEr
     }
 }
 
-// find_parent finds the parent node of the given node.
-// Lifetimes ('a) in the function tie the root, node, and the returned reference to the same lifetime.
-// They ensure that the returned reference (if any) doesn't outlive the input references thereby prevent dangling references (ptrs to data that no longer exists).
-// Without lifetimes, Rust's borrow checker would not know how the lifetimes of these references relate, leading to potential memory safety issues.
-fn find_parent<'a>(
-    root: &'a TreeNode,
-    node: &'a TreeNode,
-) -> Result<&'a TreeNode, MerkleTreeError> {
-    if root == node {
-        return Err(MerkleTreeError::new("root node has no parent"));
+// subtree_height returns the number of edges on the longest path from `node` down to a leaf.
+// Each node is paired with its depth on an explicit stack instead of recursing.
+fn subtree_height(node: &TreeNode) -> usize {
+    let mut stack = vec![(node, 0)];
+    let mut height = 0;
+
+    while let Some((node, depth)) = stack.pop() {
+        height = usize::max(height, depth);
+        for child in [node.left.as_deref(), node.right.as_deref()]
+            .into_iter()
+            .flatten()
+        {
+            stack.push((child, depth + 1));
+        }
     }
 
-    // Check if the current root is the parent of the node
+    height
+}
 
-    // Check if the left child of the current root is same as the node passed in
-    if let Some(left) = &root.left {
-        if **left == *node {
-            return Ok(root);
-        }
-    }
+// subtree_node_count counts `node` together with all of its descendants
+fn subtree_node_count(node: &TreeNode) -> usize {
+    node.iter().count()
+}
 
-    // Check if the right child of the current root is same as the node passed in
-    if let Some(right) = &root.right {
-        if **right == *node {
-            return Ok(root);
-        }
+// collect_nodes_at_depth descends `depth` levels (left subtree first) and pushes the nodes it lands on into `nodes`
+fn collect_nodes_at_depth(node: &TreeNode, depth: usize, nodes: &mut Vec<(usize, usize, String)>) {
+    if depth == 0 || (node.left.is_none() && node.right.is_none()) {
+        nodes.push((node.left_idx, node.right_idx, node.hash.clone()));
+        return;
     }
 
-    // Recursively search on the left tree
-    if let Some(left) = &root.left {
-        if let Ok(parent) = find_parent(left, node) {
-            return Ok(parent);
-        }
+    if let Some(left) = &node.left {
+        collect_nodes_at_depth(left, depth - 1, nodes);
     }
-
-    // Recursively search on the right tree
-    if let Some(right) = &root.right {
-        if let Ok(parent) = find_parent(right, node) {
-            return Ok(&parent);
-        }
+    if let Some(right) = &node.right {
+        collect_nodes_at_depth(right, depth - 1, nodes);
     }
-
-    // If no parent is found, return an error
-    Err(MerkleTreeError::new("Parent not found"))
 }
 
-// find_sibling finds the sibling node of the given node.
-// as_ref() converts Option<Box<TreeNode>> into Option<&Box<TreeNode>>. This allows us to borrow the Box without taking ownership of it.
-// unwrap() retrieves the &Box<TreeNode> from the Option, assuming it is Some. The Box is then automatically dereferenced to &TreeNode, so
-// we can access the TreeNode directly without needing to manually dereference the Box.
-fn find_sibling<'a>(
-    root: &'a TreeNode,
-    node: &'a TreeNode,
-) -> Result<&'a TreeNode, MerkleTreeError> {
-    // Find the parent of the node
-    let parent = find_parent(root, node)?;
+// Same leaf count means same shape, so both nodes always cover the same range and have children on the same sides
+fn collect_differing_leaves(node: &TreeNode, other: &TreeNode, differing: &mut Vec<usize>) {
+    if node.hash == other.hash {
+        return;
+    }
 
-    // Check if the node is the left child
-    if let Some(left) = &parent.left {
-        if **left == *node {
-            return Ok(parent.right.as_ref().unwrap()); // Return the right sibling
+    match (&node.left, &node.right, &other.left, &other.right) {
+        (Some(left), Some(right), Some(other_left), Some(other_right)) => {
+            collect_differing_leaves(left, other_left, differing);
+            collect_differing_leaves(right, other_right, differing);
         }
+        _ => differing.push(node.left_idx),
     }
+}
 
-    // Check if node is be the right child
-    if let Some(right) = &parent.right {
-        if **right == *node {
-            return Ok(parent.left.as_ref().unwrap()); // Return the left sibling
-        }
+// find_leaf_by_hash walks the leaves from left to right and returns the first one whose hash matches.
+// `or_else` only searches the right subtree when nothing was found on the left.
+fn find_leaf_by_hash<'a>(root: &'a TreeNode, leaf_hash: &str) -> Option<&'a TreeNode> {
+    if root.left.is_none() && root.right.is_none() {
+        return if root.hash == leaf_hash {
+            Some(root)
+        } else {
+            None
+        };
     }
 
-    // If no sibling is found, return an error
-    Err(MerkleTreeError::new("node has no sibling"))
+    root.left
+        .as_deref()
+        .and_then(|left| find_leaf_by_hash(left, leaf_hash))
+        .or_else(|| {
+            root.right
+                .as_deref()
+                .and_then(|right| find_leaf_by_hash(right, leaf_hash))
+        })
 }
 
 // generate_proof_indices generates proof indices for the leaf node corresponding to the given leaf index.
-// It traverses the Merkle tree from the root to the leaf node, collecting the left and right indices
-// of each node in the proof path and appends them to the result.
+// It replays the midpoint split from the root to the leaf, collecting the left and right indices of each node in the
+// proof path, ordered from the leaf up like `generate_merkle_proof`.
 pub fn generate_proof_indices(
     root: &TreeNode,
     leaf_idx: usize,
 ) -> Result<Vec<[usize; 2]>, MerkleTreeError> {
-    let mut result: Vec<[usize; 2]> = Vec::new();
+    Ok(sibling_ranges(root, leaf_idx)?
+        .into_iter()
+        .map(|(_, left_idx, right_idx)| [left_idx, right_idx])
+        .collect())
+}
+
+// sibling_ranges returns `(node_id, left_idx, right_idx)` for every sibling on the path from the leaf at `leaf_idx` up
+// to `root`, i.e. the nodes of its proof. Node ids are pre-order positions, as in the flattened hash cache: the left
+// child of node `id` over `[left, right]` is `id + 1` and the right child is `id + 2 * (mid - left + 1)`. Only the
+// index ranges are used, so nothing below the root is read. A single-leaf tree returns the leaf itself.
+fn sibling_ranges(
+    root: &TreeNode,
+    leaf_idx: usize,
+) -> Result<Vec<(usize, usize, usize)>, MerkleTreeError> {
+    if leaf_idx < root.left_idx || leaf_idx > root.right_idx {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::IndexOutOfBounds,
+            "index out of bounds",
+        ));
+    }
+
+    if root.left_idx == root.right_idx {
+        return Ok(vec![(0, leaf_idx, leaf_idx)]);
+    }
 
-    // Generate the proof nodes
-    let nodes = gen_proof(root, leaf_idx)?;
+    let (mut left, mut right, mut id) = (root.left_idx, root.right_idx, 0);
+    let mut siblings = Vec::new();
 
-    // Collect the left and right indices of each node in the proof path
-    for node in nodes {
-        result.push([node.left_idx, node.right_idx]);
+    while left < right {
+        let mid = left + (right - left) / 2;
+        let (left_id, right_id) = (id + 1, id + 2 * (mid - left + 1));
+
+        if leaf_idx <= mid {
+            siblings.push((right_id, mid + 1, right));
+            right = mid;
+            id = left_id;
+        } else {
+            siblings.push((left_id, left, mid));
+            left = mid + 1;
+            id = right_id;
+        }
     }
 
-    Ok(result)
+    // Siblings were collected from the root down; proofs are ordered from the leaf up
+    siblings.reverse();
+    Ok(siblings)
+}
+
+// proof_coverage returns the `[left_idx, right_idx]` leaf range of every node in an arbitrary proof, in proof order.
+// Unlike `generate_proof_indices` nothing is regenerated, so a received proof can be audited against the positions
+// it is expected to touch before any hashes are folded. The ranges are read as-is from the nodes and not checked.
+pub fn proof_coverage(proof: &[impl Borrow<TreeNode>]) -> Vec<[usize; 2]> {
+    proof
+        .iter()
+        .map(Borrow::borrow)
+        .map(|node: &TreeNode| [node.left_idx, node.right_idx])
+        .collect()
 }
 
 // cfg(test) attribute ensures that the tests module is only included when running tests (i.e., it is ignored in the production build)
@@ -394,14 +1509,391 @@ pub fn generate_proof_indices(
 mod tests {
     // imports all from parent module to test module allowing the test function to use strcutus, functions without prefixing them
     use super::*;
+    use proptest::prelude::*;
+    use util::calc_sha256;
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn write_json_round_trip() {
+        let files: Vec<Vec<u8>> = (0..1024u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        // Vec<u8> implements `Write`, so it can stand in for a file
+        let mut buffer = Vec::new();
+        merkle_tree.write_json(&mut buffer).unwrap();
+
+        let decoded = MerkleTree::from_json(std::str::from_utf8(&buffer).unwrap()).unwrap();
+        assert_eq!(decoded, merkle_tree);
+    }
+
+    #[test]
+    fn from_paths_matches_in_memory_build() {
+        let dir = std::env::temp_dir().join(format!("rustle-tree-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 100_000]).collect();
+        let paths: Vec<PathBuf> = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                let path = dir.join(format!("file{}", i));
+                std::fs::write(&path, file).unwrap();
+                path
+            })
+            .collect();
+
+        let merkle_tree = MerkleTree::from_paths(&paths).unwrap();
+        assert_eq!(merkle_tree, MerkleTree::new(&files).unwrap());
+
+        let err = MerkleTree::from_paths(&[dir.join("missing")]).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::Io);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_reports_changed_leaves() {
+        let files: Vec<Vec<u8>> = (0..11u8).map(|i| vec![i]).collect();
+        let mut changed = files.clone();
+        changed[3] = b"edited".to_vec();
+        changed[10] = b"edited too".to_vec();
+
+        let tree = MerkleTree::new(&files).unwrap();
+        assert_eq!(tree.diff(&tree).unwrap(), Vec::<usize>::new());
+        assert_eq!(
+            tree.diff(&MerkleTree::new(&changed).unwrap()).unwrap(),
+            vec![3, 10]
+        );
+
+        let err = tree
+            .diff(&MerkleTree::new(&files[..5]).unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+
+        // Each setting that differs is named
+        let err = tree
+            .diff(&MerkleTree::new_with_algorithm(&files, HashAlgorithm::Blake3).unwrap())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("different hash algorithms (sha256 and blake3)"));
+        let err = tree
+            .diff(&MerkleTree::new_rfc6962(&files).unwrap())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("different hash modes (Classic and Rfc6962)"));
+    }
+
+    #[test]
+    fn new_strict_rejects_duplicate_leaves() {
+        let files = vec![b"a".to_vec(), b"b".to_vec(), b"b".to_vec(), b"c".to_vec()];
+
+        let err = MerkleTree::new_strict(&files).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::DuplicateLeaf);
+        assert!(err.to_string().contains("files 1 and 2"));
+
+        // The default constructor still accepts them, and unique files pass the strict check
+        assert!(MerkleTree::new(&files).is_ok());
+        let unique = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        assert_eq!(
+            MerkleTree::new_strict(&unique).unwrap(),
+            MerkleTree::new(&unique).unwrap()
+        );
+    }
+
+    #[test]
+    fn find_index_by_hash() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            assert_eq!(
+                merkle_tree.find_index_by_hash(&calc_sha256(file)),
+                Some(idx)
+            );
+        }
+
+        // Internal node hashes are not leaves
+        assert_eq!(
+            merkle_tree.find_index_by_hash(&merkle_tree.root_hash()),
+            None
+        );
+        assert!(!merkle_tree.contains_leaf_hash(&calc_sha256(b"Z")));
+    }
+
+    #[test]
+    fn nodes_at_depth() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'H').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        let root = merkle_tree.nodes_at_depth(0);
+        assert_eq!(root, vec![(0, 7, merkle_tree.root_hash())]);
+
+        let level_one: Vec<(usize, usize)> = merkle_tree
+            .nodes_at_depth(1)
+            .into_iter()
+            .map(|(left, right, _)| (left, right))
+            .collect();
+        assert_eq!(level_one, vec![(0, 3), (4, 7)]);
+
+        // Beyond the height of the tree the leaves are returned
+        let leaves = merkle_tree.nodes_at_depth(10);
+        assert_eq!(leaves.len(), files.len());
+        for (idx, (left, right, hash)) in leaves.iter().enumerate() {
+            assert_eq!((*left, *right), (idx, idx));
+            assert_eq!(*hash, calc_sha256(&files[idx]));
+        }
+    }
+
+    #[test]
+    fn height_and_node_count() {
+        // (file count, expected height, expected node count)
+        let cases = [(1, 0, 1), (2, 1, 3), (4, 2, 7), (5, 3, 9), (8, 3, 15)];
+
+        for (n, height, node_count) in cases {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+            assert_eq!(merkle_tree.height(), height, "height for {} files", n);
+            assert_eq!(
+                merkle_tree.node_count(),
+                node_count,
+                "node count for {} files",
+                n
+            );
+        }
+
+        let empty = MerkleTree::empty();
+        assert_eq!(empty.height(), 0);
+        assert_eq!(empty.node_count(), 0);
+    }
+
+    #[test]
+    fn node_count_matches_built_tree() {
+        for n in 0..=9usize {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let actual = MerkleTree::new(&files).map_or(0, |tree| tree.node_count());
+            assert_eq!(node_count(n), actual, "node count for {} files", n);
+        }
+
+        assert_eq!(node_count(5), 9);
+        assert_eq!(
+            bytes_estimate(5, 64),
+            9 * (std::mem::size_of::<TreeNode>() + 64)
+        );
+    }
+
+    #[test]
+    fn proof_length_guard() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+            assert_eq!(proofs.len(), expected_proof_len(files.len(), idx));
+
+            // Oversized proof: an extra node appended after the valid path
+            let mut oversized = proofs.clone();
+            oversized.push(proofs[0].clone());
+            let err = merkle_tree
+                .verify_merkle_proof(&root_hash, &calc_sha256(file), idx, &oversized)
+                .unwrap_err();
+            assert_eq!(err.to_string(), "MerkleTreeError: unexpected proof length");
+
+            // Undersized proof: the last step towards the root is missing
+            let undersized = &proofs[..proofs.len() - 1];
+            let err = merkle_tree
+                .verify_merkle_proof(&root_hash, &calc_sha256(file), idx, undersized)
+                .unwrap_err();
+            assert_eq!(err.to_string(), "MerkleTreeError: unexpected proof length");
+        }
+    }
+
+    #[test]
+    fn verify_unbalanced_tree() {
+        // Five leaves split as [0,2] + [3,4], so leaves sit at different depths
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+            assert!(
+                merkle_tree
+                    .verify_merkle_proof(&root_hash, &calc_sha256(file), idx, &proofs)
+                    .unwrap(),
+                "verification failed for index {}",
+                idx
+            );
+        }
+    }
+
+    #[test]
+    fn new_with_progress_reports_every_leaf() {
+        let files: Vec<Vec<u8>> = (0..10u8).map(|c| vec![c]).collect();
+        let mut reports = Vec::new();
+
+        let merkle_tree =
+            MerkleTree::new_with_progress(&files, |hashed, total| reports.push((hashed, total)))
+                .unwrap();
+
+        let expected: Vec<(usize, usize)> = (1..=10).map(|hashed| (hashed, 10)).collect();
+        assert_eq!(reports, expected);
+        assert_eq!(merkle_tree, MerkleTree::new(&files).unwrap());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_build_matches_sequential() {
+        for n in [1usize, 2, 5, 64, 1000] {
+            let files: Vec<Vec<u8>> = (0..n).map(|i| i.to_le_bytes().to_vec()).collect();
+            let parallel = MerkleTree::new(&files).unwrap();
+            let sequential = MerkleTree::build_tree(
+                &files,
+                0,
+                n - 1,
+                HashAlgorithm::Sha256,
+                HashMode::Classic,
+                OrderingMode::Positional,
+                &mut || {},
+            );
+            assert_eq!(*parallel.root.unwrap(), sequential, "{} files", n);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn cached_proofs_match_tree_proofs() {
+        for n in [1usize, 2, 3, 5, 8, 13] {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+
+            // A deserialized tree fills its cache on first use
+            let json = serde_json::to_string(&merkle_tree).unwrap();
+            let loaded: MerkleTree = serde_json::from_str(&json).unwrap();
+
+            for idx in 0..n {
+                // The siblings along the path from the root, read from the nodes themselves
+                let mut expected: Vec<(String, usize, usize)> = Vec::new();
+                let mut node = merkle_tree.root.as_deref().unwrap();
+                while let (Some(left), Some(right)) = (node.left.as_deref(), node.right.as_deref())
+                {
+                    let (next, sibling) = if idx <= left.right_idx {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    expected.push((sibling.hash.clone(), sibling.left_idx, sibling.right_idx));
+                    node = next;
+                }
+                expected.reverse();
+                if n == 1 {
+                    expected.push((node.hash.clone(), idx, idx));
+                }
+
+                for tree in [&merkle_tree, &loaded] {
+                    let cached: Vec<(String, usize, usize)> = tree
+                        .generate_merkle_proof(idx)
+                        .unwrap()
+                        .into_iter()
+                        .map(|node| (node.hash, node.left_idx, node.right_idx))
+                        .collect();
+                    assert_eq!(cached, expected, "index {} of {} leaves", idx, n);
+                }
+            }
+
+            assert!(merkle_tree.generate_merkle_proof(n).is_err());
+        }
+
+        // Editing the nodes through `root_mut` clears the cache, so the next proof is taken from the edited tree
+        let files: Vec<Vec<u8>> = (0..4u8).map(|c| vec![c]).collect();
+        let mut merkle_tree = MerkleTree::new(&files).unwrap();
+        merkle_tree.generate_merkle_proof(0).unwrap();
+        let edited = calc_sha256(b"edited");
+        merkle_tree.root_mut().unwrap().right.as_mut().unwrap().hash = edited.clone();
+        let proof = merkle_tree.generate_merkle_proof(0).unwrap();
+        assert_eq!(proof.last().unwrap().hash, edited);
+    }
+
+    #[test]
+    fn hash_algorithms() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+
+        for (name, hash_algorithm) in [
+            ("sha256", HashAlgorithm::Sha256),
+            ("sha512", HashAlgorithm::Sha512),
+            ("blake3", HashAlgorithm::Blake3),
+        ] {
+            assert_eq!(name.parse::<HashAlgorithm>().unwrap(), hash_algorithm);
+
+            let merkle_tree = MerkleTree::new_with_algorithm(&files, hash_algorithm).unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            // The algorithm is recorded in the JSON and read back, so proofs verify with the same function
+            #[cfg(feature = "serde")]
+            let merkle_tree = {
+                let json = serde_json::to_string(&merkle_tree).unwrap();
+                assert!(json.contains(&format!("\"hash_algorithm\":\"{}\"", name)));
+                let merkle_tree: MerkleTree = serde_json::from_str(&json).unwrap();
+                assert_eq!(merkle_tree.hash_algorithm, hash_algorithm);
+                merkle_tree
+            };
+
+            for (idx, file) in files.iter().enumerate() {
+                let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+                assert!(merkle_tree
+                    .verify_merkle_proof(&root_hash, &hash_algorithm.hash(file), idx, &proofs)
+                    .unwrap());
+            }
+        }
+
+        // Different algorithms give different roots over the same files
+        let sha256 = MerkleTree::new(&files).unwrap().root_hash();
+        let blake3 = MerkleTree::new_with_algorithm(&files, HashAlgorithm::Blake3)
+            .unwrap()
+            .root_hash();
+        assert_ne!(sha256, blake3);
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn leaf_map_survives_serialization() {
+        let files = vec![
+            b"A".to_vec(),
+            b"B".to_vec(),
+            b"A".to_vec(),
+            b"C".to_vec(),
+            b"B".to_vec(),
+        ];
+        let (merkle_tree, _) = MerkleTree::new_deduplicated(&files).unwrap();
+        assert_eq!(merkle_tree.leaf_map, Some(vec![0, 1, 3]));
+
+        let json = serde_json::to_string(&merkle_tree).unwrap();
+        let decoded: MerkleTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.leaf_map, Some(vec![0, 1, 3]));
+        assert_eq!(decoded.file_index(2), Some(3));
+        assert_eq!(decoded.leaf_index(3), Some(2));
+        assert_eq!(decoded.leaf_index(2), None);
+
+        // Trees serialized before the field existed still load, without a mapping
+        let legacy = serde_json::to_string(&MerkleTree::new(&files).unwrap())
+            .unwrap()
+            .replace(",\"leaf_map\":null", "");
+        let decoded: MerkleTree = serde_json::from_str(&legacy).unwrap();
+        assert_eq!(decoded.leaf_map, None);
+        assert_eq!(decoded.file_index(4), Some(4));
+    }
 
     #[test]
     fn merkle_tree() {
         // Vec<Vec<u8>> is necessary because it owns the file contents. Each file is a dynamically created vector (Vec<u8>) that is owned by the Vec<Vec<u8>.
         // Vec<&[u8]> would require borrowing data that already exists somewhere, and in our case, we're generating the data on the fly.
         // We need ownership here, which is why Vec<Vec<u8>> is the appropriate choice.
-        let tests = vec![
-            ("EmptyFile", vec![]),
+        let tests = [
+            // No files at all, not a list of empty files (see `empty_files_are_leaves`)
+            ("NoFiles", vec![]),
             // Represents a single file, which is a byte vector containing the ASCII value of "A". b"A" is a byte string literal,
             // representing the byte sequence for the character "A". The `.to_vec()` method converts this byte string into a Vec<u8>.
             ("SingleFile", vec![b"A".to_vec()]),
@@ -425,7 +1917,7 @@ mod tests {
             ("TwentySixFiles", (b'A'..=b'Z').map(|c| vec![c]).collect()),
         ];
 
-        // Test for Empty file
+        // Test for an empty file list
         let (_, files) = &tests[0];
 
         // Rust's deref coercion -  Converts &Vec<Vec<u8>> to &[Vec<u8>] implictly. These two types are not the same but can be compatible
@@ -434,6 +1926,10 @@ mod tests {
             err.unwrap_err().to_string(),
             "MerkleTreeError: empty file list"
         );
+        assert_eq!(
+            MerkleTree::new(files).unwrap_err().kind(),
+            MerkleErrorKind::EmptyInput
+        );
 
         // Test for Five files
         let (_, files) = &tests[3];
@@ -478,8 +1974,7 @@ mod tests {
         }
 
         // Verification test for non-empty files
-        for i in 1..tests.len() {
-            let (name, files) = &tests[i];
+        for (name, files) in tests.iter().skip(1) {
             println!("Running test case: {}", name);
 
             let merkle_tree = MerkleTree::new(files).expect("MerkleTreeError: empty file list");
@@ -541,4 +2036,660 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn deep_trees_are_cloned_and_walked_iteratively() {
+        // A hand-built chain far deeper than any balanced tree; a recursive clone or traversal would overflow
+        let depth = 200_000;
+        let mut chain = TreeNode {
+            hash: "leaf".to_string(),
+            left_idx: 0,
+            right_idx: 0,
+            left: None,
+            right: None,
+        };
+        for level in 1..=depth {
+            chain = TreeNode {
+                hash: format!("node-{}", level),
+                left_idx: 0,
+                right_idx: 0,
+                left: Some(Box::new(chain)),
+                right: None,
+            };
+        }
+
+        let tree = MerkleTree::with_root(
+            chain,
+            HashAlgorithm::Sha256,
+            HashMode::Classic,
+            OrderingMode::Positional,
+        );
+        let copy = tree.clone();
+
+        assert_eq!(copy.height(), depth);
+        assert_eq!(copy.node_count(), depth + 1);
+        let hashes = |tree: &MerkleTree| -> Vec<String> {
+            tree.root
+                .as_deref()
+                .unwrap()
+                .iter()
+                .map(|node| node.hash.clone())
+                .collect()
+        };
+        assert_eq!(hashes(&copy), hashes(&tree));
+
+        // The default drop is recursive, so the chains are taken apart link by link
+        for tree in [tree, copy] {
+            let mut next = tree.root;
+            while let Some(mut node) = next {
+                next = node.left.take();
+            }
+        }
+
+        // Pre-order on a balanced tree: node, left subtree, right subtree
+        let files: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::new(&files).unwrap();
+        let ranges: Vec<(usize, usize)> = tree
+            .root
+            .as_deref()
+            .unwrap()
+            .iter()
+            .map(|node| (node.left_idx, node.right_idx))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(0, 3), (0, 1), (0, 0), (1, 1), (2, 3), (2, 2), (3, 3)]
+        );
+        assert_eq!(tree.clone(), tree);
+    }
+
+    #[test]
+    fn rfc6962_mode_separates_leaves_from_nodes() {
+        let files = vec![b"left".to_vec(), b"right".to_vec()];
+
+        // Classic mode: the concatenated child hashes, presented as the content of a single leaf, give the same root
+        let classic = MerkleTree::new(&files).unwrap();
+        let forged = [calc_sha256(&files[0]), calc_sha256(&files[1])]
+            .concat()
+            .into_bytes();
+        assert_eq!(
+            MerkleTree::new(&[forged]).unwrap().root_hash(),
+            classic.root_hash()
+        );
+
+        // RFC 6962 mode: the leaf and node prefixes make the forgery fail
+        let rfc6962 = MerkleTree::new_rfc6962(&files).unwrap();
+        assert_eq!(rfc6962.hash_mode, HashMode::Rfc6962);
+        assert_ne!(rfc6962.root_hash(), classic.root_hash());
+        let leaf_hashes: Vec<String> = files
+            .iter()
+            .enumerate()
+            .map(|(idx, file)| HashAlgorithm::Sha256.hash_leaf(HashMode::Rfc6962, idx, file))
+            .collect();
+        let forged = leaf_hashes.concat().into_bytes();
+        assert_ne!(
+            MerkleTree::new_rfc6962(&[forged]).unwrap().root_hash(),
+            rfc6962.root_hash()
+        );
+
+        // Proofs verify in the mode of their tree and nowhere else
+        let root_hash = rfc6962.root_hash();
+        for (idx, file) in files.iter().enumerate() {
+            let proofs = rfc6962.generate_merkle_proof(idx).unwrap();
+            assert!(rfc6962
+                .verify_merkle_proof(&root_hash, &leaf_hashes[idx], idx, &proofs)
+                .unwrap());
+
+            let mut proof_file = rfc6962.generate_proof_file(idx).unwrap();
+            assert_eq!(proof_file.hash_mode, HashMode::Rfc6962);
+            assert!(proof_file.verify(file).unwrap());
+            proof_file.hash_mode = HashMode::Classic;
+            assert!(!proof_file.verify(file).unwrap());
+        }
+    }
+
+    #[test]
+    fn empty_tree_has_a_defined_root() {
+        let empty = MerkleTree::empty();
+        assert_eq!(empty.root_hash(), calc_sha256(b""));
+        assert_eq!(
+            MerkleTree::empty_with_algorithm(HashAlgorithm::Blake3).root_hash(),
+            HashAlgorithm::Blake3.hash(b"")
+        );
+        assert_eq!(empty.node_count(), 0);
+        assert!(MerkleTree::new(&[]).is_err());
+
+        // No proof can be generated or verified, whichever entry point is used
+        assert!(empty.generate_merkle_proof(0).is_err());
+        let err = empty
+            .verify_merkle_proof(&empty.root_hash(), &calc_sha256(b""), 0, &[] as &[TreeNode])
+            .unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::EmptyInput);
+        let err = crate::verify(&empty.root_hash(), &calc_sha256(b""), 0, 0, &[]).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::EmptyInput);
+    }
+
+    // Random file sets, including unbalanced trees of every shape up to 256 leaves. A proof must verify for every
+    // index, and changing a single character of any sibling hash must make it fail.
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn proofs_round_trip_and_detect_tampering(
+            files in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 1..256),
+            tamper in any::<usize>(),
+        ) {
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+            let root_hash = merkle_tree.root_hash();
+            let leaf_count = files.len();
+
+            for (idx, file) in files.iter().enumerate() {
+                let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+                prop_assert!(merkle_tree
+                    .verify_merkle_proof(&root_hash, &calc_sha256(file), idx, &proofs)
+                    .unwrap());
+
+                let mut steps = merkle_tree.generate_proof_file(idx).unwrap().steps;
+                prop_assert!(
+                    crate::verify_file(&root_hash, file, idx, leaf_count, &steps).unwrap()
+                );
+
+                // A single-leaf tree has no sibling to tamper with
+                if steps.is_empty() {
+                    continue;
+                }
+                let step = tamper % steps.len();
+                let position = tamper % steps[step].hash.len();
+                let mut hash = steps[step].hash.clone().into_bytes();
+                hash[position] = if hash[position] == b'0' { b'1' } else { b'0' };
+                steps[step].hash = String::from_utf8(hash).unwrap();
+
+                let verified = crate::verify_file(&root_hash, file, idx, leaf_count, &steps);
+                prop_assert!(!verified.unwrap_or(false));
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_pair_ordering_is_recorded_in_proofs() {
+        let files: Vec<Vec<u8>> = (0..7u8).map(|c| vec![c]).collect();
+        let positional = MerkleTree::new(&files).unwrap();
+        let sorted = MerkleTree::builder()
+            .ordering(OrderingMode::SortedPair)
+            .build(&files)
+            .unwrap();
+        assert_eq!(sorted.ordering, OrderingMode::SortedPair);
+        assert_ne!(sorted.root_hash(), positional.root_hash());
+        let root_hash = sorted.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proofs = sorted.generate_merkle_proof(idx).unwrap();
+            assert!(sorted.verify_file(&root_hash, file, idx, &proofs).unwrap());
+
+            let mut verifier = ProofVerifier::new(&calc_sha256(file), idx, files.len())
+                .with_ordering(OrderingMode::SortedPair);
+            for proof in &proofs {
+                verifier.feed(proof).unwrap();
+            }
+            assert!(verifier.finish(&root_hash).unwrap());
+
+            let mut proof_file = sorted.generate_proof_file(idx).unwrap();
+            assert_eq!(proof_file.ordering, OrderingMode::SortedPair);
+            assert!(proof_file.verify(file).unwrap());
+            assert!(sorted
+                .generate_proof(idx)
+                .unwrap()
+                .verify(&root_hash, file)
+                .unwrap());
+            assert!(sorted.generate_full_attestation(idx).unwrap().verify());
+
+            // The recorded ordering survives serialization, and verifying with the other rule fails
+            #[cfg(feature = "serde")]
+            assert_eq!(
+                ProofFile::from_json(&proof_file.to_json().unwrap()).unwrap(),
+                proof_file
+            );
+            if !proof_file.steps.is_empty() {
+                proof_file.ordering = OrderingMode::Positional;
+                assert!(!proof_file.verify(file).unwrap());
+            }
+        }
+
+        let range = sorted.generate_range_proof(2, 5).unwrap();
+        assert!(verify_range_proof(&root_hash, &files[2..=5], &range).unwrap());
+
+        // Proof files written before the field existed are positional
+        #[cfg(feature = "serde")]
+        {
+            let mut json: serde_json::Value = serde_json::from_str(
+                &positional
+                    .generate_proof_file(3)
+                    .unwrap()
+                    .to_json()
+                    .unwrap(),
+            )
+            .unwrap();
+            json.as_object_mut().unwrap().remove("ordering");
+            let legacy = ProofFile::from_json(&json.to_string()).unwrap();
+            assert_eq!(legacy.ordering, OrderingMode::Positional);
+            assert!(legacy.verify(&files[3]).unwrap());
+        }
+
+        let err = positional.diff(&sorted).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("different orderings (Positional and SortedPair)"));
+    }
+
+    #[test]
+    fn proof_len_matches_generated_proofs() {
+        for n in 1..=17usize {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+
+            for idx in 0..n {
+                let steps = merkle_tree.generate_proof_file(idx).unwrap().steps;
+                assert_eq!(merkle_tree.proof_len(idx).unwrap(), steps.len());
+            }
+            assert_eq!(
+                merkle_tree.proof_len(n).unwrap_err().kind(),
+                MerkleErrorKind::IndexOutOfBounds
+            );
+        }
+
+        assert_eq!(
+            MerkleTree::empty().proof_len(0).unwrap_err().kind(),
+            MerkleErrorKind::EmptyInput
+        );
+    }
+
+    #[test]
+    fn tree_node_debug_is_not_recursive() {
+        let files: Vec<Vec<u8>> = (0..64u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root = merkle_tree.root.as_deref().unwrap();
+
+        let debug = format!("{:?}", root);
+        assert_eq!(
+            debug,
+            format!(
+                "TreeNode {{ hash: \"{}…\", left_idx: 0, right_idx: 63, children: 2 }}",
+                &root.hash[..8]
+            )
+        );
+        assert!(!debug.contains(&root.left.as_ref().unwrap().hash[..8]));
+
+        let proofs = merkle_tree.generate_merkle_proof(5).unwrap();
+        assert_eq!(
+            proofs[0].summary(),
+            format!("{}… [4, 4]", &proofs[0].hash[..8])
+        );
+
+        // Short hashes are shown as they are
+        let node = TreeNode {
+            hash: "abc".to_string(),
+            left_idx: 1,
+            right_idx: 1,
+            left: None,
+            right: None,
+        };
+        assert_eq!(node.summary(), "abc [1, 1]");
+    }
+
+    #[test]
+    fn file_chunks_are_leaves() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let merkle_tree = MerkleTree::from_file_chunks(&data, 256).unwrap();
+        assert_eq!(merkle_tree.root.as_ref().unwrap().right_idx, 3);
+
+        // The last chunk holds the remaining 232 bytes
+        let chunks: Vec<Vec<u8>> = data.chunks(256).map(|chunk| chunk.to_vec()).collect();
+        assert_eq!(chunks[3].len(), 1000 - 3 * 256);
+        assert_eq!(merkle_tree, MerkleTree::new(&chunks).unwrap());
+
+        // Each chunk verifies on its own
+        let root_hash = merkle_tree.root_hash();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let proof = merkle_tree.generate_proof(idx).unwrap();
+            assert!(proof.verify(&root_hash, chunk).unwrap());
+        }
+
+        let single = MerkleTree::from_file_chunks(&data, 4096).unwrap();
+        assert_eq!(single.root_hash(), calc_sha256(&data));
+        for (data, chunk_size) in [(&data[..], 0), (&[][..], 256)] {
+            let err = MerkleTree::from_file_chunks(data, chunk_size).unwrap_err();
+            assert_eq!(err.kind(), MerkleErrorKind::EmptyInput);
+        }
+    }
+
+    #[test]
+    fn leaf_hash_is_the_stored_leaf() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new_rfc6962(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            let expected = merkle_tree
+                .hash_algorithm
+                .hash_leaf(merkle_tree.hash_mode, idx, file);
+            assert_eq!(merkle_tree.leaf_hash(idx).unwrap(), expected);
+        }
+
+        let err = merkle_tree.leaf_hash(5).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::IndexOutOfBounds);
+        let err = MerkleTree::empty().leaf_hash(0).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::EmptyInput);
+    }
+
+    #[test]
+    fn deduplicated_files_share_a_leaf() {
+        let files = vec![
+            b"A".to_vec(),
+            b"B".to_vec(),
+            b"A".to_vec(),
+            b"C".to_vec(),
+            b"B".to_vec(),
+        ];
+        let (merkle_tree, file_to_leaf) = MerkleTree::new_deduplicated(&files).unwrap();
+        assert_eq!(file_to_leaf, vec![0, 1, 0, 2, 1]);
+        let unique = vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec()];
+        assert_eq!(
+            merkle_tree.root_hash(),
+            MerkleTree::new(&unique).unwrap().root_hash()
+        );
+
+        // Every original position is proven through the leaf of its content
+        let root_hash = merkle_tree.root_hash();
+        for (file, &leaf_idx) in files.iter().zip(&file_to_leaf) {
+            let proof = merkle_tree.generate_proof(leaf_idx).unwrap();
+            assert!(proof.verify(&root_hash, file).unwrap());
+        }
+
+        assert!(MerkleTree::new_deduplicated(&[]).is_err());
+    }
+
+    #[test]
+    fn subtree_root_matches_aligned_ranges_only() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        // Every node of the tree is found by its own range
+        for node in merkle_tree.root.as_deref().unwrap().iter() {
+            assert_eq!(
+                merkle_tree
+                    .subtree_root(node.left_idx, node.right_idx)
+                    .unwrap(),
+                node.hash
+            );
+        }
+        assert_eq!(
+            merkle_tree.subtree_root(0, 4).unwrap(),
+            merkle_tree.root_hash()
+        );
+
+        // The aggregate of a subtree equals the root of a tree over just those files
+        let left_half = MerkleTree::new(&files[..3]).unwrap().root_hash();
+        assert_eq!(merkle_tree.subtree_root(0, 2).unwrap(), left_half);
+
+        let err = merkle_tree.subtree_root(2, 3).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+        for (left, right) in [(3, 2), (0, 5), (5, 5)] {
+            let err = merkle_tree.subtree_root(left, right).unwrap_err();
+            assert_eq!(err.kind(), MerkleErrorKind::IndexOutOfBounds);
+        }
+        assert!(MerkleTree::empty().subtree_root(0, 0).is_err());
+    }
+
+    #[test]
+    fn validate_detects_forged_nodes() {
+        let files: Vec<Vec<u8>> = (0..6u8).map(|c| vec![c]).collect();
+        for merkle_tree in [
+            MerkleTree::new(&files).unwrap(),
+            MerkleTree::builder()
+                .hash_mode(HashMode::Rfc6962)
+                .ordering(OrderingMode::SortedPair)
+                .build(&files)
+                .unwrap(),
+        ] {
+            assert!(merkle_tree.validate().is_ok());
+        }
+        assert!(MerkleTree::empty().validate().is_ok());
+
+        // A forged leaf no longer matches the hash stored in its parent
+        let mut forged = MerkleTree::new(&files).unwrap();
+        let root = forged.root_mut().unwrap();
+        let left = root.left.as_mut().unwrap();
+        left.right.as_mut().unwrap().hash = calc_sha256(b"forged");
+        let err = forged.validate().unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::NodeHashMismatch);
+        assert!(err.to_string().contains("[0, 2]"));
+
+        // So does a root swapped for another tree's
+        let mut forged = MerkleTree::new(&files).unwrap();
+        forged.root_mut().unwrap().hash = calc_sha256(b"forged");
+        let err = forged.validate().unwrap_err();
+        assert!(err.to_string().contains("[0, 5]"));
+
+        // Ranges that do not follow the midpoint split are rejected before any hash is compared
+        let mut forged = MerkleTree::new(&files).unwrap();
+        forged.root_mut().unwrap().left.as_mut().unwrap().right_idx = 1;
+        let err = forged.validate().unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+    }
+
+    #[test]
+    fn empty_files_are_leaves() {
+        // "No files" is an error, "files that happen to be empty" are not
+        assert_eq!(
+            MerkleTree::new(&[]).unwrap_err().kind(),
+            MerkleErrorKind::EmptyInput
+        );
+
+        let files = vec![
+            Vec::new(),
+            b"A".to_vec(),
+            Vec::new(),
+            b"B".to_vec(),
+            Vec::new(),
+        ];
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let leaf_hash = calc_sha256(file);
+            assert_eq!(merkle_tree.leaf_hash(idx).unwrap(), leaf_hash);
+
+            let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+            assert!(merkle_tree
+                .verify_merkle_proof(&root_hash, &leaf_hash, idx, &proofs)
+                .unwrap());
+        }
+        assert_eq!(merkle_tree.leaf_hash(0).unwrap(), calc_sha256(b""));
+
+        // Empty files share a leaf hash, so they are duplicates for `new_strict`
+        assert_eq!(
+            MerkleTree::new_strict(&files).unwrap_err().kind(),
+            MerkleErrorKind::DuplicateLeaf
+        );
+
+        // In the classic mode a single empty file has the root of the empty tree. RFC 6962 leaves are prefixed, so
+        // there the two differ.
+        let single = vec![Vec::new()];
+        assert_eq!(
+            MerkleTree::new(&single).unwrap().root_hash(),
+            MerkleTree::empty().root_hash()
+        );
+        assert_ne!(
+            MerkleTree::new_rfc6962(&single).unwrap().root_hash(),
+            MerkleTree::empty().root_hash()
+        );
+    }
+
+    #[test]
+    fn proof_coverage_lists_sibling_ranges() {
+        let files: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root = merkle_tree.root.as_deref().unwrap();
+
+        for leaf_idx in 0..files.len() {
+            let proof = merkle_tree.generate_merkle_proof(leaf_idx).unwrap();
+            assert_eq!(
+                proof_coverage(&proof),
+                generate_proof_indices(root, leaf_idx).unwrap()
+            );
+        }
+
+        // Leaf 5 is proven by its sibling leaf, then the pair next to it, then the other half of the tree
+        let proof = merkle_tree.generate_merkle_proof(5).unwrap();
+        assert_eq!(proof_coverage(&proof), vec![[4, 4], [6, 7], [0, 3]]);
+        assert!(proof_coverage(&[] as &[TreeNode]).is_empty());
+    }
+
+    #[test]
+    fn indexed_leaves_are_bound_to_their_position() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new_indexed(&files).unwrap();
+        assert_eq!(merkle_tree.hash_mode, HashMode::Indexed);
+        assert_ne!(
+            merkle_tree.root_hash(),
+            MerkleTree::new(&files).unwrap().root_hash()
+        );
+        let root_hash = merkle_tree.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let expected = calc_sha256(&[&(idx as u64).to_be_bytes()[..], file].concat());
+            assert_eq!(merkle_tree.leaf_hash(idx).unwrap(), expected);
+
+            let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+            assert!(merkle_tree
+                .verify_file(&root_hash, file, idx, &proofs)
+                .unwrap());
+            let proof = merkle_tree.generate_proof(idx).unwrap();
+            assert!(proof.verify(&root_hash, file).unwrap());
+            assert_eq!(merkle_tree.generate_proof_for_content(file).unwrap().0, idx);
+        }
+
+        // A file presented at another position no longer verifies, even with a valid proof for that position
+        let proof = merkle_tree.generate_proof(1).unwrap();
+        assert!(!proof.verify(&root_hash, &files[0]).unwrap());
+
+        // The plain content hash is not a leaf hash of an indexed tree
+        let proofs = merkle_tree.generate_merkle_proof(0).unwrap();
+        assert!(!merkle_tree
+            .verify_merkle_proof(&root_hash, &calc_sha256(&files[0]), 0, &proofs)
+            .unwrap());
+
+        // Duplicate contents hash differently at different positions
+        let same = vec![b"X".to_vec(), b"X".to_vec()];
+        assert!(MerkleTree::builder()
+            .hash_mode(HashMode::Indexed)
+            .strict(true)
+            .build(&same)
+            .is_ok());
+    }
+
+    #[test]
+    fn rehash_migrates_to_another_algorithm() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new_rfc6962(&files).unwrap();
+
+        let migrated = merkle_tree.rehash(&files, HashAlgorithm::Sha512).unwrap();
+        let expected = MerkleTree::builder()
+            .algorithm(HashAlgorithm::Sha512)
+            .hash_mode(HashMode::Rfc6962)
+            .build(&files)
+            .unwrap();
+        assert_eq!(migrated, expected);
+        assert_eq!(migrated.hash_algorithm, HashAlgorithm::Sha512);
+
+        // The leaf map of a deduplicated tree carries over
+        let mut duplicated = files.clone();
+        duplicated.push(files[0].clone());
+        let (deduplicated, _) = MerkleTree::new_deduplicated(&duplicated).unwrap();
+        let migrated = deduplicated
+            .rehash(&duplicated, HashAlgorithm::Blake3)
+            .unwrap();
+        assert_eq!(migrated.leaf_map, deduplicated.leaf_map);
+        assert_eq!(
+            migrated.leaf_hash(0).unwrap(),
+            HashAlgorithm::Blake3.hash(&files[0])
+        );
+
+        // Wrong number of files, or files that were not the leaves of the tree
+        assert_eq!(
+            merkle_tree
+                .rehash(&files[..4], HashAlgorithm::Sha512)
+                .unwrap_err()
+                .kind(),
+            MerkleErrorKind::ShapeMismatch
+        );
+        let mut swapped = files.clone();
+        swapped.swap(1, 2);
+        assert_eq!(
+            merkle_tree
+                .rehash(&swapped, HashAlgorithm::Sha512)
+                .unwrap_err()
+                .kind(),
+            MerkleErrorKind::NodeHashMismatch
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn forged_deep_trees_are_rejected() {
+        // Every internal node has a leaf on the left and the rest of the range on the right, so the height grows with
+        // the leaf count instead of its logarithm
+        fn chain(left: usize, right: usize) -> TreeNode {
+            let leaf = |idx: usize| TreeNode {
+                hash: calc_sha256(&[idx as u8]),
+                left_idx: idx,
+                right_idx: idx,
+                left: None,
+                right: None,
+            };
+            if left == right {
+                return leaf(left);
+            }
+            let right_child = chain(left + 1, right);
+            TreeNode {
+                hash: calc_sha256(right_child.hash.as_bytes()),
+                left_idx: left,
+                right_idx: right,
+                left: Some(Box::new(leaf(left))),
+                right: Some(Box::new(right_child)),
+            }
+        }
+        let forged = |leaf_count: usize| {
+            MerkleTree::with_root(
+                chain(0, leaf_count - 1),
+                HashAlgorithm::Sha256,
+                HashMode::Classic,
+                OrderingMode::Positional,
+            )
+        };
+
+        assert_eq!(balanced_height(1), 0);
+        assert_eq!(balanced_height(5), 3);
+        assert_eq!(balanced_height(8), 3);
+        let files: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        assert!(MerkleTree::new(&files).unwrap().check_depth().is_ok());
+
+        // Shallow enough to load, but too deep for its 12 leaves
+        let tree = forged(12);
+        assert_eq!(tree.height(), 11);
+        for err in [
+            tree.check_depth().unwrap_err(),
+            tree.validate().unwrap_err(),
+            MerkleTree::from_bincode(&tree.to_bincode()).unwrap_err(),
+        ] {
+            assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+        }
+
+        // Nested beyond `MAX_NODE_DEPTH`, which fails while decoding
+        let tree = forged(MAX_NODE_DEPTH + 10);
+        let err = MerkleTree::from_bincode(&tree.to_bincode()).unwrap_err();
+        assert!(err.to_string().contains("nested deeper than"));
+        let mut json = Vec::new();
+        tree.write_json(&mut json).unwrap();
+        assert!(MerkleTree::from_json(std::str::from_utf8(&json).unwrap()).is_err());
+    }
 }
@@ -0,0 +1,83 @@
+use crate::proof::fold_proof_nodes;
+use crate::{MerkleTreeError, TreeNode};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use util::calc_sha256;
+
+// NestedProof proves a leaf of an inner tree whose root is itself a leaf (file) of an outer tree, e.g. when each
+// outer leaf commits to a whole dataset. `inner` proves the leaf against the inner root, and `outer` proves the inner
+// root against the outer root. Both proofs are ordered from the leaf up, as returned by `generate_merkle_proof`.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NestedProof {
+    pub inner: Vec<TreeNode>,
+    pub outer: Vec<TreeNode>,
+    pub inner_leaf_idx: usize,
+    pub outer_leaf_idx: usize,
+}
+
+// Verifies an inner leaf against the outer root in one call: the inner proof is folded to the inner root, which is then
+// hashed like any other file content (the outer leaf is the hex root string) and folded up the outer proof.
+pub fn verify_nested(
+    outer_root: &str,
+    inner_leaf_hash: &str,
+    nested_proof: &NestedProof,
+) -> Result<bool, MerkleTreeError> {
+    let inner: Vec<&TreeNode> = nested_proof.inner.iter().collect();
+    let inner_root = fold_proof_nodes(inner_leaf_hash, nested_proof.inner_leaf_idx, &inner)?;
+
+    let outer: Vec<&TreeNode> = nested_proof.outer.iter().collect();
+    let computed_root = fold_proof_nodes(
+        &calc_sha256(inner_root.as_bytes()),
+        nested_proof.outer_leaf_idx,
+        &outer,
+    )?;
+
+    Ok(computed_root == outer_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn inner_leaf_verifies_against_outer_root() {
+        // Three inner trees whose roots become the files of the outer tree
+        let inner_trees: Vec<MerkleTree> = (0..3u8)
+            .map(|tree| {
+                let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![tree, c]).collect();
+                MerkleTree::new(&files).unwrap()
+            })
+            .collect();
+        let outer_files: Vec<Vec<u8>> = inner_trees
+            .iter()
+            .map(|tree| tree.root_hash().into_bytes())
+            .collect();
+        let outer_tree = MerkleTree::new(&outer_files).unwrap();
+        let outer_root = outer_tree.root_hash();
+
+        let (outer_leaf_idx, inner_leaf_idx) = (1, 3);
+        let nested_proof = NestedProof {
+            inner: inner_trees[outer_leaf_idx]
+                .generate_merkle_proof(inner_leaf_idx)
+                .unwrap(),
+            outer: outer_tree.generate_merkle_proof(outer_leaf_idx).unwrap(),
+            inner_leaf_idx,
+            outer_leaf_idx,
+        };
+
+        let leaf_hash = calc_sha256(&[1, 3]);
+        assert!(verify_nested(&outer_root, &leaf_hash, &nested_proof).unwrap());
+
+        // A leaf of a different inner tree does not verify
+        assert!(!verify_nested(&outer_root, &calc_sha256(&[0, 3]), &nested_proof).unwrap());
+
+        // Neither does a proof pointing at the wrong outer leaf
+        let wrong_outer = NestedProof {
+            outer_leaf_idx: 2,
+            ..nested_proof
+        };
+        assert!(verify_nested(&outer_root, &leaf_hash, &wrong_outer).is_err());
+    }
+}
@@ -0,0 +1,1424 @@
+use crate::{
+    checked_idx, expected_proof_len, HashAlgorithm, HashMode, MerkleErrorKind, MerkleTree,
+    MerkleTreeError, OrderingMode, TreeNode,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use tracing::info;
+use util::{bytes_to_hex, calc_sha256, hex_to_bytes};
+
+// Side tells on which side of the running hash the sibling sits, which decides the concatenation order
+// while folding the proof: a `Right` sibling is appended (`curr || sibling`), a `Left` sibling is prepended (`sibling || curr`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// A single step of a Merkle proof: the hash of the sibling node and the side it sits on.
+// Unlike `TreeNode` it carries no children, so a proof stays small and does not depend on the tree layout.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProofStep {
+    pub hash: String,
+    pub side: Side,
+}
+
+// ProofFile is a self-contained proof that can be stored on disk and verified later using only the
+// bytes of the target leaf. Steps are ordered from the leaf up to the root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProofFile {
+    pub root_hash: String,
+    pub leaf_idx: usize,
+    pub leaf_count: usize,
+    pub steps: Vec<ProofStep>,
+    // Hash function of the tree the proof was generated from; SHA-256 for proof files written before the field existed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_algorithm: HashAlgorithm,
+    // Hashing rule of that tree; `Classic` for proof files written before the field existed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_mode: HashMode,
+    // Concatenation order of that tree; `Positional` for proof files written before the field existed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordering: OrderingMode,
+}
+
+impl ProofFile {
+    // Builds a proof file from the sibling nodes returned by `generate_merkle_proof` (or received over gRPC).
+    // The side of each sibling is derived from its index range relative to the range covered so far, and the
+    // leaf count is the right edge of the final range (which always spans the whole tree).
+    // The proof is assumed to be classic, positional SHA-256; set `hash_algorithm`, `hash_mode` and `ordering` afterwards
+    // for other trees.
+    pub fn from_nodes(
+        root_hash: String,
+        leaf_idx: usize,
+        proofs: &[impl Borrow<TreeNode>],
+    ) -> Result<ProofFile, MerkleTreeError> {
+        let mut left_idx = leaf_idx;
+        let mut right_idx = leaf_idx;
+        let mut steps = Vec::with_capacity(proofs.len());
+
+        for proof in proofs.iter().map(Borrow::borrow) {
+            // A single-leaf tree returns the leaf itself as its proof, which contributes no step
+            if proof.left_idx == leaf_idx && proof.right_idx == leaf_idx {
+                continue;
+            }
+
+            // `checked_add` keeps a node claiming to end at `usize::MAX` from overflowing; it is simply not adjacent
+            let side = if right_idx.checked_add(1) == Some(proof.left_idx) {
+                Side::Right
+            } else if proof.right_idx.checked_add(1) == Some(left_idx) {
+                Side::Left
+            } else {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::ProofStructureInvalid,
+                    "proof node is not adjacent to the leaf path",
+                ));
+            };
+
+            steps.push(ProofStep {
+                hash: proof.hash.clone(),
+                side,
+            });
+
+            left_idx = usize::min(left_idx, proof.left_idx);
+            right_idx = usize::max(right_idx, proof.right_idx);
+        }
+
+        if left_idx != 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "proof does not reach the root",
+            ));
+        }
+
+        Ok(ProofFile {
+            root_hash,
+            leaf_idx,
+            leaf_count: checked_idx(
+                right_idx.checked_add(1),
+                MerkleErrorKind::ProofStructureInvalid,
+            )?,
+            steps,
+            hash_algorithm: HashAlgorithm::Sha256,
+            hash_mode: HashMode::Classic,
+            ordering: OrderingMode::Positional,
+        })
+    }
+
+    // Verifies the proof using only the bytes of the leaf it was generated for.
+    pub fn verify(&self, leaf: &[u8]) -> Result<bool, MerkleTreeError> {
+        verify_steps(
+            self.hash_algorithm,
+            self.hash_mode,
+            self.ordering,
+            &self.root_hash,
+            &self
+                .hash_algorithm
+                .hash_leaf(self.hash_mode, self.leaf_idx, leaf),
+            self.leaf_idx,
+            self.leaf_count,
+            &self.steps,
+        )
+    }
+
+    // Running hash of the fold, starting with the hash of `leaf` and followed by the hash after each step, so the last
+    // entry is the root the proof leads to. Comparing the entries with the tree shows at which level a proof diverges.
+    pub fn running_hashes(&self, leaf: &[u8]) -> Vec<String> {
+        let mut hashes = Vec::with_capacity(self.steps.len() + 1);
+        hashes.push(
+            self.hash_algorithm
+                .hash_leaf(self.hash_mode, self.leaf_idx, leaf),
+        );
+        for step in &self.steps {
+            let curr = &hashes[hashes.len() - 1];
+            let next = match step.side {
+                Side::Right => {
+                    self.hash_algorithm
+                        .hash_pair(self.hash_mode, self.ordering, curr, &step.hash)
+                }
+                Side::Left => {
+                    self.hash_algorithm
+                        .hash_pair(self.hash_mode, self.ordering, &step.hash, curr)
+                }
+            };
+            hashes.push(next);
+        }
+        hashes
+    }
+
+    // `map_err` converts the serde error into our own error type so callers only deal with `MerkleTreeError`
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, MerkleTreeError> {
+        serde_json::to_string(self)
+            .map_err(|e| MerkleTreeError::new(MerkleErrorKind::Internal, &e.to_string()))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<ProofFile, MerkleTreeError> {
+        serde_json::from_str(json).map_err(|e| {
+            MerkleTreeError::new(MerkleErrorKind::ProofStructureInvalid, &e.to_string())
+        })
+    }
+}
+
+// ProofVerifier folds a proof one sibling at a time, for verifiers that receive proof nodes over a stream and cannot
+// buffer the whole proof. The fold order and direction rule are the same as in `MerkleTree::verify_merkle_proof`.
+#[derive(Clone, Debug)]
+pub struct ProofVerifier {
+    hash: String,
+    left_idx: usize,
+    right_idx: usize,
+    leaf_count: usize,
+    steps_left: usize,
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+}
+
+impl ProofVerifier {
+    pub fn new(leaf_hash: &str, leaf_idx: usize, leaf_count: usize) -> ProofVerifier {
+        ProofVerifier {
+            hash: leaf_hash.to_string(),
+            left_idx: leaf_idx,
+            right_idx: leaf_idx,
+            leaf_count,
+            steps_left: expected_proof_len(leaf_count, leaf_idx),
+            hash_algorithm: HashAlgorithm::Sha256,
+            hash_mode: HashMode::Classic,
+            ordering: OrderingMode::Positional,
+        }
+    }
+
+    // Folds with the given hashing rule instead of `Classic`
+    pub fn with_hash_mode(mut self, hash_mode: HashMode) -> ProofVerifier {
+        self.hash_mode = hash_mode;
+        self
+    }
+
+    // Folds with the given concatenation order instead of `Positional`
+    pub fn with_ordering(mut self, ordering: OrderingMode) -> ProofVerifier {
+        self.ordering = ordering;
+        self
+    }
+
+    // Folds with `hash_algorithm` instead of the default SHA-256
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> ProofVerifier {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    // Folds the next sibling (ordered from the leaf up) into the running hash
+    pub fn feed(&mut self, node: &TreeNode) -> Result<(), MerkleTreeError> {
+        // A single-leaf tree sends the leaf itself as its proof, which contributes no step
+        if self.leaf_count == 1
+            && node.left_idx == self.left_idx
+            && node.right_idx == self.right_idx
+        {
+            return Ok(());
+        }
+
+        if self.steps_left == 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "unexpected proof length",
+            ));
+        }
+
+        // Both nodes must be adjacent children of the same parent
+        if self.right_idx.checked_add(1) != Some(node.left_idx)
+            && node.right_idx.checked_add(1) != Some(self.left_idx)
+        {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "proof node is not adjacent to the leaf path",
+            ));
+        }
+
+        let parent_left_idx = usize::min(self.left_idx, node.left_idx);
+        let parent_right_idx = usize::max(self.right_idx, node.right_idx);
+
+        // The current node is the left child iff it starts where the parent starts
+        self.hash = if self.left_idx == parent_left_idx {
+            self.hash_algorithm
+                .hash_pair(self.hash_mode, self.ordering, &self.hash, &node.hash)
+        } else {
+            self.hash_algorithm
+                .hash_pair(self.hash_mode, self.ordering, &node.hash, &self.hash)
+        };
+
+        self.left_idx = parent_left_idx;
+        self.right_idx = parent_right_idx;
+        self.steps_left -= 1;
+        Ok(())
+    }
+
+    // Completes the verification once every sibling has been fed
+    pub fn finish(self, expected_root: &str) -> Result<bool, MerkleTreeError> {
+        if self.steps_left != 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "unexpected proof length",
+            ));
+        }
+
+        Ok(self.hash == expected_root)
+    }
+}
+
+// Verifies a proof without the tree: only the root hash, the hash of the leaf, its position and the number of leaves
+// are needed. Steps are ordered from the leaf up to the root and hashed with SHA-256.
+pub fn verify(
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    verify_with_algorithm(
+        HashAlgorithm::Sha256,
+        root_hash,
+        leaf_hash,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// Same as `verify`, but accepts the proof if it folds to any of `root_hashes`, e.g. while a verifier still trusts the
+// old root during a tree update. The proof is folded once and the result compared with every root. An empty list
+// accepts nothing.
+pub fn verify_any(
+    root_hashes: &[String],
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    let computed_root = compute_root_from_proof(leaf_hash, leaf_idx, leaf_count, proof)?;
+
+    if !root_hashes.contains(&computed_root) {
+        info!(
+            "[merkle-tree] proof for leaf {} folds to root {}, which is none of the {} accepted roots",
+            leaf_idx,
+            computed_root,
+            root_hashes.len()
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// Same as `verify`, but the steps are given as the JSON array of `ProofStep`s (`[{"hash": "..", "side": "Left"}, ..]`),
+// e.g. as received by a light client. No I/O is involved, so this is the entry point wrapped for WebAssembly.
+#[cfg(feature = "serde")]
+pub fn verify_json(
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof_json: &str,
+) -> Result<bool, MerkleTreeError> {
+    let proof: Vec<ProofStep> = serde_json::from_str(proof_json).map_err(|e| {
+        MerkleTreeError::new(MerkleErrorKind::ProofStructureInvalid, &e.to_string())
+    })?;
+    verify(root_hash, leaf_hash, leaf_idx, leaf_count, &proof)
+}
+
+// Same as `verify`, but the proof is given as the sibling nodes returned by `generate_merkle_proof` and no leaf count is
+// passed in. `verify` trusts its `leaf_count` to decide the concatenation order, so a forged count can make a proof of
+// one position pass for another (e.g. the last leaf of 3 for the last leaf of 2). Here the count is the right edge of
+// the last sibling, and every sibling range must be exactly the one the midpoint split gives for that count, so the
+// order follows from the ranges the proof was generated with. SHA-256 and the classic rule are used, like `verify`.
+pub fn verify_nodes(
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    proofs: &[impl Borrow<TreeNode>],
+) -> Result<bool, MerkleTreeError> {
+    Ok(fold_proof_nodes(leaf_hash, leaf_idx, proofs)? == root_hash)
+}
+
+// Same as `verify` for trees built with another hash algorithm
+pub fn verify_with_algorithm(
+    hash_algorithm: HashAlgorithm,
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    verify_steps(
+        hash_algorithm,
+        HashMode::Classic,
+        OrderingMode::Positional,
+        root_hash,
+        leaf_hash,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// Same as `verify`, but takes the file itself and hashes it, so the leaf hash cannot be computed with another algorithm
+// than the one the proof is checked with
+pub fn verify_file(
+    root_hash: &str,
+    file: &[u8],
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    verify_file_with_algorithm(
+        HashAlgorithm::Sha256,
+        root_hash,
+        file,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// Same as `verify_file` for trees built with another hash algorithm
+pub fn verify_file_with_algorithm(
+    hash_algorithm: HashAlgorithm,
+    root_hash: &str,
+    file: &[u8],
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    verify_with_algorithm(
+        hash_algorithm,
+        root_hash,
+        &hash_algorithm.hash_leaf(HashMode::Classic, leaf_idx, file),
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// Shared by the free functions and the `verify` methods of the proof types, which also know their hash mode and
+// ordering
+#[allow(clippy::too_many_arguments)]
+fn verify_steps(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<bool, MerkleTreeError> {
+    let computed_root = compute_root(
+        hash_algorithm,
+        hash_mode,
+        ordering,
+        leaf_hash,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )?;
+
+    if computed_root != root_hash {
+        info!(
+            "[merkle-tree] proof for leaf {} folds to root {} but {} was expected",
+            leaf_idx, computed_root, root_hash
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// Returns the root hash that the SHA-256 proof of `leaf_hash` at `leaf_idx` folds to, instead of only whether it matches
+// an expected root. Comparing it with the expected root shows what a failing proof actually commits to.
+pub fn compute_root_from_proof(
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<String, MerkleTreeError> {
+    compute_root_from_proof_with_algorithm(
+        HashAlgorithm::Sha256,
+        leaf_hash,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// Same as `compute_root_from_proof` for trees built with another hash algorithm
+pub fn compute_root_from_proof_with_algorithm(
+    hash_algorithm: HashAlgorithm,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<String, MerkleTreeError> {
+    compute_root(
+        hash_algorithm,
+        HashMode::Classic,
+        OrderingMode::Positional,
+        leaf_hash,
+        leaf_idx,
+        leaf_count,
+        proof,
+    )
+}
+
+// The shape of a tree with `leaf_count` leaves is fixed by the midpoint split, so the number of steps and the side of
+// every sibling are checked against it before folding.
+fn compute_root(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof: &[ProofStep],
+) -> Result<String, MerkleTreeError> {
+    // Like `verify_merkle_proof` on `MerkleTree::empty()`: an empty tree has no leaf a proof could lead from
+    if leaf_count == 0 {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::EmptyInput,
+            "an empty tree has no leaves to prove",
+        ));
+    }
+    if leaf_idx >= leaf_count {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::IndexOutOfBounds,
+            "index out of bounds",
+        ));
+    }
+
+    let sides = expected_sides(leaf_count, leaf_idx);
+    if proof.len() != sides.len() {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "unexpected proof length",
+        ));
+    }
+    if proof
+        .iter()
+        .zip(&sides)
+        .any(|(step, side)| step.side != *side)
+    {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "proof step on the wrong side",
+        ));
+    }
+
+    Ok(fold_steps(
+        hash_algorithm,
+        hash_mode,
+        ordering,
+        leaf_hash,
+        proof,
+    ))
+}
+
+// Folds proof steps into the root hash they commit to
+fn fold_steps(
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+    leaf_hash: &str,
+    steps: &[ProofStep],
+) -> String {
+    // `fold` threads the running hash through every step, starting from the leaf hash
+    steps
+        .iter()
+        .fold(leaf_hash.to_string(), |curr, step| match step.side {
+            Side::Right => hash_algorithm.hash_pair(hash_mode, ordering, &curr, &step.hash),
+            Side::Left => hash_algorithm.hash_pair(hash_mode, ordering, &step.hash, &curr),
+        })
+}
+
+// MerkleProof is the authentication path of a single leaf, without the root it leads to: the verifier supplies the
+// trusted root. Steps are ordered from the leaf up to the root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MerkleProof {
+    pub leaf_idx: usize,
+    pub leaf_count: usize,
+    pub steps: Vec<ProofStep>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_algorithm: HashAlgorithm,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_mode: HashMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordering: OrderingMode,
+}
+
+impl MerkleProof {
+    // Checks that `leaf` sits at `leaf_idx` in the tree whose root is `root_hash`
+    pub fn verify(&self, root_hash: &str, leaf: &[u8]) -> Result<bool, MerkleTreeError> {
+        verify_steps(
+            self.hash_algorithm,
+            self.hash_mode,
+            self.ordering,
+            root_hash,
+            &self
+                .hash_algorithm
+                .hash_leaf(self.hash_mode, self.leaf_idx, leaf),
+            self.leaf_idx,
+            self.leaf_count,
+            &self.steps,
+        )
+    }
+
+    // Encodes the proof as one flat, self-describing byte blob, e.g. for a binary key-value store. Integers are big endian:
+    //   version (u8) | algorithm (u8) | hash mode (u8) | ordering (u8) | leaf_idx (u64) | leaf_count (u64) | steps (u32)
+    // followed, for every step, by its side (u8, 0 = left, 1 = right) and the raw digest (`digest_len` bytes).
+    // Digests are stored as bytes rather than hex, so the blob is well under half the size of the JSON.
+    // Fails with `ProofStructureInvalid` if a step hash is not a hex digest of the proof's algorithm. Proofs generated
+    // from a tree always are, but a proof deserialized from untrusted JSON may not be.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleTreeError> {
+        let digest_len = self.hash_algorithm.digest_len();
+        let mut bytes = Vec::with_capacity(PROOF_HEADER_LEN + self.steps.len() * (1 + digest_len));
+
+        bytes.push(PROOF_BYTES_VERSION);
+        bytes.push(algorithm_code(self.hash_algorithm));
+        bytes.push(match self.hash_mode {
+            HashMode::Classic => 0,
+            HashMode::Rfc6962 => 1,
+            HashMode::Indexed => 2,
+        });
+        bytes.push(match self.ordering {
+            OrderingMode::Positional => 0,
+            OrderingMode::SortedPair => 1,
+        });
+        bytes.extend_from_slice(&(self.leaf_idx as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_be_bytes());
+        let step_count = u32::try_from(self.steps.len()).map_err(|_| {
+            MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "too many proof steps",
+            )
+        })?;
+        bytes.extend_from_slice(&step_count.to_be_bytes());
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let digest = hex_to_bytes(&step.hash)
+                .filter(|digest| digest.len() == digest_len)
+                .ok_or_else(|| {
+                    MerkleTreeError::new(
+                        MerkleErrorKind::ProofStructureInvalid,
+                        &format!(
+                            "proof step {} is not a {} hex digest",
+                            i, self.hash_algorithm
+                        ),
+                    )
+                })?;
+            bytes.push(match step.side {
+                Side::Left => 0,
+                Side::Right => 1,
+            });
+            bytes.extend_from_slice(&digest);
+        }
+        Ok(bytes)
+    }
+
+    // Parses a blob written by `to_bytes`. The step count is checked against the remaining length before anything is
+    // allocated, so a corrupted count cannot make the reader reserve gigabytes. Whether the steps fit the leaf position
+    // is left to `verify`, like for every other proof format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleProof, MerkleTreeError> {
+        let invalid = |msg: &str| MerkleTreeError::new(MerkleErrorKind::ProofStructureInvalid, msg);
+
+        if bytes.len() < PROOF_HEADER_LEN {
+            return Err(invalid("proof bytes are shorter than the header"));
+        }
+        let (header, body) = bytes.split_at(PROOF_HEADER_LEN);
+
+        if header[0] != PROOF_BYTES_VERSION {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!(
+                    "unsupported proof bytes version {} (expected {})",
+                    header[0], PROOF_BYTES_VERSION
+                ),
+            ));
+        }
+        let hash_algorithm = match header[1] {
+            0 => HashAlgorithm::Sha256,
+            1 => HashAlgorithm::Sha512,
+            2 => HashAlgorithm::Blake3,
+            code => return Err(invalid(&format!("unknown hash algorithm code {}", code))),
+        };
+        let hash_mode = match header[2] {
+            0 => HashMode::Classic,
+            1 => HashMode::Rfc6962,
+            2 => HashMode::Indexed,
+            code => return Err(invalid(&format!("unknown hash mode code {}", code))),
+        };
+        let ordering = match header[3] {
+            0 => OrderingMode::Positional,
+            1 => OrderingMode::SortedPair,
+            code => return Err(invalid(&format!("unknown ordering code {}", code))),
+        };
+
+        let to_usize = |be: &[u8]| {
+            let value = u64::from_be_bytes(be.try_into().expect("8 byte slice"));
+            usize::try_from(value).map_err(|_| invalid("index does not fit in usize"))
+        };
+        let leaf_idx = to_usize(&header[4..12])?;
+        let leaf_count = to_usize(&header[12..20])?;
+        let step_count =
+            u32::from_be_bytes(header[20..24].try_into().expect("4 byte slice")) as usize;
+
+        let step_len = 1 + hash_algorithm.digest_len();
+        if step_count.checked_mul(step_len) != Some(body.len()) {
+            return Err(invalid(&format!(
+                "expected {} steps of {} bytes, found {} bytes",
+                step_count,
+                step_len,
+                body.len()
+            )));
+        }
+
+        let steps = body
+            .chunks_exact(step_len)
+            .map(|chunk| {
+                let side = match chunk[0] {
+                    0 => Side::Left,
+                    1 => Side::Right,
+                    code => return Err(invalid(&format!("unknown side code {}", code))),
+                };
+                Ok(ProofStep {
+                    hash: bytes_to_hex(&chunk[1..]),
+                    side,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(MerkleProof {
+            leaf_idx,
+            leaf_count,
+            steps,
+            hash_algorithm,
+            hash_mode,
+            ordering,
+        })
+    }
+}
+
+// Layout version of `MerkleProof::to_bytes`. Bump it whenever the byte layout changes.
+const PROOF_BYTES_VERSION: u8 = 1;
+
+// version, algorithm, hash mode and ordering bytes, leaf_idx and leaf_count as u64, step count as u32
+const PROOF_HEADER_LEN: usize = 4 + 8 + 8 + 4;
+
+// The byte codes are part of the format, so they are spelled out instead of relying on the enum declaration order
+fn algorithm_code(hash_algorithm: HashAlgorithm) -> u8 {
+    match hash_algorithm {
+        HashAlgorithm::Sha256 => 0,
+        HashAlgorithm::Sha512 => 1,
+        HashAlgorithm::Blake3 => 2,
+    }
+}
+
+// Attestation is everything a verifier needs to check one leaf in a single serializable object: the leaf hash, its
+// position, the ordered sibling steps and the root they fold into. Unlike `ProofFile` it carries the leaf hash, so
+// `verify` needs no input at all.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Attestation {
+    pub leaf_hash: String,
+    pub leaf_idx: usize,
+    pub leaf_count: usize,
+    pub steps: Vec<ProofStep>,
+    pub root_hash: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_algorithm: HashAlgorithm,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_mode: HashMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordering: OrderingMode,
+}
+
+impl Attestation {
+    // Recomputes the root from the leaf hash and the steps and compares it with the attested root.
+    // A structurally invalid attestation (wrong step count or sides, index out of range) does not verify.
+    pub fn verify(&self) -> bool {
+        verify_steps(
+            self.hash_algorithm,
+            self.hash_mode,
+            self.ordering,
+            &self.root_hash,
+            &self.leaf_hash,
+            self.leaf_idx,
+            self.leaf_count,
+            &self.steps,
+        )
+        .unwrap_or(false)
+    }
+}
+
+// Replays the midpoint split from the root down to `leaf_idx` and returns the side of the sibling at every level,
+// ordered from the leaf up like the proof steps.
+fn expected_sides(leaf_count: usize, leaf_idx: usize) -> Vec<Side> {
+    let (mut left, mut right) = (0, leaf_count - 1);
+    let mut sides = Vec::new();
+
+    while left < right {
+        let mid = left + (right - left) / 2;
+        if leaf_idx <= mid {
+            sides.push(Side::Right);
+            right = mid;
+        } else {
+            sides.push(Side::Left);
+            left = mid + 1;
+        }
+    }
+
+    sides.reverse();
+    sides
+}
+
+// Replays the midpoint split like `expected_sides` and returns the index range of the sibling at every level, ordered
+// from the leaf up
+fn expected_sibling_ranges(leaf_count: usize, leaf_idx: usize) -> Vec<(usize, usize)> {
+    let (mut left, mut right) = (0, leaf_count - 1);
+    let mut ranges = Vec::new();
+
+    while left < right {
+        let mid = left + (right - left) / 2;
+        if leaf_idx <= mid {
+            ranges.push((mid + 1, right));
+            right = mid;
+        } else {
+            ranges.push((left, mid));
+            left = mid + 1;
+        }
+    }
+
+    ranges.reverse();
+    ranges
+}
+
+// Folds proof nodes (ordered from the leaf up, as returned by `generate_merkle_proof`) into the root hash they commit to,
+// using SHA-256 and the same direction rule as `verify_merkle_proof`. Unlike the tree method it needs no tree: the shape is
+// taken from the node ranges, whose final right edge gives the leaf count. Proofs that are not adjacent, do not reach the
+// root, or whose ranges differ from the midpoint split for that leaf count are rejected.
+pub(crate) fn fold_proof_nodes(
+    leaf_hash: &str,
+    leaf_idx: usize,
+    proofs: &[impl Borrow<TreeNode>],
+) -> Result<String, MerkleTreeError> {
+    let mut hash = leaf_hash.to_string();
+    let (mut left_idx, mut right_idx) = (leaf_idx, leaf_idx);
+    let mut ranges = Vec::with_capacity(proofs.len());
+
+    for proof in proofs.iter().map(Borrow::borrow) {
+        // A single-leaf tree returns the leaf itself as its proof, which contributes no step
+        if proof.left_idx == leaf_idx && proof.right_idx == leaf_idx {
+            continue;
+        }
+
+        // The current node is the left child iff the proof node starts right after it
+        hash = if right_idx.checked_add(1) == Some(proof.left_idx) {
+            calc_sha256(&[hash.as_bytes(), proof.hash.as_bytes()].concat())
+        } else if proof.right_idx.checked_add(1) == Some(left_idx) {
+            calc_sha256(&[proof.hash.as_bytes(), hash.as_bytes()].concat())
+        } else {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ProofStructureInvalid,
+                "proof node is not adjacent to the leaf path",
+            ));
+        };
+
+        left_idx = usize::min(left_idx, proof.left_idx);
+        right_idx = usize::max(right_idx, proof.right_idx);
+        ranges.push((proof.left_idx, proof.right_idx));
+    }
+
+    let leaf_count = checked_idx(
+        right_idx.checked_add(1),
+        MerkleErrorKind::ProofStructureInvalid,
+    )?;
+    if left_idx != 0 {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "proof does not reach the root",
+        ));
+    }
+    if ranges != expected_sibling_ranges(leaf_count, leaf_idx) {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "proof ranges do not match the tree shape",
+        ));
+    }
+
+    Ok(hash)
+}
+
+impl MerkleTree {
+    // Generates a self-contained `ProofFile` for the given leaf index
+    pub fn generate_proof_file(&self, leaf_idx: usize) -> Result<ProofFile, MerkleTreeError> {
+        let proofs = self.generate_merkle_proof(leaf_idx)?;
+        let mut proof_file = ProofFile::from_nodes(self.root_hash(), leaf_idx, &proofs)?;
+        proof_file.hash_algorithm = self.hash_algorithm;
+        proof_file.hash_mode = self.hash_mode;
+        proof_file.ordering = self.ordering;
+        Ok(proof_file)
+    }
+
+    // Generates the `MerkleProof` of the given leaf index
+    pub fn generate_proof(&self, leaf_idx: usize) -> Result<MerkleProof, MerkleTreeError> {
+        let proof_file = self.generate_proof_file(leaf_idx)?;
+        Ok(MerkleProof {
+            leaf_idx,
+            leaf_count: proof_file.leaf_count,
+            steps: proof_file.steps,
+            hash_algorithm: proof_file.hash_algorithm,
+            hash_mode: proof_file.hash_mode,
+            ordering: proof_file.ordering,
+        })
+    }
+
+    // The proof of `leaf_idx` in the minimal form most other tooling expects: `(sibling_hash, is_right)` pairs ordered
+    // from the leaf up, where `is_right` tells whether the sibling is the right child. A single-leaf tree has an empty
+    // path.
+    pub fn audit_path(&self, leaf_idx: usize) -> Result<Vec<(String, bool)>, MerkleTreeError> {
+        let proof_file = self.generate_proof_file(leaf_idx)?;
+        Ok(proof_file
+            .steps
+            .into_iter()
+            .map(|step| (step.hash, step.side == Side::Right))
+            .collect())
+    }
+
+    // For clients that hold the file bytes but not the index: the content is hashed with the tree's algorithm and
+    // looked up with `find_index_by_hash`. Returns the leaf index that was found together with its proof, or a
+    // `LeafNotFound` error if no leaf has this content. With duplicate contents, the leftmost leaf is proven.
+    // In `HashMode::Indexed` the leaf hash depends on the index, so the content is instead rehashed at every leaf.
+    pub fn generate_proof_for_content(
+        &self,
+        file: &[u8],
+    ) -> Result<(usize, MerkleProof), MerkleTreeError> {
+        let found = match self.hash_mode {
+            HashMode::Indexed => self.root.as_deref().and_then(|root| {
+                root.iter()
+                    .filter(|node| node.left_idx == node.right_idx)
+                    .find(|leaf| {
+                        leaf.hash
+                            == self
+                                .hash_algorithm
+                                .hash_leaf(self.hash_mode, leaf.left_idx, file)
+                    })
+                    .map(|leaf| leaf.left_idx)
+            }),
+            _ => self.find_index_by_hash(&self.hash_algorithm.hash_leaf(self.hash_mode, 0, file)),
+        };
+        let leaf_idx = match found {
+            Some(leaf_idx) => leaf_idx,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::LeafNotFound,
+                    "content is not a leaf of the tree",
+                ))
+            }
+        };
+
+        Ok((leaf_idx, self.generate_proof(leaf_idx)?))
+    }
+
+    // Generates an `Attestation` for the given leaf. The root is computed from the authentication path and checked
+    // against the tree root, so a returned attestation always verifies.
+    pub fn generate_full_attestation(
+        &self,
+        leaf_idx: usize,
+    ) -> Result<Attestation, MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "empty root",
+                ))
+            }
+        };
+        let leaf_hash = crate::find_leaf(root, leaf_idx)?.hash.clone();
+        let proof_file = self.generate_proof_file(leaf_idx)?;
+
+        let root_hash = fold_steps(
+            self.hash_algorithm,
+            self.hash_mode,
+            self.ordering,
+            &leaf_hash,
+            &proof_file.steps,
+        );
+        if root_hash != root.hash {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::Internal,
+                "computed root does not match the tree root",
+            ));
+        }
+
+        Ok(Attestation {
+            leaf_hash,
+            leaf_idx,
+            leaf_count: proof_file.leaf_count,
+            steps: proof_file.steps,
+            root_hash,
+            hash_algorithm: self.hash_algorithm,
+            hash_mode: self.hash_mode,
+            ordering: self.ordering,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proof_for_content_finds_the_leaf() {
+        let files: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i; 3]).collect();
+        let tree = MerkleTree::new_with_algorithm(&files, HashAlgorithm::Sha512).unwrap();
+
+        for (file_idx, file) in files.iter().enumerate() {
+            let (leaf_idx, proof) = tree.generate_proof_for_content(file).unwrap();
+            assert_eq!(leaf_idx, file_idx);
+            assert!(proof.verify(&tree.root_hash(), file).unwrap());
+        }
+
+        let err = tree.generate_proof_for_content(b"missing").unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::LeafNotFound);
+    }
+
+    #[test]
+    fn computed_root_shows_what_a_proof_commits_to() {
+        let files: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proof = merkle_tree.generate_proof(idx).unwrap();
+            let leaf_hash = calc_sha256(file);
+            assert_eq!(
+                compute_root_from_proof(&leaf_hash, idx, files.len(), &proof.steps).unwrap(),
+                root_hash
+            );
+        }
+
+        // A proof for the wrong leaf folds to a different root, which is now visible to the caller
+        let proof = merkle_tree.generate_proof(0).unwrap();
+        let computed =
+            compute_root_from_proof(&calc_sha256(b"forged"), 0, files.len(), &proof.steps).unwrap();
+        assert_ne!(computed, root_hash);
+        assert!(!verify(
+            &root_hash,
+            &calc_sha256(b"forged"),
+            0,
+            files.len(),
+            &proof.steps
+        )
+        .unwrap());
+
+        // Structural errors are still reported as errors
+        assert!(compute_root_from_proof(&root_hash, 0, files.len(), &proof.steps[1..]).is_err());
+        assert!(
+            compute_root_from_proof(&root_hash, files.len(), files.len(), &proof.steps).is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn proof_file_round_trip() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'E').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proof = merkle_tree.generate_proof_file(idx).unwrap();
+            assert_eq!(proof.leaf_count, files.len());
+
+            // The proof survives a JSON round-trip and verifies from the leaf bytes alone
+            let proof = ProofFile::from_json(&proof.to_json().unwrap()).unwrap();
+            assert!(
+                proof.verify(file).unwrap(),
+                "proof failed for index {}",
+                idx
+            );
+
+            // A different leaf must not verify against the same proof
+            assert!(!proof.verify(b"tampered").unwrap());
+        }
+
+        // Proofs of trees built with another algorithm carry it, so they verify with the matching function
+        for hash_algorithm in [HashAlgorithm::Sha512, HashAlgorithm::Blake3] {
+            let merkle_tree = MerkleTree::new_with_algorithm(&files, hash_algorithm).unwrap();
+            let proof = merkle_tree.generate_proof_file(3).unwrap();
+            let proof = ProofFile::from_json(&proof.to_json().unwrap()).unwrap();
+            assert_eq!(proof.hash_algorithm, hash_algorithm);
+            assert!(proof.verify(&files[3]).unwrap());
+        }
+
+        // A single-file tree has no proof steps
+        let merkle_tree = MerkleTree::new(&[b"A".to_vec()]).unwrap();
+        let proof = merkle_tree.generate_proof_file(0).unwrap();
+        assert!(proof.steps.is_empty());
+        assert!(proof.verify(b"A").unwrap());
+    }
+
+    #[test]
+    fn free_verify_needs_no_tree() {
+        for n in [1usize, 2, 5, 8] {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            for (idx, file) in files.iter().enumerate() {
+                let proof = merkle_tree.generate_proof_file(idx).unwrap();
+                let leaf_hash = calc_sha256(file);
+                assert!(verify(&root_hash, &leaf_hash, idx, n, &proof.steps).unwrap());
+                assert!(!verify(&root_hash, &calc_sha256(b"x"), idx, n, &proof.steps).unwrap());
+            }
+        }
+
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+        let leaf_hash = calc_sha256(&files[0]);
+        let mut steps = merkle_tree.generate_proof_file(0).unwrap().steps;
+
+        // Out of range index, a proof with the wrong length and a flipped side are all rejected
+        assert!(verify(&root_hash, &leaf_hash, 5, 5, &steps).is_err());
+        assert!(verify(&root_hash, &leaf_hash, 0, 5, &steps[1..]).is_err());
+        steps[0].side = Side::Left;
+        assert!(verify(&root_hash, &leaf_hash, 0, 5, &steps).is_err());
+    }
+
+    #[test]
+    fn attestations_verify_for_every_leaf() {
+        let files: Vec<Vec<u8>> = (b'A'..=b'G').map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            let attestation = merkle_tree.generate_full_attestation(idx).unwrap();
+            assert_eq!(attestation.leaf_hash, calc_sha256(file));
+            assert_eq!(attestation.root_hash, merkle_tree.root_hash());
+            assert!(attestation.verify(), "attestation failed for index {}", idx);
+
+            // A tampered leaf no longer folds into the attested root
+            let tampered = Attestation {
+                leaf_hash: calc_sha256(b"tampered"),
+                ..attestation.clone()
+            };
+            assert!(!tampered.verify());
+
+            // A leaf index that does not match the steps is rejected too
+            let moved = Attestation {
+                leaf_idx: (idx + 1) % files.len(),
+                ..attestation
+            };
+            assert!(!moved.verify());
+        }
+
+        assert!(merkle_tree.generate_full_attestation(files.len()).is_err());
+    }
+
+    #[test]
+    fn incremental_verification_matches_batch() {
+        for n in [1usize, 2, 5, 8] {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            for (idx, file) in files.iter().enumerate() {
+                let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+                let batch = merkle_tree
+                    .verify_merkle_proof(&root_hash, &calc_sha256(file), idx, &proofs)
+                    .unwrap();
+
+                let mut verifier = ProofVerifier::new(&calc_sha256(file), idx, n);
+                for node in &proofs {
+                    verifier.feed(node).unwrap();
+                }
+                assert_eq!(verifier.finish(&root_hash).unwrap(), batch);
+                assert!(batch);
+
+                // A tampered leaf folds to a different root
+                let mut verifier = ProofVerifier::new(&calc_sha256(b"tampered"), idx, n);
+                for node in &proofs {
+                    verifier.feed(node).unwrap();
+                }
+                assert!(!verifier.finish(&root_hash).unwrap());
+            }
+        }
+
+        // Finishing before all siblings were fed is rejected
+        let files: Vec<Vec<u8>> = (0..4u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let proofs = merkle_tree.generate_merkle_proof(0).unwrap();
+        let mut verifier = ProofVerifier::new(&calc_sha256(&files[0]), 0, 4);
+        verifier.feed(&proofs[0]).unwrap();
+        assert!(verifier.finish(&merkle_tree.root_hash()).is_err());
+    }
+
+    #[test]
+    fn verify_file_hashes_with_the_proof_algorithm() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c; 4]).collect();
+
+        for hash_algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let merkle_tree = MerkleTree::new_with_algorithm(&files, hash_algorithm).unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            for (idx, file) in files.iter().enumerate() {
+                let steps = merkle_tree.generate_proof_file(idx).unwrap().steps;
+                assert!(verify_file_with_algorithm(
+                    hash_algorithm,
+                    &root_hash,
+                    file,
+                    idx,
+                    files.len(),
+                    &steps
+                )
+                .unwrap());
+                assert!(!verify_file_with_algorithm(
+                    hash_algorithm,
+                    &root_hash,
+                    b"other",
+                    idx,
+                    files.len(),
+                    &steps
+                )
+                .unwrap());
+
+                // The tree method picks the algorithm up from the tree
+                let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+                assert!(merkle_tree
+                    .verify_file(&root_hash, file, idx, &proofs)
+                    .unwrap());
+            }
+        }
+
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let steps = merkle_tree.generate_proof_file(2).unwrap().steps;
+        assert!(verify_file(&merkle_tree.root_hash(), &files[2], 2, files.len(), &steps).unwrap());
+    }
+
+    #[test]
+    fn running_hashes_end_at_the_root() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            let proof_file = merkle_tree.generate_proof_file(idx).unwrap();
+            let hashes = proof_file.running_hashes(file);
+            assert_eq!(hashes.len(), proof_file.steps.len() + 1);
+            assert_eq!(hashes[0], calc_sha256(file));
+            assert_eq!(hashes.last(), Some(&merkle_tree.root_hash()));
+        }
+    }
+
+    #[test]
+    fn audit_path_folds_to_the_root() {
+        let files: Vec<Vec<u8>> = (0..6u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+
+        for (idx, file) in files.iter().enumerate() {
+            let path = merkle_tree.audit_path(idx).unwrap();
+            assert_eq!(path.len(), merkle_tree.proof_len(idx).unwrap());
+
+            let root = path
+                .iter()
+                .fold(calc_sha256(file), |curr, (hash, is_right)| {
+                    if *is_right {
+                        calc_sha256(format!("{}{}", curr, hash).as_bytes())
+                    } else {
+                        calc_sha256(format!("{}{}", hash, curr).as_bytes())
+                    }
+                });
+            assert_eq!(root, merkle_tree.root_hash());
+        }
+
+        // The first leaf's siblings are all on its right
+        assert!(merkle_tree
+            .audit_path(0)
+            .unwrap()
+            .iter()
+            .all(|(_, is_right)| *is_right));
+        assert!(MerkleTree::new(&files[..1])
+            .unwrap()
+            .audit_path(0)
+            .unwrap()
+            .is_empty());
+        assert!(merkle_tree.audit_path(6).is_err());
+    }
+
+    #[test]
+    fn malicious_indices_are_errors_not_panics() {
+        let node = |left_idx, right_idx| TreeNode {
+            hash: calc_sha256(b"sibling"),
+            left_idx,
+            right_idx,
+            left: None,
+            right: None,
+        };
+        let leaf_hash = calc_sha256(b"leaf");
+
+        // A sibling claiming to reach `usize::MAX` used to overflow the leaf count
+        let overflowing = node(1, usize::MAX);
+        assert!(ProofFile::from_nodes(leaf_hash.clone(), 0, &[&overflowing]).is_err());
+        assert!(fold_proof_nodes(&leaf_hash, 0, &[&overflowing]).is_err());
+
+        // A leaf at `usize::MAX` used to overflow the adjacency check
+        let first = node(0, 5);
+        assert!(ProofFile::from_nodes(leaf_hash.clone(), usize::MAX, &[&first]).is_err());
+
+        let mut verifier = ProofVerifier::new(&leaf_hash, 0, 4);
+        verifier.feed(&overflowing).unwrap();
+        assert!(verifier.feed(&node(2, 3)).is_err());
+
+        // A hand-edited tree whose root range is reversed used to underflow while looking up the leaf
+        #[cfg(feature = "serde")]
+        {
+            let leaf = serde_json::json!({"hash": leaf_hash, "left_idx": 0, "right_idx": 0, "left": null, "right": null});
+            let tree = serde_json::json!({
+                "root": {"hash": "root", "left_idx": 3, "right_idx": 1, "left": leaf, "right": leaf},
+            });
+            let tree: MerkleTree = serde_json::from_value(tree).unwrap();
+            let err = tree
+                .verify_merkle_proof("root", &leaf_hash, 0, &[] as &[TreeNode])
+                .unwrap_err();
+            assert_eq!(err.kind(), MerkleErrorKind::Internal);
+        }
+    }
+
+    #[test]
+    fn node_ranges_fix_the_leaf_count() {
+        let files: Vec<Vec<u8>> = (0..3u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+        let leaf_hash = calc_sha256(&files[2]);
+
+        // The last leaf of 3 has a single sibling on its left, just like the last leaf of 2. Told that the tree has 2
+        // leaves, `verify` accepts the proof for index 1.
+        let steps = merkle_tree.generate_proof_file(2).unwrap().steps;
+        assert!(verify(&root_hash, &leaf_hash, 1, 2, &steps).unwrap());
+
+        // The sibling ranges say where the leaf really is, so the forged position is rejected
+        let proofs = merkle_tree.generate_merkle_proof(2).unwrap();
+        assert!(verify_nodes(&root_hash, &leaf_hash, 2, &proofs).unwrap());
+        assert!(verify_nodes(&root_hash, &leaf_hash, 1, &proofs).is_err());
+
+        // Ranges that are adjacent but not the ones the midpoint split gives are rejected too
+        let mut forged = proofs[0].clone();
+        forged.right_idx = 0;
+        let mut split = proofs[0].clone();
+        split.left_idx = 1;
+        assert!(verify_nodes(&root_hash, &leaf_hash, 2, &[&split, &forged]).is_err());
+
+        for (idx, file) in files.iter().enumerate() {
+            let proofs = merkle_tree.generate_merkle_proof(idx).unwrap();
+            let leaf_hash = calc_sha256(file);
+            assert!(verify_nodes(&root_hash, &leaf_hash, idx, &proofs).unwrap());
+        }
+    }
+
+    #[test]
+    fn merkle_proof_bytes_round_trip() {
+        let files: Vec<Vec<u8>> = (0..7u8).map(|c| vec![c]).collect();
+
+        for hash_algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            let merkle_tree = MerkleTree::builder()
+                .algorithm(hash_algorithm)
+                .hash_mode(HashMode::Rfc6962)
+                .ordering(OrderingMode::SortedPair)
+                .build(&files)
+                .unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            for (idx, file) in files.iter().enumerate() {
+                let proof = merkle_tree.generate_proof(idx).unwrap();
+                let bytes = proof.to_bytes().unwrap();
+                assert_eq!(
+                    bytes.len(),
+                    24 + proof.steps.len() * (1 + hash_algorithm.digest_len())
+                );
+
+                let decoded = MerkleProof::from_bytes(&bytes).unwrap();
+                assert_eq!(decoded, proof);
+                assert!(decoded.verify(&root_hash, file).unwrap());
+            }
+        }
+
+        // Truncated, padded and unknown-version blobs are errors, not panics
+        let bytes = MerkleTree::new(&files)
+            .unwrap()
+            .generate_proof(3)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        for len in 0..bytes.len() {
+            assert!(MerkleProof::from_bytes(&bytes[..len]).is_err());
+        }
+        assert!(MerkleProof::from_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+
+        let mut future = bytes.clone();
+        future[0] = 2;
+        let err = MerkleProof::from_bytes(&future).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::UnsupportedFormat);
+
+        // A huge step count is rejected before anything is allocated
+        let mut huge = bytes;
+        huge[20..24].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(MerkleProof::from_bytes(&huge).is_err());
+
+        // The fields are public and a proof read from untrusted JSON may carry step hashes that are no digest at all
+        let mut proof = MerkleTree::new(&files).unwrap().generate_proof(3).unwrap();
+        for malformed in ["not hex", "abcd", ""] {
+            proof.steps[0].hash = malformed.to_string();
+            let err = proof.to_bytes().unwrap_err();
+            assert_eq!(err.kind(), MerkleErrorKind::ProofStructureInvalid);
+        }
+    }
+
+    #[test]
+    fn verify_any_accepts_every_listed_root() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let old_root = MerkleTree::new(&files[..4]).unwrap().root_hash();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+
+        let proof = merkle_tree.generate_proof_file(2).unwrap();
+        let leaf_hash = calc_sha256(&files[2]);
+
+        let roots = vec![old_root.clone(), root_hash.clone()];
+        assert!(verify_any(&roots, &leaf_hash, 2, 5, &proof.steps).unwrap());
+        assert!(!verify_any(&roots[..1], &leaf_hash, 2, 5, &proof.steps).unwrap());
+        assert!(!verify_any(&[], &leaf_hash, 2, 5, &proof.steps).unwrap());
+
+        // Structural problems are still errors, whatever the roots
+        assert!(verify_any(&roots, &leaf_hash, 5, 5, &proof.steps).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn verify_json_reads_proof_steps() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new(&files).unwrap();
+        let root_hash = merkle_tree.root_hash();
+        let leaf_hash = calc_sha256(&files[3]);
+
+        let steps = merkle_tree.generate_proof_file(3).unwrap().steps;
+        let proof_json = serde_json::to_string(&steps).unwrap();
+        assert!(proof_json.contains("\"side\":\"Left\""));
+
+        assert!(verify_json(&root_hash, &leaf_hash, 3, 5, &proof_json).unwrap());
+        assert!(!verify_json(&root_hash, &calc_sha256(b"x"), 3, 5, &proof_json).unwrap());
+
+        let err = verify_json(&root_hash, &leaf_hash, 3, 5, "not json").unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ProofStructureInvalid);
+    }
+}
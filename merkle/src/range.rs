@@ -0,0 +1,190 @@
+use crate::{
+    checked_idx, HashAlgorithm, HashMode, MerkleErrorKind, MerkleTree, MerkleTreeError,
+    OrderingMode, TreeNode,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// RangeProof proves the contiguous leaves `start..=end` at once. Instead of one path per leaf, it holds the hashes of
+// the largest subtrees lying entirely outside the range, from left to right. Every node above the range is rebuilt
+// from the range's own leaves and these hashes, so the proof grows with the height of the tree, not with the length
+// of the range.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RangeProof {
+    pub start: usize,
+    pub end: usize,
+    pub leaf_count: usize,
+    pub hashes: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_algorithm: HashAlgorithm,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_mode: HashMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordering: OrderingMode,
+}
+
+impl MerkleTree {
+    // Generates the proof of the leaves `start..=end` (both inclusive), to be checked with `verify_range_proof`
+    pub fn generate_range_proof(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Result<RangeProof, MerkleTreeError> {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => {
+                return Err(MerkleTreeError::new(
+                    MerkleErrorKind::EmptyInput,
+                    "merkle tree is empty",
+                ))
+            }
+        };
+
+        let leaf_count = checked_idx(root.right_idx.checked_add(1), MerkleErrorKind::Internal)?;
+        check_range(start, end, leaf_count)?;
+
+        let mut hashes = Vec::new();
+        collect_outside(root, start, end, &mut hashes);
+
+        Ok(RangeProof {
+            start,
+            end,
+            leaf_count,
+            hashes,
+            hash_algorithm: self.hash_algorithm,
+            hash_mode: self.hash_mode,
+            ordering: self.ordering,
+        })
+    }
+}
+
+// Verifies that `files` are the leaves `proof.start..=proof.end` of the tree with root `root_hash`. The files are
+// hashed and combined with the algorithm, mode and ordering recorded in the proof.
+pub fn verify_range_proof(
+    root_hash: &str,
+    files: &[Vec<u8>],
+    proof: &RangeProof,
+) -> Result<bool, MerkleTreeError> {
+    check_range(proof.start, proof.end, proof.leaf_count)?;
+    if files.len() != proof.end - proof.start + 1 {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "expected one file per leaf of the range",
+        ));
+    }
+
+    let mut leaves = files.iter().enumerate().map(|(i, file)| {
+        proof
+            .hash_algorithm
+            .hash_leaf(proof.hash_mode, proof.start + i, file)
+    });
+    let mut hashes = proof.hashes.iter();
+
+    let computed_root = fold_range(proof, 0, proof.leaf_count - 1, &mut leaves, &mut hashes);
+
+    // Every proof hash must have been used exactly once
+    match computed_root {
+        Some(computed_root) if hashes.next().is_none() => Ok(computed_root == root_hash),
+        _ => Err(MerkleTreeError::new(
+            MerkleErrorKind::ProofStructureInvalid,
+            "unexpected proof length",
+        )),
+    }
+}
+
+fn check_range(start: usize, end: usize, leaf_count: usize) -> Result<(), MerkleTreeError> {
+    if start > end || end >= leaf_count {
+        return Err(MerkleTreeError::new(
+            MerkleErrorKind::IndexOutOfBounds,
+            "invalid leaf range",
+        ));
+    }
+    Ok(())
+}
+
+// Walks the tree from the left and keeps the hash of every maximal subtree outside `start..=end`. Subtrees inside the
+// range are skipped, since the verifier rebuilds them from the leaves.
+fn collect_outside(node: &TreeNode, start: usize, end: usize, hashes: &mut Vec<String>) {
+    if node.right_idx < start || node.left_idx > end {
+        hashes.push(node.hash.clone());
+        return;
+    }
+    if start <= node.left_idx && node.right_idx <= end {
+        return;
+    }
+    for child in [node.left.as_deref(), node.right.as_deref()]
+        .into_iter()
+        .flatten()
+    {
+        collect_outside(child, start, end, hashes);
+    }
+}
+
+// Hash of the node over `[left, right]`, following the midpoint split. Nodes outside the range take the next proof
+// hash and leaves inside it the next leaf hash, both in left-to-right order. None if the proof runs out of hashes.
+fn fold_range(
+    proof: &RangeProof,
+    left: usize,
+    right: usize,
+    leaves: &mut impl Iterator<Item = String>,
+    hashes: &mut std::slice::Iter<'_, String>,
+) -> Option<String> {
+    if right < proof.start || left > proof.end {
+        return hashes.next().cloned();
+    }
+    if left == right {
+        return leaves.next();
+    }
+    let mid = left + (right - left) / 2;
+    let left_hash = fold_range(proof, left, mid, leaves, hashes)?;
+    let right_hash = fold_range(proof, mid + 1, right, leaves, hashes)?;
+    Some(
+        proof
+            .hash_algorithm
+            .hash_pair(proof.hash_mode, proof.ordering, &left_hash, &right_hash),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_proofs_verify_every_range() {
+        for n in 1..=9usize {
+            let files: Vec<Vec<u8>> = (0..n as u8).map(|c| vec![c]).collect();
+            let merkle_tree = MerkleTree::new(&files).unwrap();
+            let root_hash = merkle_tree.root_hash();
+
+            for start in 0..n {
+                for end in start..n {
+                    let proof = merkle_tree.generate_range_proof(start, end).unwrap();
+                    let range = &files[start..=end];
+                    assert!(verify_range_proof(&root_hash, range, &proof).unwrap());
+
+                    // Other contents for the same range fail
+                    let mut tampered = range.to_vec();
+                    tampered[0].push(0xff);
+                    assert!(!verify_range_proof(&root_hash, &tampered, &proof).unwrap());
+                }
+            }
+        }
+
+        let files: Vec<Vec<u8>> = (0..64u8).map(|c| vec![c]).collect();
+        let merkle_tree = MerkleTree::new_rfc6962(&files).unwrap();
+        let proof = merkle_tree.generate_range_proof(10, 41).unwrap();
+        assert!(verify_range_proof(&merkle_tree.root_hash(), &files[10..=41], &proof).unwrap());
+
+        // 32 consecutive leaves need far fewer hashes than 32 separate paths of 6 siblings
+        assert!(proof.hashes.len() <= 2 * 6);
+
+        assert!(verify_range_proof(&merkle_tree.root_hash(), &files[10..=40], &proof).is_err());
+        let mut truncated = proof.clone();
+        truncated.hashes.pop();
+        assert!(verify_range_proof(&merkle_tree.root_hash(), &files[10..=41], &truncated).is_err());
+
+        assert!(merkle_tree.generate_range_proof(5, 4).is_err());
+        assert!(merkle_tree.generate_range_proof(0, 64).is_err());
+    }
+}
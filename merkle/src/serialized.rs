@@ -0,0 +1,712 @@
+use crate::{
+    checked_idx, Encoding, HashAlgorithm, HashMode, MerkleErrorKind, MerkleProof, MerkleTree,
+    MerkleTreeError, OrderingMode, ProofStep, Side, TreeNode,
+};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+
+// Version of the serialized tree layout. Bump it whenever the node layout of `MerkleTree` or `TreeNode` changes, so
+// older readers refuse the new files instead of silently loading garbage.
+// - 1: `root`, `leaf_map` and `hash_algorithm`
+// - 2: adds `hash_mode`; an older reader would load an RFC 6962 tree as classic and compute wrong roots
+// - 3: adds `ordering`; an older reader would load a sorted-pair tree as positional
+pub const TREE_FORMAT_VERSION: u32 = 3;
+
+// Oldest version that is still read. JSON fills the fields added since with their `serde(default)`, bincode (which is
+// not self-describing) is decoded with the layout of the version it was written with.
+const OLDEST_TREE_FORMAT_VERSION: u32 = 1;
+
+// SerializedTree is the envelope written around a tree on disk. `format_version` is checked before the tree itself
+// is decoded, and `algorithm` must agree with the algorithm recorded in the tree.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedTree {
+    pub format_version: u32,
+    pub algorithm: String,
+    pub tree: MerkleTree,
+}
+
+// Borrowing twin of `SerializedTree`, so writing a tree does not need to clone it. The field names must stay in sync.
+#[derive(Serialize)]
+struct SerializedTreeRef<'a> {
+    format_version: u32,
+    algorithm: &'a str,
+    tree: &'a MerkleTree,
+}
+
+// Only the version is read in the first pass. Unknown fields are ignored by serde, so this parses any future layout.
+#[derive(Deserialize)]
+struct FormatHeader {
+    #[serde(default)]
+    format_version: Option<u32>,
+}
+
+impl MerkleTree {
+    // Streams the tree, wrapped in a `SerializedTree` envelope, straight into `writer` instead of building the whole
+    // string in memory first, which halves peak memory for very large trees. Generic over `W: Write` so it works with
+    // files, sockets or in-memory buffers. `serde_json::Error` converts into `io::Error` through the `?` operator.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, &self.envelope())?;
+        writer.flush()
+    }
+
+    // Compact binary encoding of the same envelope, roughly half the size of the JSON for large trees
+    pub fn to_bincode(&self) -> Vec<u8> {
+        bincode::serialize(&self.envelope()).expect("a Merkle tree always has a binary encoding")
+    }
+
+    // Loads a tree written by `to_bincode`. bincode is not self-describing, but `format_version` is the first field of
+    // the envelope, so it can be decoded and checked on its own before the rest.
+    pub fn from_bincode(bytes: &[u8]) -> Result<MerkleTree, MerkleTreeError> {
+        let format_version: u32 = bincode::deserialize(bytes).map_err(|e| {
+            MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!("not a serialized Merkle tree: {}", e),
+            )
+        })?;
+        check_format_version(Some(format_version), TREE_FORMAT_VERSION)?;
+
+        let serialized = match format_version {
+            1 => decode_bincode::<SerializedTreeV1>(bytes)?.into(),
+            2 => decode_bincode::<SerializedTreeV2>(bytes)?.into(),
+            _ => decode_bincode::<SerializedTree>(bytes)?,
+        };
+        serialized.into_tree()
+    }
+
+    fn envelope(&self) -> SerializedTreeRef<'_> {
+        SerializedTreeRef {
+            format_version: TREE_FORMAT_VERSION,
+            algorithm: self.hash_algorithm.name(),
+            tree: self,
+        }
+    }
+
+    // Loads a tree written by `write_json`. The version header is checked first, so a file from an unsupported format
+    // fails with a clear error rather than a cryptic decoding error deep inside the node layout.
+    pub fn from_json(json: &str) -> Result<MerkleTree, MerkleTreeError> {
+        let header: FormatHeader = serde_json::from_str(json).map_err(|e| {
+            MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!("not a serialized Merkle tree: {}", e),
+            )
+        })?;
+
+        check_format_version(header.format_version, TREE_FORMAT_VERSION)?;
+
+        let serialized: SerializedTree = serde_json::from_str(json).map_err(|e| {
+            MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!("corrupted serialized tree: {}", e),
+            )
+        })?;
+
+        serialized.into_tree()
+    }
+
+    // Reads the proof of `leaf_idx` straight from a tree written by `write_json`, without deserializing the tree. The
+    // JSON is still parsed from start to end, but only the nodes on the path from the root to the leaf and their
+    // siblings are kept; every other subtree is skipped with `IgnoredAny`, so memory stays proportional to the depth
+    // of the tree rather than its size. Returns the root hash recorded in the file together with the proof. As with
+    // `generate_proof`, `leaf_idx` is a leaf index (see `leaf_map` for deduplicated trees).
+    // `serde_json` reads byte by byte, so wrap a `File` in a `BufReader`.
+    pub fn read_proof_json<R: Read>(
+        reader: R,
+        leaf_idx: usize,
+    ) -> Result<(String, MerkleProof), MerkleTreeError> {
+        let corrupted = |e: serde_json::Error| {
+            MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!("corrupted serialized tree: {}", e),
+            )
+        };
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let envelope = deserializer
+            .deserialize_map(EnvelopeVisitor { leaf_idx })
+            .map_err(corrupted)?;
+        deserializer.end().map_err(corrupted)?;
+
+        check_format_version(envelope.format_version, TREE_FORMAT_VERSION)?;
+        let tree = envelope.tree.ok_or_else(|| {
+            MerkleTreeError::new(MerkleErrorKind::UnsupportedFormat, "missing tree")
+        })?;
+        if envelope.algorithm.as_deref() != Some(tree.hash_algorithm.name()) {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!(
+                    "envelope algorithm {} does not match the tree's {}",
+                    envelope.algorithm.unwrap_or_default(),
+                    tree.hash_algorithm
+                ),
+            ));
+        }
+
+        let root = tree
+            .root
+            .ok_or_else(|| MerkleTreeError::new(MerkleErrorKind::EmptyInput, "empty root"))?;
+        // The ranges come from the file, so the leaf count is only derived from a root that starts at 0, as in
+        // `validate`, and whose right edge does not overflow
+        if root.left_idx != 0 {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!(
+                    "root range [{}, {}] does not start at 0",
+                    root.left_idx, root.right_idx
+                ),
+            ));
+        }
+        let leaf_count = checked_idx(
+            root.right_idx.checked_add(1),
+            MerkleErrorKind::ShapeMismatch,
+        )?;
+        if leaf_idx < root.left_idx || leaf_idx > root.right_idx {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::IndexOutOfBounds,
+                "index out of bounds",
+            ));
+        }
+        if !root.reached {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::ShapeMismatch,
+                &format!("no leaf {} on the path from the root", leaf_idx),
+            ));
+        }
+
+        Ok((
+            root.hash,
+            MerkleProof {
+                leaf_idx,
+                leaf_count,
+                steps: root.steps,
+                hash_algorithm: tree.hash_algorithm,
+                hash_mode: tree.hash_mode,
+                ordering: tree.ordering,
+            },
+        ))
+    }
+}
+
+// What `read_proof_json` keeps of the envelope. A tree in an unsupported format is skipped rather than decoded, so
+// the version check afterwards reports the version instead of a decoding error.
+struct LazyEnvelope {
+    format_version: Option<u32>,
+    algorithm: Option<String>,
+    tree: Option<LazyTree>,
+}
+
+// What `read_proof_json` keeps of the tree: the settings, and the root reduced to a `PathNode`
+struct LazyTree {
+    root: Option<PathNode>,
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+    ordering: OrderingMode,
+}
+
+// A serialized node reduced to its range and hash, the proof steps from the leaf up to it (if the leaf is below it),
+// and whether the leaf itself was found below it
+struct PathNode {
+    hash: String,
+    left_idx: usize,
+    right_idx: usize,
+    steps: Vec<ProofStep>,
+    reached: bool,
+}
+
+struct EnvelopeVisitor {
+    leaf_idx: usize,
+}
+
+impl<'de> Visitor<'de> for EnvelopeVisitor {
+    type Value = LazyEnvelope;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a serialized tree envelope")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<LazyEnvelope, A::Error> {
+        let mut envelope = LazyEnvelope {
+            format_version: None,
+            algorithm: None,
+            tree: None,
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "format_version" => envelope.format_version = Some(map.next_value()?),
+                "algorithm" => envelope.algorithm = Some(map.next_value()?),
+                "tree"
+                    if check_format_version(envelope.format_version, TREE_FORMAT_VERSION)
+                        .is_ok() =>
+                {
+                    envelope.tree = Some(map.next_value_seed(TreeSeed {
+                        leaf_idx: self.leaf_idx,
+                    })?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(envelope)
+    }
+}
+
+struct TreeSeed {
+    leaf_idx: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed {
+    type Value = LazyTree;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<LazyTree, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed {
+    type Value = LazyTree;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a serialized Merkle tree")
+    }
+
+    // Missing settings take the same defaults as `serde(default)` on `MerkleTree`
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<LazyTree, A::Error> {
+        let mut tree = LazyTree {
+            root: None,
+            hash_algorithm: HashAlgorithm::default(),
+            hash_mode: HashMode::default(),
+            ordering: OrderingMode::default(),
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "root" => {
+                    tree.root = map.next_value_seed(NodeSeed {
+                        leaf_idx: self.leaf_idx,
+                    })?
+                }
+                "hash_algorithm" => tree.hash_algorithm = map.next_value()?,
+                "hash_mode" => tree.hash_mode = map.next_value()?,
+                "ordering" => tree.ordering = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(tree)
+    }
+}
+
+// Reads an `Option<Box<TreeNode>>`. A node whose range does not contain the leaf keeps only its own hash and skips
+// its children, since a proof needs the hash of a sibling but nothing below it.
+struct NodeSeed {
+    leaf_idx: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for NodeSeed {
+    type Value = Option<PathNode>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Option<PathNode>, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeSeed {
+    type Value = Option<PathNode>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a tree node or null")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<PathNode>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<PathNode>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Option<PathNode>, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Option<PathNode>, A::Error> {
+        let mut hash: Option<String> = None;
+        let mut left_idx: Option<usize> = None;
+        let mut right_idx: Option<usize> = None;
+        let mut left: Option<PathNode> = None;
+        let mut right: Option<PathNode> = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "hash" => hash = Some(map.next_value()?),
+                "left_idx" => left_idx = Some(map.next_value()?),
+                "right_idx" => right_idx = Some(map.next_value()?),
+                "left" | "right" => {
+                    // `write_json` writes the range before the children. Should it come later, the children are read
+                    // anyway and each of them decides for itself.
+                    let off_path = match (left_idx, right_idx) {
+                        (Some(l), Some(r)) => self.leaf_idx < l || self.leaf_idx > r,
+                        _ => false,
+                    };
+                    let child = if off_path {
+                        map.next_value::<IgnoredAny>()?;
+                        None
+                    } else {
+                        map.next_value_seed(NodeSeed {
+                            leaf_idx: self.leaf_idx,
+                        })?
+                    };
+                    if key == "left" {
+                        left = child;
+                    } else {
+                        right = child;
+                    }
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let mut node = PathNode {
+            hash: hash.ok_or_else(|| de::Error::missing_field("hash"))?,
+            left_idx: left_idx.ok_or_else(|| de::Error::missing_field("left_idx"))?,
+            right_idx: right_idx.ok_or_else(|| de::Error::missing_field("right_idx"))?,
+            steps: Vec::new(),
+            reached: false,
+        };
+
+        match (left, right) {
+            // The child holding the leaf brings the steps below it, the other child becomes the next step
+            (Some(left), Some(right)) => {
+                let (inner, sibling, side) = if self.leaf_idx <= left.right_idx {
+                    (left, right, Side::Right)
+                } else {
+                    (right, left, Side::Left)
+                };
+                node.reached = inner.reached;
+                node.steps = inner.steps;
+                node.steps.push(ProofStep {
+                    hash: sibling.hash,
+                    side,
+                });
+            }
+            (None, None) => {
+                node.reached = node.left_idx == self.leaf_idx && node.right_idx == self.leaf_idx
+            }
+            // Either a node off the path, whose children were skipped, or a node with a single child
+            _ => {}
+        }
+        Ok(Some(node))
+    }
+}
+
+impl SerializedTree {
+    // Unwraps the tree after checking that the envelope and the tree agree on the algorithm, and that the tree is not
+    // deeper than its leaf count allows
+    fn into_tree(self) -> Result<MerkleTree, MerkleTreeError> {
+        if self.algorithm != self.tree.hash_algorithm.name() {
+            return Err(MerkleTreeError::new(
+                MerkleErrorKind::UnsupportedFormat,
+                &format!(
+                    "envelope algorithm {} does not match the tree's {}",
+                    self.algorithm, self.tree.hash_algorithm
+                ),
+            ));
+        }
+        self.tree.check_depth()?;
+        Ok(self.tree)
+    }
+}
+
+// Layout of version 1 in bincode, before `hash_mode` was added. Such trees were always hashed in the classic mode.
+#[derive(Deserialize)]
+struct SerializedTreeV1 {
+    format_version: u32,
+    algorithm: String,
+    tree: MerkleTreeV1,
+}
+
+#[derive(Deserialize)]
+struct MerkleTreeV1 {
+    root: Option<Box<TreeNode>>,
+    leaf_map: Option<Vec<usize>>,
+    hash_algorithm: HashAlgorithm,
+}
+
+impl From<SerializedTreeV1> for SerializedTree {
+    fn from(v1: SerializedTreeV1) -> SerializedTree {
+        SerializedTree {
+            format_version: v1.format_version,
+            algorithm: v1.algorithm,
+            tree: MerkleTree {
+                root: v1.tree.root,
+                leaf_map: v1.tree.leaf_map,
+                hash_algorithm: v1.tree.hash_algorithm,
+                hash_mode: HashMode::Classic,
+                ordering: OrderingMode::Positional,
+                encoding: Encoding::Hex,
+                hash_cache: OnceLock::new(),
+            },
+        }
+    }
+}
+
+// Layout of version 2 in bincode, before `ordering` was added. Such trees always concatenated in positional order.
+#[derive(Deserialize)]
+struct SerializedTreeV2 {
+    format_version: u32,
+    algorithm: String,
+    tree: MerkleTreeV2,
+}
+
+#[derive(Deserialize)]
+struct MerkleTreeV2 {
+    root: Option<Box<TreeNode>>,
+    leaf_map: Option<Vec<usize>>,
+    hash_algorithm: HashAlgorithm,
+    hash_mode: HashMode,
+}
+
+impl From<SerializedTreeV2> for SerializedTree {
+    fn from(v2: SerializedTreeV2) -> SerializedTree {
+        SerializedTree {
+            format_version: v2.format_version,
+            algorithm: v2.algorithm,
+            tree: MerkleTree {
+                root: v2.tree.root,
+                leaf_map: v2.tree.leaf_map,
+                hash_algorithm: v2.tree.hash_algorithm,
+                hash_mode: v2.tree.hash_mode,
+                ordering: OrderingMode::Positional,
+                encoding: Encoding::Hex,
+                hash_cache: OnceLock::new(),
+            },
+        }
+    }
+}
+
+fn decode_bincode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MerkleTreeError> {
+    bincode::deserialize(bytes).map_err(|e| {
+        MerkleTreeError::new(
+            MerkleErrorKind::UnsupportedFormat,
+            &format!("corrupted serialized tree: {}", e),
+        )
+    })
+}
+
+// Accepts the versions from `OLDEST_TREE_FORMAT_VERSION` up to `newest`, the version the reader was built for
+fn check_format_version(format_version: Option<u32>, newest: u32) -> Result<(), MerkleTreeError> {
+    match format_version {
+        Some(version) if (OLDEST_TREE_FORMAT_VERSION..=newest).contains(&version) => Ok(()),
+        Some(version) => Err(MerkleTreeError::new(
+            MerkleErrorKind::UnsupportedFormat,
+            &format!(
+                "unsupported tree format version {} (expected {} to {})",
+                version, OLDEST_TREE_FORMAT_VERSION, newest
+            ),
+        )),
+        None => Err(MerkleTreeError::new(
+            MerkleErrorKind::UnsupportedFormat,
+            "missing tree format version; the file was not written by `write_json`",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+
+    fn write(tree: &MerkleTree) -> String {
+        let mut buffer = Vec::new();
+        tree.write_json(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn envelope_is_version_checked() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::new_with_algorithm(&files, HashAlgorithm::Blake3).unwrap();
+        let json = write(&tree);
+
+        assert_eq!(MerkleTree::from_json(&json).unwrap(), tree);
+
+        // A file from a future format is refused by its header
+        let future = json.replace(
+            &format!("\"format_version\":{}", TREE_FORMAT_VERSION),
+            "\"format_version\":99",
+        );
+        let err = MerkleTree::from_json(&future).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::UnsupportedFormat);
+        assert!(err
+            .to_string()
+            .contains("unsupported tree format version 99"));
+
+        // A bare tree without the envelope is refused too
+        let bare = serde_json::to_string(&tree).unwrap();
+        let err = MerkleTree::from_json(&bare).unwrap_err();
+        assert!(err.to_string().contains("missing tree format version"));
+
+        // The envelope and the tree must agree on the algorithm
+        let mismatched = json.replace("\"algorithm\":\"blake3\"", "\"algorithm\":\"sha256\"");
+        assert!(MerkleTree::from_json(&mismatched).is_err());
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        let files: Vec<Vec<u8>> = (0..1024u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let tree = MerkleTree::new(&files).unwrap();
+
+        let bytes = tree.to_bincode();
+        assert_eq!(MerkleTree::from_bincode(&bytes).unwrap(), tree);
+        assert!(bytes.len() < write(&tree).len());
+
+        // The version is the first field, little-endian by default
+        let mut future = bytes.clone();
+        future[..4].copy_from_slice(&99u32.to_le_bytes());
+        let err = MerkleTree::from_bincode(&future).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unsupported tree format version 99"));
+
+        assert!(MerkleTree::from_bincode(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn proofs_are_read_without_loading_the_tree() {
+        let files: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let tree = MerkleTree::builder()
+            .algorithm(HashAlgorithm::Sha512)
+            .build(&files)
+            .unwrap();
+        let json = write(&tree);
+
+        for leaf_idx in [0, 1, 499, 500, 998, 999] {
+            let (root_hash, proof) =
+                MerkleTree::read_proof_json(json.as_bytes(), leaf_idx).unwrap();
+            assert_eq!(root_hash, tree.root_hash());
+            assert_eq!(proof, tree.generate_proof(leaf_idx).unwrap());
+            assert!(proof.verify(&root_hash, &files[leaf_idx]).unwrap());
+        }
+
+        // A single leaf has an empty path
+        let single = write(&MerkleTree::new(&files[..1]).unwrap());
+        let (_, proof) = MerkleTree::read_proof_json(single.as_bytes(), 0).unwrap();
+        assert!(proof.steps.is_empty());
+
+        let err = MerkleTree::read_proof_json(json.as_bytes(), 1000).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::IndexOutOfBounds);
+
+        let future = json.replace(
+            &format!("\"format_version\":{}", TREE_FORMAT_VERSION),
+            "\"format_version\":99",
+        );
+        let err = MerkleTree::read_proof_json(future.as_bytes(), 0).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unsupported tree format version 99"));
+
+        assert!(MerkleTree::read_proof_json(&json.as_bytes()[..json.len() / 2], 0).is_err());
+
+        // Root ranges from the file are not trusted: an offset root or one ending at `usize::MAX` is an error
+        let offset = single.replace("\"left_idx\":0", "\"left_idx\":1");
+        let offset = offset.replace("\"right_idx\":0", "\"right_idx\":1");
+        let err = MerkleTree::read_proof_json(offset.as_bytes(), 1).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+
+        let overflowing =
+            single.replace("\"right_idx\":0", &format!("\"right_idx\":{}", usize::MAX));
+        let err = MerkleTree::read_proof_json(overflowing.as_bytes(), 0).unwrap_err();
+        assert_eq!(err.kind(), MerkleErrorKind::ShapeMismatch);
+        assert!(err.to_string().contains("overflow"));
+    }
+
+    #[test]
+    fn hash_mode_bumps_the_format_version() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::new_rfc6962(&files).unwrap();
+
+        // A reader that only knows version 1 refuses the tree instead of loading it as classic
+        let json = write(&tree);
+        assert!(json.starts_with(&format!("{{\"format_version\":{},", TREE_FORMAT_VERSION)));
+        assert!(check_format_version(Some(TREE_FORMAT_VERSION), 1).is_err());
+        let bytes = tree.to_bincode();
+        assert_eq!(bytes[..4], TREE_FORMAT_VERSION.to_le_bytes());
+        assert_eq!(MerkleTree::from_bincode(&bytes).unwrap(), tree);
+
+        // Version 1 files load as classic trees, in JSON and in bincode
+        let classic = MerkleTree::new(&files).unwrap();
+        let v1_json = write(&classic)
+            .replace(
+                &format!("\"format_version\":{}", TREE_FORMAT_VERSION),
+                "\"format_version\":1",
+            )
+            .replace(",\"hash_mode\":\"classic\"", "");
+        assert!(!v1_json.contains("hash_mode"));
+        let loaded = MerkleTree::from_json(&v1_json).unwrap();
+        assert_eq!(loaded.hash_mode, HashMode::Classic);
+        assert_eq!(loaded.root_hash(), classic.root_hash());
+
+        let v1_bytes = bincode::serialize(&(
+            1u32,
+            "sha256",
+            (&classic.root, &classic.leaf_map, HashAlgorithm::Sha256),
+        ))
+        .unwrap();
+        let loaded = MerkleTree::from_bincode(&v1_bytes).unwrap();
+        assert_eq!(loaded.hash_mode, HashMode::Classic);
+        assert_eq!(loaded.root_hash(), classic.root_hash());
+    }
+
+    #[test]
+    fn ordering_bumps_the_format_version() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let sorted = MerkleTree::builder()
+            .ordering(OrderingMode::SortedPair)
+            .build(&files)
+            .unwrap();
+
+        // Readers of version 2 did not know `ordering` and refuse the tree instead of loading it as positional
+        assert_eq!(TREE_FORMAT_VERSION, 3);
+        assert!(write(&sorted).starts_with("{\"format_version\":3,"));
+        assert!(check_format_version(Some(TREE_FORMAT_VERSION), 2).is_err());
+        assert_eq!(
+            MerkleTree::from_bincode(&sorted.to_bincode()).unwrap(),
+            sorted
+        );
+
+        // Version 2 files load with positional ordering, in JSON and in bincode
+        let rfc6962 = MerkleTree::new_rfc6962(&files).unwrap();
+        let v2_json = write(&rfc6962)
+            .replace("\"format_version\":3", "\"format_version\":2")
+            .replace(",\"ordering\":\"positional\"", "");
+        assert!(!v2_json.contains("ordering"));
+        assert_eq!(MerkleTree::from_json(&v2_json).unwrap(), rfc6962);
+
+        let v2_bytes = bincode::serialize(&(
+            2u32,
+            "sha256",
+            (
+                &rfc6962.root,
+                &rfc6962.leaf_map,
+                HashAlgorithm::Sha256,
+                HashMode::Rfc6962,
+            ),
+        ))
+        .unwrap();
+        let loaded = MerkleTree::from_bincode(&v2_bytes).unwrap();
+        assert_eq!(loaded.ordering, OrderingMode::Positional);
+        assert_eq!(loaded, rfc6962);
+    }
+}
@@ -0,0 +1,17 @@
+use crate::verify_json;
+use wasm_bindgen::prelude::*;
+
+// Browser entry point for light clients, which check a proof against a root they trust without the tree or a server.
+// It only wraps `verify_json`, which does no I/O. JavaScript has no `Result`, so a malformed proof or a structural
+// error counts as not verified, like `Attestation::verify` does. Build with `wasm-pack build merkle`
+// (or `cargo build -p merkle --target wasm32-unknown-unknown`).
+#[wasm_bindgen]
+pub fn verify_proof_js(
+    root_hash: &str,
+    leaf_hash: &str,
+    leaf_idx: usize,
+    leaf_count: usize,
+    proof_json: &str,
+) -> bool {
+    verify_json(root_hash, leaf_hash, leaf_idx, leaf_count, proof_json).unwrap_or(false)
+}
@@ -47,16 +47,16 @@ $CLI_PATH -d -i 3 -o "$DOWNLOAD_DIR/file3.txt"
 mkdir -p "$PROOF_DIR"
 
 echo -e "${YELLOW}Client requesting merkle proofs for file0 from the grpc-server${NC}"
-$CLI_PATH -M -i 0 -o "$PROOF_DIR/file0.json"  # Pass the directory, CLI will append file name
+$CLI_PATH -M -i 0 -O "$OUTPUT_DIR_MERKLE_ROOT" -o "$PROOF_DIR/file0.json"  # Pass the directory, CLI will append file name
 
 echo -e "${YELLOW}Client requesting merkle proofs for file1 from the grpc-server${NC}"
-$CLI_PATH -M -i 1 -o "$PROOF_DIR/file1.json"  # Pass the directory, CLI will append file name
+$CLI_PATH -M -i 1 -O "$OUTPUT_DIR_MERKLE_ROOT" -o "$PROOF_DIR/file1.json"  # Pass the directory, CLI will append file name
 
 echo -e "${YELLOW}Client requesting merkle proofs for file2 from the grpc-server${NC}"
-$CLI_PATH -M -i 2 -o "$PROOF_DIR/file2.json"  # Pass the directory, CLI will append file name
+$CLI_PATH -M -i 2 -O "$OUTPUT_DIR_MERKLE_ROOT" -o "$PROOF_DIR/file2.json"  # Pass the directory, CLI will append file name
 
 echo -e "${YELLOW}Client requesting merkle proofs for file3 from the grpc-server${NC}"
-$CLI_PATH -M -i 3 -o "$PROOF_DIR/file3.json"  # Pass the directory, CLI will append file name
+$CLI_PATH -M -i 3 -O "$OUTPUT_DIR_MERKLE_ROOT" -o "$PROOF_DIR/file3.json"  # Pass the directory, CLI will append file name
 
 # Step 6: Client independently verifies the integrity of the file without involving the server
 
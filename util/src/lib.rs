@@ -1,7 +1,16 @@
-use sha2::{Digest, Sha256};
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+#[cfg(feature = "fs")]
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest, Sha256, Sha512};
+use std::cmp::Ordering;
+#[cfg(feature = "fs")]
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "fs")]
+use std::io::Write;
+use std::io::{self, Read};
+#[cfg(feature = "fs")]
 use std::path::Path;
+#[cfg(feature = "fs")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn calc_sha256(data: &[u8]) -> String {
     // A new instance of a hasher is initialized. `update` method feeds the input data into the hasher for processing.
@@ -12,9 +21,100 @@ pub fn calc_sha256(data: &[u8]) -> String {
     format!("{:x}", result)
 }
 
+// Same as `calc_sha256`, but reads the data from `reader` in fixed-size chunks instead of requiring it all in memory,
+// so hashing a large file only ever holds one buffer of it
+pub fn calc_sha256_reader<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        // `read` returns 0 at the end of the input. `Interrupted` errors are retried as the `Read` docs advise.
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Same as `calc_sha256` but with SHA-512, which yields a 128 character hex digest
+pub fn calc_sha512(data: &[u8]) -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+// BLAKE3 hash of `data` as a 64 character hex string. BLAKE3 is considerably faster than SHA-2 on large inputs.
+pub fn calc_blake3(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+// Lowercase hex encoding of `bytes`, the same format as the digests returned by the functions above
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Decodes a hex string such as a digest back into its bytes. Returns `None` for an odd length or a non-hex character.
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 // Returns a Result containing a vector of byte vectors where the outer vector represents multiple files,
 // while each inner vector contains the bytes of a single file.
+#[cfg(feature = "fs")]
 pub fn read_files_from_dir(dir: &str) -> io::Result<Vec<Vec<u8>>> {
+    // Drop the file names and keep only the contents, preserving the sorted order
+    let file_contents = read_files_with_names(dir)?
+        .into_iter()
+        .map(|(_, content)| content)
+        .collect();
+
+    Ok(file_contents)
+}
+
+// Order in which the files of a directory become leaves
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SortMode {
+    // Byte order of the file names, so `file10.txt` comes before `file2.txt`. The order `read_files_from_dir` has
+    // always used, so existing roots do not change.
+    #[default]
+    Lexicographic,
+    // Runs of digits are compared as numbers, so `file2.txt` comes before `file10.txt` (see `natural_cmp`)
+    Natural,
+}
+
+// Same as `read_files_from_dir`, with the order of the files chosen by `sort_mode`
+#[cfg(feature = "fs")]
+pub fn read_files_from_dir_sorted(dir: &str, sort_mode: SortMode) -> io::Result<Vec<Vec<u8>>> {
+    let file_contents = read_files_with_names_sorted(dir, sort_mode)?
+        .into_iter()
+        .map(|(_, content)| content)
+        .collect();
+
+    Ok(file_contents)
+}
+
+// Same as `read_files_from_dir` but keeps each file name next to its content as a `(name, bytes)` pair,
+// so callers can later map a file index back to the original file name.
+#[cfg(feature = "fs")]
+pub fn read_files_with_names(dir: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+    read_files_with_names_sorted(dir, SortMode::Lexicographic)
+}
+
+// Same as `read_files_with_names`, with the order of the files chosen by `sort_mode`
+#[cfg(feature = "fs")]
+pub fn read_files_with_names_sorted(
+    dir: &str,
+    sort_mode: SortMode,
+) -> io::Result<Vec<(String, Vec<u8>)>> {
     let mut file_contents = Vec::new();
 
     // Collect entries and sort by file name
@@ -23,7 +123,15 @@ pub fn read_files_from_dir(dir: &str) -> io::Result<Vec<Vec<u8>>> {
         .filter(|e| e.path().is_file()) // Only process files
         .collect(); // collect the filtered entries into the vector
 
-    entries.sort_by_key(|entry| entry.file_name()); // Sort by file name
+    match sort_mode {
+        SortMode::Lexicographic => entries.sort_by_key(|entry| entry.file_name()), // Sort by file name
+        SortMode::Natural => entries.sort_by(|a, b| {
+            natural_cmp(
+                &a.file_name().to_string_lossy(),
+                &b.file_name().to_string_lossy(),
+            )
+        }),
+    }
 
     for entry in entries {
         // Obtain the path for each file entry
@@ -33,13 +141,107 @@ pub fn read_files_from_dir(dir: &str) -> io::Result<Vec<Vec<u8>>> {
         let mut content = Vec::new();
         file.read_to_end(&mut content)?; // entire file content is read into the `content` vector
 
-        // Each file content is pushed into the `file_contents` vector
-        file_contents.push(content);
+        // `to_string_lossy` replaces any non UTF-8 sequences so that every file name can be represented as a String
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        // Each (name, content) pair is pushed into the `file_contents` vector
+        file_contents.push((name, content));
     }
 
     Ok(file_contents)
 }
 
+// Compares two names the way a person would: runs of ASCII digits are compared by their numeric value, everything else
+// character by character. `file2` < `file10` < `file10a` < `file11`. Numbers are compared as digit strings without
+// their leading zeros, so arbitrarily long runs never overflow. Names that only differ in leading zeros (`file01` and
+// `file1`) fall back to plain string order, so the order is total and sorting stays deterministic.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a, b);
+
+    loop {
+        match (a_rest.chars().next(), b_rest.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (a_digits, a_tail) = split_digits(a_rest);
+                let (b_digits, b_tail) = split_digits(b_rest);
+                let (a_num, b_num) = (
+                    a_digits.trim_start_matches('0'),
+                    b_digits.trim_start_matches('0'),
+                );
+
+                // Without leading zeros, a longer run of digits is a larger number
+                let ordering = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                (a_rest, b_rest) = (a_tail, b_tail);
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                (a_rest, b_rest) = (&a_rest[x.len_utf8()..], &b_rest[y.len_utf8()..]);
+            }
+        }
+    }
+}
+
+// Splits `s` into its leading run of ASCII digits and the rest
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+// Same as `read_files_from_dir`, but files ending in `.gz` are gunzipped, so the tree commits to their decompressed
+// contents. Files are ordered by their name without the `.gz` suffix, so `a.txt.gz` takes the place `a.txt` would have:
+// compressing some files of a directory changes neither their order nor the Merkle root.
+#[cfg(feature = "fs")]
+pub fn read_files_from_dir_gz(dir: &str) -> io::Result<Vec<Vec<u8>>> {
+    let mut files = read_files_with_names(dir)?
+        .into_iter()
+        .map(|(name, content)| match name.strip_suffix(".gz") {
+            Some(stem) => {
+                // `MultiGzDecoder` also reads files made of several concatenated gzip members, like `cat a.gz b.gz`
+                let mut decompressed = Vec::new();
+                MultiGzDecoder::new(content.as_slice()).read_to_end(&mut decompressed)?;
+                Ok((stem.to_string(), decompressed))
+            }
+            None => Ok((name, content)),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(files.into_iter().map(|(_, content)| content).collect())
+}
+
+// Reads the regular files of the tar archive at `path` without extracting it, sorted by their path inside the archive
+// so that the same archive always yields the same order (and therefore the same Merkle root), however it was packed.
+// Directory entries, links and other special entries carry no content and are skipped.
+#[cfg(feature = "fs")]
+pub fn read_files_from_tar(path: &str) -> io::Result<Vec<Vec<u8>>> {
+    let mut archive = tar::Archive::new(File::open(path)?);
+    let mut files = Vec::new();
+
+    // Entries can only be read in archive order, so each content is read before moving on and sorted afterwards
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = entry.path()?.into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.push((name, content));
+    }
+
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(files.into_iter().map(|(_, content)| content).collect())
+}
+
+#[cfg(feature = "fs")]
 pub fn write_file(directory: &str, file_name: &str, content: &str) -> io::Result<()> {
     // Create the directory if it doesn't exist
     fs::create_dir_all(directory)?;
@@ -56,6 +258,52 @@ pub fn write_file(directory: &str, file_name: &str, content: &str) -> io::Result
     Ok(())
 }
 
+// Appends a `<timestamp> <root_hash> <leaf_count>` line to an append-only root log (a local transparency log), where the
+// timestamp is in seconds since the Unix epoch. The file is opened in append mode and the whole line is written with a
+// single `write_all`, so concurrent appenders never interleave partial lines.
+#[cfg(feature = "fs")]
+pub fn append_root_log(path: &str, root_hash: &str, leaf_count: usize) -> io::Result<()> {
+    // A root containing whitespace would break the line format
+    if root_hash.is_empty() || root_hash.contains(char::is_whitespace) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "root hash must be non-empty and must not contain whitespace",
+        ));
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(io::Error::other)?
+        .as_secs();
+    let line = format!("{} {} {}\n", timestamp, root_hash, leaf_count);
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()
+}
+
+// Looks `root_hash` up in a root log written by `append_root_log` and returns the `(timestamp, leaf_count)` of the first
+// record that committed to it, or None if the root was never recorded. Malformed lines (e.g. a torn write) are skipped.
+#[cfg(feature = "fs")]
+pub fn verify_root_in_log(path: &str, root_hash: &str) -> io::Result<Option<(u64, usize)>> {
+    let log = fs::read_to_string(path)?;
+
+    for line in log.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let [timestamp, root, leaf_count] = fields.as_slice() {
+            if *root != root_hash {
+                continue;
+            }
+            // `parse` fails on a malformed record, in which case the line is ignored
+            if let (Ok(timestamp), Ok(leaf_count)) = (timestamp.parse(), leaf_count.parse()) {
+                return Ok(Some((timestamp, leaf_count)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 // Since the `min` and `max` function takes in a value type (`usize`) we don't require lifetime annotations here
 // because they own their data and  Rust creates a copy of that value in the function’s stack frame. If we were
 // to modify the fucntions to take references, then we would need to specify lifetimes. Lifetimes are primarily
@@ -75,3 +323,173 @@ pub fn max(a: usize, b: usize) -> usize {
         b
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fs")]
+    use std::env;
+
+    #[test]
+    fn reader_hash_matches_in_memory_hash() {
+        // Larger than the read buffer, so the hasher is fed several chunks
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            calc_sha256_reader(data.as_slice()).unwrap(),
+            calc_sha256(&data)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn root_log_append_and_verify() {
+        let path = env::temp_dir().join(format!("rustle-tree-root-log-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        append_root_log(path, &calc_sha256(b"first"), 4).unwrap();
+        append_root_log(path, &calc_sha256(b"second"), 7).unwrap();
+
+        let (timestamp, leaf_count) = verify_root_in_log(path, &calc_sha256(b"first"))
+            .unwrap()
+            .unwrap();
+        assert!(timestamp >= before);
+        assert_eq!(leaf_count, 4);
+
+        let (_, leaf_count) = verify_root_in_log(path, &calc_sha256(b"second"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(leaf_count, 7);
+
+        // Every record is one parseable line
+        assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 2);
+        assert_eq!(
+            verify_root_in_log(path, &calc_sha256(b"unknown")).unwrap(),
+            None
+        );
+        assert!(append_root_log(path, "not a hash", 1).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let digest = calc_sha256(b"hello");
+        let bytes = hex_to_bytes(&digest).unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes_to_hex(&bytes), digest);
+
+        assert_eq!(hex_to_bytes("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(hex_to_bytes("abc"), None);
+        assert_eq!(hex_to_bytes("zz"), None);
+        assert_eq!(hex_to_bytes("é0"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn tar_files_are_read_in_path_order() {
+        let path = env::temp_dir().join(format!("rustle-tree-{}.tar", std::process::id()));
+
+        // Packed out of order, with a directory entry in between
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, content) in [("b.txt", &b"B"[..]), ("a.txt", b"A"), ("dir/c.txt", b"C")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content).unwrap();
+            if name == "b.txt" {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, "dir/", io::empty())
+                    .unwrap();
+            }
+        }
+        builder.into_inner().unwrap();
+
+        let files = read_files_from_tar(path.to_str().unwrap()).unwrap();
+        assert_eq!(files, vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec()]);
+
+        fs::remove_file(&path).unwrap();
+        assert!(read_files_from_tar(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn gzipped_files_hash_like_their_contents() {
+        let dir = |name: &str| {
+            env::temp_dir().join(format!("rustle-tree-{}-{}", name, std::process::id()))
+        };
+        let (plain, mixed) = (dir("plain"), dir("gz"));
+        fs::create_dir_all(&plain).unwrap();
+        fs::create_dir_all(&mixed).unwrap();
+
+        for (name, content) in [("a.txt", "alpha"), ("b.txt", "beta"), ("c.txt", "gamma")] {
+            fs::write(plain.join(name), content).unwrap();
+        }
+
+        // `b.txt` is stored compressed; `b.txt.gz` must still come between `a.txt` and `c.txt`
+        fs::write(mixed.join("a.txt"), "alpha").unwrap();
+        fs::write(mixed.join("c.txt"), "gamma").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(b"beta").unwrap();
+        fs::write(mixed.join("b.txt.gz"), encoder.finish().unwrap()).unwrap();
+
+        let expected = read_files_from_dir(plain.to_str().unwrap()).unwrap();
+        assert_eq!(
+            read_files_from_dir_gz(mixed.to_str().unwrap()).unwrap(),
+            expected
+        );
+        assert_ne!(
+            read_files_from_dir(mixed.to_str().unwrap()).unwrap(),
+            expected
+        );
+
+        // Corrupt gzip data is an error rather than silently hashed as is
+        fs::write(mixed.join("d.gz"), b"not gzip").unwrap();
+        assert!(read_files_from_dir_gz(mixed.to_str().unwrap()).is_err());
+
+        fs::remove_dir_all(plain).unwrap();
+        fs::remove_dir_all(mixed).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn natural_sort_orders_numbers_by_value() {
+        let dir = env::temp_dir().join(format!("rustle-tree-natural-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["file10.txt", "file2.txt", "file1.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let dir_str = dir.to_str().unwrap();
+
+        let natural = read_files_from_dir_sorted(dir_str, SortMode::Natural).unwrap();
+        assert_eq!(
+            natural,
+            [&b"file1.txt"[..], b"file2.txt", b"file10.txt"].map(|name| name.to_vec())
+        );
+
+        // The default stays lexicographic
+        let lexicographic = read_files_from_dir(dir_str).unwrap();
+        assert_eq!(lexicographic[1], b"file10.txt");
+        assert_eq!(
+            read_files_from_dir_sorted(dir_str, SortMode::default()).unwrap(),
+            lexicographic
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(natural_cmp("file10a", "file10b"), Ordering::Less);
+        assert_eq!(natural_cmp("file10a", "file11"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("a99999999999999999999999", "a100000000000000000000000"),
+            Ordering::Less
+        );
+        assert_ne!(natural_cmp("file01", "file1"), Ordering::Equal);
+    }
+}